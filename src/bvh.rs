@@ -0,0 +1,445 @@
+use crate::math::*;
+use crate::sampler::*;
+use crate::types::*;
+
+/// number of buckets centroids are binned into when evaluating splits
+const SAH_BUCKETS: usize = 12;
+/// cost of visiting a node relative to intersecting a primitive
+const TRAVERSAL_COST: Num = 0.125;
+/// primitives a leaf may hold when splitting isn't worth it
+const MAX_LEAF_SIZE: usize = 4;
+
+enum BvhNode {
+    Leaf {
+        bounds: Aabb,
+        start: usize,
+        count: usize,
+    },
+    Interior {
+        bounds: Aabb,
+        axis: usize,
+        left: usize,
+        right: usize,
+    },
+}
+
+impl BvhNode {
+    fn bounds(&self) -> &Aabb {
+        match self {
+            BvhNode::Leaf { bounds, .. } | BvhNode::Interior { bounds, .. } => bounds,
+        }
+    }
+}
+
+/// A bounding volume hierarchy over primitives identified by index.
+///
+/// The tree only knows the primitives' boxes; intersecting the primitives
+/// themselves is left to the caller, which lets meshes index their faces
+/// without wrapping each one in a `HittablePtr`.
+pub struct BvhTree {
+    nodes: Vec<BvhNode>,
+    /// primitive indices, ordered so every leaf refers to a contiguous range
+    indices: Vec<usize>,
+}
+
+impl BvhTree {
+    /// Builds the tree with the surface area heuristic.
+    pub fn new(bounds: &[Aabb]) -> BvhTree {
+        let mut tree = BvhTree {
+            nodes: Vec::with_capacity(2 * bounds.len()),
+            indices: (0..bounds.len()).collect(),
+        };
+        if !bounds.is_empty() {
+            let centroids: Vec<Point> = bounds.iter().map(Aabb::centroid).collect();
+            tree.build(bounds, &centroids, 0, bounds.len());
+        }
+        tree
+    }
+
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        self.nodes.first().map(|root| *root.bounds())
+    }
+
+    /// Returns the closest hit reported by `hit_primitive`, which is called
+    /// with a primitive's index and the current `[t_min, t_max]` interval.
+    pub fn hit<F>(
+        &self,
+        ray: &Ray,
+        t_min: Num,
+        t_max: Num,
+        mut hit_primitive: F,
+    ) -> Option<HitRecord>
+    where
+        F: FnMut(usize, Num, Num) -> Option<HitRecord>,
+    {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let mut record = None;
+        let mut closest_so_far = t_max;
+        // manual stack, children are pushed far-first so the near one is popped next
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            if !node.bounds().hit(ray, t_min, closest_so_far) {
+                continue;
+            }
+            match *node {
+                BvhNode::Leaf { start, count, .. } => {
+                    for &index in &self.indices[start..(start + count)] {
+                        if let Some(r) = hit_primitive(index, t_min, closest_so_far) {
+                            closest_so_far = r.t;
+                            record = Some(r);
+                        }
+                    }
+                }
+                BvhNode::Interior {
+                    axis, left, right, ..
+                } => {
+                    if ray.direction[axis] < 0.0 {
+                        stack.push(left);
+                        stack.push(right);
+                    } else {
+                        stack.push(right);
+                        stack.push(left);
+                    }
+                }
+            }
+        }
+        record
+    }
+
+    /// Recursively builds the subtree over `indices[start..end]`, returning
+    /// the index of its root node.
+    fn build(&mut self, bounds: &[Aabb], centroids: &[Point], start: usize, end: usize) -> usize {
+        let node_index = self.nodes.len();
+        let count = end - start;
+        let node_bounds = self.indices[start..end]
+            .iter()
+            .map(|&i| bounds[i])
+            .fold(bounds[self.indices[start]], Aabb::union);
+        let leaf = BvhNode::Leaf {
+            bounds: node_bounds,
+            start,
+            count,
+        };
+        if count == 1 {
+            self.nodes.push(leaf);
+            return node_index;
+        }
+
+        let centroid_bounds = self.indices[start..end]
+            .iter()
+            .map(|&i| Aabb::new(centroids[i], centroids[i]))
+            .fold(
+                Aabb::new(
+                    centroids[self.indices[start]],
+                    centroids[self.indices[start]],
+                ),
+                Aabb::union,
+            );
+        let extent = centroid_bounds.max - centroid_bounds.min;
+        let axis = if extent.x > extent.y && extent.x > extent.z {
+            0
+        } else if extent.y > extent.z {
+            1
+        } else {
+            2
+        };
+        if extent[axis] <= 0.0 {
+            // every centroid coincides, nothing to split on
+            if count <= MAX_LEAF_SIZE {
+                self.nodes.push(leaf);
+                return node_index;
+            }
+            return self.build_interior(bounds, centroids, start, start + count / 2, end, axis);
+        }
+
+        let bucket_of = |i: usize| {
+            let offset = (centroids[i][axis] - centroid_bounds.min[axis]) / extent[axis];
+            usize::min((offset * SAH_BUCKETS as Num) as usize, SAH_BUCKETS - 1)
+        };
+        let mut bucket_counts = [0usize; SAH_BUCKETS];
+        let mut bucket_bounds: [Option<Aabb>; SAH_BUCKETS] = [None; SAH_BUCKETS];
+        for &i in &self.indices[start..end] {
+            let bucket = bucket_of(i);
+            bucket_counts[bucket] += 1;
+            bucket_bounds[bucket] = Some(match bucket_bounds[bucket] {
+                Some(b) => b.union(bounds[i]),
+                None => bounds[i],
+            });
+        }
+
+        // cost of splitting after each bucket, relative to the node's area
+        let mut best_split = 0;
+        let mut best_cost = INFINITY;
+        for split in 0..(SAH_BUCKETS - 1) {
+            let side = |buckets: std::ops::Range<usize>| {
+                let count: usize = bucket_counts[buckets.clone()].iter().sum();
+                let area = bucket_bounds[buckets]
+                    .iter()
+                    .flatten()
+                    .copied()
+                    .reduce(Aabb::union)
+                    .map_or(0.0, |b| b.surface_area());
+                count as Num * area
+            };
+            let cost = TRAVERSAL_COST
+                + (side(0..(split + 1)) + side((split + 1)..SAH_BUCKETS))
+                    / node_bounds.surface_area();
+            if cost < best_cost {
+                best_cost = cost;
+                best_split = split;
+            }
+        }
+
+        if count <= MAX_LEAF_SIZE && best_cost >= count as Num {
+            self.nodes.push(leaf);
+            return node_index;
+        }
+
+        // partition the range in place around the chosen bucket boundary
+        let mut mid = start;
+        for i in start..end {
+            if bucket_of(self.indices[i]) <= best_split {
+                self.indices.swap(i, mid);
+                mid += 1;
+            }
+        }
+        if mid == start || mid == end {
+            mid = start + count / 2;
+        }
+        self.build_interior(bounds, centroids, start, mid, end, axis)
+    }
+
+    fn build_interior(
+        &mut self,
+        bounds: &[Aabb],
+        centroids: &[Point],
+        start: usize,
+        mid: usize,
+        end: usize,
+        axis: usize,
+    ) -> usize {
+        let node_index = self.nodes.len();
+        // placeholder until the children's indices are known
+        self.nodes.push(BvhNode::Leaf {
+            bounds: bounds[self.indices[start]],
+            start,
+            count: 0,
+        });
+        let left = self.build(bounds, centroids, start, mid);
+        let right = self.build(bounds, centroids, mid, end);
+        self.nodes[node_index] = BvhNode::Interior {
+            bounds: self.nodes[left].bounds().union(*self.nodes[right].bounds()),
+            axis,
+            left,
+            right,
+        };
+        node_index
+    }
+}
+
+/// A bounding volume hierarchy over arbitrary hittables.
+///
+/// Unbounded objects (like infinite planes) can't be placed in the tree, so
+/// they're kept aside and tested against every ray.
+pub struct Bvh {
+    tree: BvhTree,
+    objects: Vec<HittablePtr>,
+    unbounded: HittablesList,
+}
+
+impl Bvh {
+    pub fn new(objects: Vec<HittablePtr>) -> Bvh {
+        let (bounded, unbounded): (Vec<_>, Vec<_>) = objects
+            .into_iter()
+            .partition(|object| object.bounding_box().is_some());
+        let bounds: Vec<Aabb> = bounded
+            .iter()
+            .map(|object| object.bounding_box().unwrap())
+            .collect();
+        Bvh {
+            tree: BvhTree::new(&bounds),
+            objects: bounded,
+            unbounded,
+        }
+    }
+}
+
+impl Hit for Bvh {
+    fn hit(&self, ray: &Ray, t_min: Num, t_max: Num) -> Option<HitRecord> {
+        let closest = self.unbounded.hit(ray, t_min, t_max);
+        let t_max = closest.as_ref().map_or(t_max, |r| r.t);
+        self.tree
+            .hit(ray, t_min, t_max, |index, t_min, t_max| {
+                self.objects[index].hit(ray, t_min, t_max)
+            })
+            .or(closest)
+    }
+
+    fn sample_hit(
+        &self,
+        ray: &Ray,
+        t_min: Num,
+        t_max: Num,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
+        let closest = self.unbounded.sample_hit(ray, t_min, t_max, sampler);
+        let t_max = closest.as_ref().map_or(t_max, |r| r.t);
+        self.tree
+            .hit(ray, t_min, t_max, |index, t_min, t_max| {
+                self.objects[index].sample_hit(ray, t_min, t_max, sampler)
+            })
+            .or(closest)
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: Num, t_max: Num, sampler: &mut dyn Sampler) -> Num {
+        let mut transmittance = self.unbounded.transmittance(ray, t_min, t_max, sampler);
+        // every object along the ray is visited, none is ever the closest
+        self.tree.hit(ray, t_min, t_max, |index, t_min, t_max| {
+            if transmittance > 0.0 {
+                transmittance *= self.objects[index].transmittance(ray, t_min, t_max, sampler);
+            }
+            None
+        });
+        transmittance
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        if self.unbounded.is_empty() {
+            self.tree.bounding_box()
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_bvh {
+    use super::*;
+    use crate::materials::*;
+    use crate::math::transform::*;
+    use crate::math::vec3::*;
+    use std::sync::Arc;
+
+    fn scene(rng: &mut Rng) -> HittablesList {
+        let mut world = HittablesList::new();
+        let material: MaterialPtr = Arc::new(Lambertian::new(Color::one()));
+        for _ in 0..300 {
+            world.push(Arc::new(Sphere {
+                center: random_vec3_rng(rng, -10.0, 10.0),
+                radius: rng.num_range(0.05, 1.0),
+                material: material.clone(),
+            }));
+        }
+        for _ in 0..300 {
+            let p1 = random_vec3_rng(rng, -10.0, 10.0);
+            world.push(Arc::new(Triangle::new(
+                p1,
+                p1 + random_vec3_rng(rng, -1.0, 1.0),
+                p1 + random_vec3_rng(rng, -1.0, 1.0),
+                material.clone(),
+            )));
+        }
+        world
+    }
+
+    #[test]
+    fn test_matches_linear_list() {
+        let mut rng = Rng::new(0);
+        let rng = &mut rng;
+        let list = scene(rng);
+        let bvh = Bvh::new(list.clone());
+        assert_eq!(bvh.bounding_box(), list.bounding_box());
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let ray = Ray {
+                origin: random_vec3_rng(rng, -15.0, 15.0),
+                direction: random_vec3_rng(rng, -1.0, 1.0),
+                time: 0.0,
+            };
+            let expected = list.hit(&ray, 0.001, INFINITY);
+            let actual = bvh.hit(&ray, 0.001, INFINITY);
+            match (expected, actual) {
+                (Some(expected), Some(actual)) => {
+                    hits += 1;
+                    assert_eq!(expected.t, actual.t);
+                    assert_eq!(expected.position, actual.position);
+                    assert_eq!(expected.normal, actual.normal);
+                }
+                (None, None) => {}
+                (expected, actual) => panic!(
+                    "linear list hit: {}, bvh hit: {}",
+                    expected.is_some(),
+                    actual.is_some()
+                ),
+            }
+        }
+        assert!(hits > 0);
+    }
+
+    #[test]
+    fn test_empty_and_unbounded() {
+        let empty = Bvh::new(Vec::new());
+        let ray = Ray {
+            origin: Point::zero(),
+            direction: Vec3::unit_x(),
+            time: 0.0,
+        };
+        assert!(empty.hit(&ray, 0.001, INFINITY).is_none());
+        assert!(empty.bounding_box().is_none());
+
+        let plane: HittablePtr = Arc::new(Plane {
+            p1: Point::zero(),
+            normal: Vec3::unit_y(),
+            material: Arc::new(Lambertian::new(Color::one())),
+        });
+        let with_plane = Bvh::new(vec![plane]);
+        assert!(with_plane.bounding_box().is_none());
+    }
+
+    #[test]
+    fn test_shared_instances() {
+        let mut rng = Rng::new(1);
+        let rng = &mut rng;
+        let mesh: HittablePtr = Arc::new(Bvh::new(scene(rng)));
+        let mut copies = Vec::new();
+        let mut placed: Vec<HittablePtr> = Vec::new();
+        for i in 0..1000 {
+            let offset =
+                Vec3::new((i % 10) as Num, ((i / 10) % 10) as Num, (i / 100) as Num) * 50.0;
+            let transform =
+                Transform::rotate(Vec3::unit_y(), i as Num).then(&Transform::translate(offset));
+            placed.push(Arc::new(Instance::new(mesh.clone(), transform)));
+            if i % 100 == 7 {
+                copies.push((transform, offset));
+            }
+        }
+        // every copy is the same object
+        assert_eq!(Arc::strong_count(&mesh), 1001);
+        let world = Bvh::new(placed);
+        for (transform, offset) in copies {
+            for _ in 0..20 {
+                let local = Ray {
+                    origin: random_vec3_rng(rng, -15.0, 15.0),
+                    direction: random_vec3_rng(rng, -1.0, 1.0),
+                    time: 0.0,
+                };
+                let ray = Ray {
+                    origin: transform.point(local.origin),
+                    direction: transform.vector(local.direction),
+                    time: local.time,
+                };
+                // stay well inside this copy's cell of the grid
+                if (ray.origin - offset).magnitude() > 14.0 {
+                    continue;
+                }
+                if let Some(expected) = mesh.hit(&local, 0.001, INFINITY) {
+                    let actual = world.hit(&ray, 0.001, INFINITY).unwrap();
+                    assert!((expected.t - actual.t).abs() < 1e-6);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,724 @@
+use std::path::{Path, PathBuf};
+use yart::filter::*;
+use yart::math::vec3::*;
+use yart::math::*;
+use yart::output::*;
+use yart::render::*;
+use yart::sampler::*;
+use yart::scene_file::*;
+use yart::tonemap::*;
+use yart::types::*;
+
+pub const USAGE: &str = "\
+yart - yet another ray tracer
+
+USAGE:
+    yart [OPTIONS]
+
+OPTIONS:
+        --scene <FILE>        scene description (.toml) to render [default: random spheres]
+    -o, --output <FILE>       image to write, .png, .ppm, or linear .pfm or .exr [default: 21-hello_hello.png]
+        --exr-compression <C> none or zip, for .exr output [default: zip]
+        --exposure <STOPS>    brighten (or darken, if negative) .png and .ppm output [default: 0]
+        --tonemap <NAME>      clamp, reinhard, reinhard-extended, hable or aces [default: clamp]
+        --white-point <L>     luminance reinhard-extended maps to white [default: 4]
+    -w, --width <PIXELS>      image width [default: 1366]
+        --height <PIXELS>     image height [default: keeps the aspect ratio, 16:9]
+    -s, --samples <N>         samples per pixel [default: 100]
+    -d, --max-depth <N>       maximum bounces per path [default: 50]
+    -t, --threads <N>         worker threads [default: number of CPUs]
+        --seed <N>            random seed, the same seed gives the same image [default: 0]
+        --sampler <NAME>      independent, stratified, halton or sobol [default: sobol]
+        --max-samples <N>     sample noisy pixels adaptively, up to N samples each [default: off]
+        --noise-threshold <X> relative error adaptive sampling stops at [default: 0.02]
+        --filter <NAME>       box, tent, gaussian, mitchell or lanczos [default: gaussian]
+        --filter-radius <PX>  how far samples spread [default: 0.5, 1, 1.5, 2 or 3, by filter]
+        --heatmap <FILE>      also write each pixel's sample count, in any output format
+        --look-from <X,Y,Z>   camera position [default: 4,2,3]
+        --look-at <X,Y,Z>     point the camera faces [default: 0,0,0]
+        --vup <X,Y,Z>         camera up direction [default: 0,1,0]
+        --fov <DEGREES>       vertical field of view [default: 90]
+        --aperture <SIZE>     lens diameter, 0 for a pinhole [default: 0.1]
+        --focus-dist <DIST>   distance to the plane in focus [default: 10]
+        --shutter-open <T>    time the shutter opens, for motion blur [default: 0]
+        --shutter-close <T>   time the shutter closes [default: 0, no motion blur]
+    -h, --help                print this message
+";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CameraOptions {
+    pub look_from: Point,
+    pub look_at: Point,
+    pub vup: Vec3,
+    pub vertical_fov: Num,
+    pub aperture: Num,
+    pub focus_dist: Num,
+    pub shutter_open: Num,
+    pub shutter_close: Num,
+}
+
+impl CameraOptions {
+    pub fn camera(&self, aspect_ratio: Num) -> Camera {
+        Camera::new(
+            self.look_from,
+            self.look_at,
+            self.vup,
+            aspect_ratio,
+            self.vertical_fov,
+            self.aperture,
+            self.focus_dist,
+        )
+        .with_shutter(self.shutter_open, self.shutter_close)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    pub output: PathBuf,
+    pub exr_compression: ExrCompression,
+    pub width: usize,
+    pub height: usize,
+    pub samples_per_pixel: usize,
+    pub max_depth: usize,
+    pub threads: usize,
+    pub seed: u64,
+    pub sampler: SamplerKind,
+    /// `Some` for adaptive sampling
+    pub max_samples: Option<usize>,
+    pub noise_threshold: Num,
+    pub heatmap: Option<PathBuf>,
+    pub filter: FilterKind,
+    /// `None` for the filter's default
+    pub filter_radius: Option<Num>,
+    pub display: DisplayTransform,
+    pub camera: CameraOptions,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        let render = RenderSettings::default();
+        Options {
+            output: PathBuf::from("21-hello_hello.png"),
+            exr_compression: ExrCompression::Zip,
+            width: render.width,
+            height: render.height,
+            samples_per_pixel: render.samples_per_pixel,
+            max_depth: render.max_depth,
+            threads: render.threads,
+            seed: render.seed,
+            sampler: render.sampler,
+            max_samples: None,
+            noise_threshold: 0.02,
+            heatmap: None,
+            filter: render.filter.kind,
+            filter_radius: None,
+            display: DisplayTransform::default(),
+            camera: CameraOptions {
+                look_from: Point::new(4, 2, 3),
+                look_at: Point::new(0, 0, 0),
+                vup: Vec3::unit_y(),
+                vertical_fov: 90.0,
+                aperture: 0.1,
+                focus_dist: 10.0,
+                shutter_open: 0.0,
+                shutter_close: 0.0,
+            },
+        }
+    }
+}
+
+impl Options {
+    pub fn render_settings(&self) -> RenderSettings {
+        RenderSettings {
+            width: self.width,
+            height: self.height,
+            samples_per_pixel: self.samples_per_pixel,
+            max_depth: self.max_depth,
+            threads: self.threads,
+            seed: self.seed,
+            sampler: self.sampler,
+            adaptive: self.max_samples.map(|max_samples| AdaptiveSampling {
+                threshold: self.noise_threshold,
+                max_samples,
+            }),
+            filter: Filter::with_radius(
+                self.filter,
+                self.filter_radius
+                    .unwrap_or_else(|| self.filter.default_radius()),
+            ),
+        }
+    }
+
+    /// The format to write `path` in, `None` for unknown extensions.
+    pub fn image_format(&self, path: &Path) -> Option<ImageFormat> {
+        ImageFormat::from_path(path).map(|format| format.with_exr_compression(self.exr_compression))
+    }
+
+    /// The defaults, overridden by whatever the scene file sets.
+    pub fn from_scene(settings: &SceneSettings) -> Options {
+        let defaults = Options::default();
+        let (width, height) = match (settings.width, settings.height) {
+            (Some(width), Some(height)) => (width, height),
+            (Some(width), None) => (width, scale(width, defaults.height, defaults.width)),
+            (None, Some(height)) => (scale(height, defaults.width, defaults.height), height),
+            (None, None) => (defaults.width, defaults.height),
+        };
+        let camera = defaults.camera.clone();
+        let display = defaults.display;
+        Options {
+            width,
+            height,
+            samples_per_pixel: settings
+                .samples_per_pixel
+                .unwrap_or(defaults.samples_per_pixel),
+            max_depth: settings.max_depth.unwrap_or(defaults.max_depth),
+            seed: settings.seed.unwrap_or(defaults.seed),
+            sampler: settings.sampler.unwrap_or(defaults.sampler),
+            max_samples: settings.max_samples.or(defaults.max_samples),
+            noise_threshold: settings.noise_threshold.unwrap_or(defaults.noise_threshold),
+            filter: settings.filter.unwrap_or(defaults.filter),
+            filter_radius: settings.filter_radius.or(defaults.filter_radius),
+            camera: CameraOptions {
+                look_from: settings.look_from.unwrap_or(camera.look_from),
+                look_at: settings.look_at.unwrap_or(camera.look_at),
+                vup: settings.vup.unwrap_or(camera.vup),
+                vertical_fov: settings.vertical_fov.unwrap_or(camera.vertical_fov),
+                aperture: settings.aperture.unwrap_or(camera.aperture),
+                focus_dist: settings.focus_dist.unwrap_or(camera.focus_dist),
+                shutter_open: settings.shutter_open.unwrap_or(camera.shutter_open),
+                shutter_close: settings.shutter_close.unwrap_or(camera.shutter_close),
+            },
+            display: DisplayTransform {
+                exposure: settings.exposure.unwrap_or(display.exposure),
+                tone_map: settings.tone_map.unwrap_or(display.tone_map),
+                white_point: settings.white_point.unwrap_or(display.white_point),
+            },
+            ..defaults
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Render(Invocation),
+    Help,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Setting {
+    Output(PathBuf),
+    ExrCompression(ExrCompression),
+    Width(usize),
+    Height(usize),
+    Samples(usize),
+    MaxDepth(usize),
+    Threads(usize),
+    Seed(u64),
+    Sampler(SamplerKind),
+    MaxSamples(usize),
+    NoiseThreshold(Num),
+    Heatmap(PathBuf),
+    Filter(FilterKind),
+    FilterRadius(Num),
+    Exposure(Num),
+    ToneMap(ToneMap),
+    WhitePoint(Num),
+    LookFrom(Vec3),
+    LookAt(Vec3),
+    Vup(Vec3),
+    Fov(Num),
+    Aperture(Num),
+    FocusDist(Num),
+    ShutterOpen(Num),
+    ShutterClose(Num),
+}
+
+/// The scene to render and the settings given on the command line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invocation {
+    pub scene: Option<PathBuf>,
+    settings: Vec<Setting>,
+}
+
+impl Invocation {
+    /// Applies the command line settings over `base` (the defaults, or the
+    /// ones from a scene file) and validates the result.
+    ///
+    /// Passing only one of the width and height keeps `base`'s aspect ratio.
+    pub fn options(&self, base: Options) -> Result<Options, String> {
+        let mut options = base.clone();
+        let (mut width_set, mut height_set) = (false, false);
+        for setting in &self.settings {
+            match setting.clone() {
+                Setting::Output(output) => options.output = output,
+                Setting::ExrCompression(compression) => options.exr_compression = compression,
+                Setting::Width(width) => {
+                    options.width = width;
+                    width_set = true;
+                }
+                Setting::Height(height) => {
+                    options.height = height;
+                    height_set = true;
+                }
+                Setting::Samples(samples) => options.samples_per_pixel = samples,
+                Setting::MaxDepth(depth) => options.max_depth = depth,
+                Setting::Threads(threads) => options.threads = threads,
+                Setting::Seed(seed) => options.seed = seed,
+                Setting::Sampler(sampler) => options.sampler = sampler,
+                Setting::MaxSamples(max) => options.max_samples = Some(max),
+                Setting::NoiseThreshold(threshold) => options.noise_threshold = threshold,
+                Setting::Heatmap(heatmap) => options.heatmap = Some(heatmap),
+                Setting::Filter(filter) => options.filter = filter,
+                Setting::FilterRadius(radius) => options.filter_radius = Some(radius),
+                Setting::Exposure(stops) => options.display.exposure = stops,
+                Setting::ToneMap(tone_map) => options.display.tone_map = tone_map,
+                Setting::WhitePoint(white) => options.display.white_point = white,
+                Setting::LookFrom(point) => options.camera.look_from = point,
+                Setting::LookAt(point) => options.camera.look_at = point,
+                Setting::Vup(vup) => options.camera.vup = vup,
+                Setting::Fov(fov) => options.camera.vertical_fov = fov,
+                Setting::Aperture(aperture) => options.camera.aperture = aperture,
+                Setting::FocusDist(dist) => options.camera.focus_dist = dist,
+                Setting::ShutterOpen(time) => options.camera.shutter_open = time,
+                Setting::ShutterClose(time) => options.camera.shutter_close = time,
+            }
+        }
+        match (width_set, height_set) {
+            (true, false) => options.height = scale(options.width, base.height, base.width),
+            (false, true) => options.width = scale(options.height, base.width, base.height),
+            _ => (),
+        }
+        validate(&options)?;
+        Ok(options)
+    }
+}
+
+/// Parses the arguments following the program name.
+///
+/// Flags take their value either as the next argument or after an `=`.
+pub fn parse_args<I>(args: I) -> Result<Command, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut invocation = Invocation {
+        scene: None,
+        settings: Vec::new(),
+    };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = match arg.find('=') {
+            Some(index) if arg.starts_with("--") => (
+                arg[..index].to_string(),
+                Some(arg[(index + 1)..].to_string()),
+            ),
+            _ => (arg.clone(), None),
+        };
+        if flag == "-h" || flag == "--help" {
+            return Ok(Command::Help);
+        }
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("{} needs a value", flag))
+        };
+        let setting = match flag.as_str() {
+            "--scene" => {
+                invocation.scene = Some(PathBuf::from(value()?));
+                continue;
+            }
+            "-o" | "--output" => Setting::Output(PathBuf::from(value()?)),
+            "--exr-compression" => Setting::ExrCompression(value()?.parse()?),
+            "-w" | "--width" => Setting::Width(parse_count(&flag, &value()?)?),
+            "--height" => Setting::Height(parse_count(&flag, &value()?)?),
+            "-s" | "--samples" => Setting::Samples(parse_count(&flag, &value()?)?),
+            "-d" | "--max-depth" => Setting::MaxDepth(parse_count(&flag, &value()?)?),
+            "-t" | "--threads" => Setting::Threads(parse_count(&flag, &value()?)?),
+            "--seed" => Setting::Seed(parse_seed(&flag, &value()?)?),
+            "--sampler" => Setting::Sampler(value()?.parse()?),
+            "--max-samples" => Setting::MaxSamples(parse_count(&flag, &value()?)?),
+            "--noise-threshold" => Setting::NoiseThreshold(parse_num(&flag, &value()?)?),
+            "--heatmap" => Setting::Heatmap(PathBuf::from(value()?)),
+            "--filter" => Setting::Filter(value()?.parse()?),
+            "--filter-radius" => Setting::FilterRadius(parse_num(&flag, &value()?)?),
+            "--exposure" => Setting::Exposure(parse_num(&flag, &value()?)?),
+            "--tonemap" => Setting::ToneMap(value()?.parse()?),
+            "--white-point" => Setting::WhitePoint(parse_num(&flag, &value()?)?),
+            "--look-from" => Setting::LookFrom(parse_vec3(&flag, &value()?)?),
+            "--look-at" => Setting::LookAt(parse_vec3(&flag, &value()?)?),
+            "--vup" => Setting::Vup(parse_vec3(&flag, &value()?)?),
+            "--fov" => Setting::Fov(parse_num(&flag, &value()?)?),
+            "--aperture" => Setting::Aperture(parse_num(&flag, &value()?)?),
+            "--focus-dist" => Setting::FocusDist(parse_num(&flag, &value()?)?),
+            "--shutter-open" => Setting::ShutterOpen(parse_num(&flag, &value()?)?),
+            "--shutter-close" => Setting::ShutterClose(parse_num(&flag, &value()?)?),
+            _ => return Err(format!("unknown option `{}`", arg)),
+        };
+        invocation.settings.push(setting);
+    }
+    Ok(Command::Render(invocation))
+}
+
+fn validate(options: &Options) -> Result<(), String> {
+    if options.height == 0 {
+        return Err("--width is too small to derive a height, pass --height".to_string());
+    }
+    if options.width == 0 {
+        return Err("--height is too small to derive a width, pass --width".to_string());
+    }
+    if ImageFormat::from_path(&options.output).is_none() {
+        return Err(format!(
+            "can't write {}, the output must end in .png, .ppm, .pfm or .exr",
+            options.output.display()
+        ));
+    }
+    if let Some(heatmap) = &options.heatmap {
+        if ImageFormat::from_path(heatmap).is_none() {
+            return Err(format!(
+                "can't write {}, the heatmap must end in .png, .ppm, .pfm or .exr",
+                heatmap.display()
+            ));
+        }
+        if options.max_samples.is_none() {
+            return Err("--heatmap needs adaptive sampling, pass --max-samples".to_string());
+        }
+    }
+    if options
+        .max_samples
+        .is_some_and(|max| max < options.samples_per_pixel)
+    {
+        return Err("--max-samples can't be less than --samples".to_string());
+    }
+    if options.noise_threshold <= 0.0 {
+        return Err("--noise-threshold must be positive".to_string());
+    }
+    if options.filter_radius.is_some_and(|radius| radius <= 0.0) {
+        return Err("--filter-radius must be positive".to_string());
+    }
+    if options.display.white_point <= 0.0 {
+        return Err("--white-point must be positive".to_string());
+    }
+    let camera = &options.camera;
+    if !(camera.vertical_fov > 0.0 && camera.vertical_fov < 180.0) {
+        return Err("--fov must be between 0 and 180 degrees".to_string());
+    }
+    if camera.aperture < 0.0 {
+        return Err("--aperture can't be negative".to_string());
+    }
+    if camera.focus_dist <= 0.0 {
+        return Err("--focus-dist must be positive".to_string());
+    }
+    if camera.shutter_close < camera.shutter_open {
+        return Err("--shutter-close can't be before --shutter-open".to_string());
+    }
+    let view = camera.look_at - camera.look_from;
+    if view.magnitude_squared() == 0.0 {
+        return Err("--look-from and --look-at must be different points".to_string());
+    }
+    if view.cross(camera.vup).magnitude_squared() == 0.0 {
+        return Err("--vup can't be parallel to the viewing direction".to_string());
+    }
+    Ok(())
+}
+
+/// `size * numerator / denominator`, rounded to the nearest pixel.
+fn scale(size: usize, numerator: usize, denominator: usize) -> usize {
+    (size * numerator + denominator / 2) / denominator
+}
+
+/// A positive integer.
+fn parse_count(flag: &str, value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(count) if count > 0 && count <= i32::MAX as usize => Ok(count),
+        _ => Err(format!(
+            "{} expects a positive whole number, got `{}`",
+            flag, value
+        )),
+    }
+}
+
+fn parse_seed(flag: &str, value: &str) -> Result<u64, String> {
+    value
+        .parse::<u64>()
+        .map_err(|_| format!("{} expects a whole number, got `{}`", flag, value))
+}
+
+fn parse_num(flag: &str, value: &str) -> Result<Num, String> {
+    match value.parse::<Num>() {
+        Ok(num) if num.is_finite() => Ok(num),
+        _ => Err(format!("{} expects a number, got `{}`", flag, value)),
+    }
+}
+
+/// Three comma separated numbers.
+fn parse_vec3(flag: &str, value: &str) -> Result<Vec3, String> {
+    let error = || {
+        format!(
+            "{} expects three numbers like `1,2,3`, got `{}`",
+            flag, value
+        )
+    };
+    let coords = value
+        .split(',')
+        .map(|coord| coord.trim().parse::<Num>().map_err(|_| error()))
+        .collect::<Result<Vec<_>, _>>()?;
+    match coords[..] {
+        [x, y, z] if x.is_finite() && y.is_finite() && z.is_finite() => Ok(Vec3::new(x, y, z)),
+        _ => Err(error()),
+    }
+}
+
+#[cfg(test)]
+mod test_cli {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn options(args: &[&str], base: Options) -> Result<Options, String> {
+        match parse(args)? {
+            Command::Render(invocation) => invocation.options(base),
+            Command::Help => panic!("unexpected help"),
+        }
+    }
+
+    #[test]
+    fn test_defaults() {
+        let defaults = Options::default();
+        assert_eq!(options(&[], Options::default()), Ok(defaults.clone()));
+        assert_eq!(defaults.height, 768);
+        let hdr = options(&["-o", "linear.exr"], Options::default()).unwrap();
+        assert_eq!(hdr.output, PathBuf::from("linear.exr"));
+        assert_eq!(
+            hdr.image_format(&hdr.output),
+            Some(ImageFormat::Exr(ExrCompression::Zip))
+        );
+        let raw = options(
+            &["-o", "linear.exr", "--exr-compression=none"],
+            Options::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            raw.image_format(&raw.output),
+            Some(ImageFormat::Exr(ExrCompression::None))
+        );
+        assert_eq!(
+            raw.image_format(Path::new("linear.png")),
+            Some(ImageFormat::Png)
+        );
+        assert_eq!(parse(&["-s", "5", "--help"]), Ok(Command::Help));
+        match parse(&["--scene", "scenes/cornell.toml"]) {
+            Ok(Command::Render(invocation)) => {
+                assert_eq!(invocation.scene, Some(PathBuf::from("scenes/cornell.toml")))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_from_scene() {
+        let settings = SceneSettings {
+            width: Some(640),
+            samples_per_pixel: Some(8),
+            look_from: Some(Point::new(1, 2, 3)),
+            ..SceneSettings::default()
+        };
+        let options = Options::from_scene(&settings);
+        assert_eq!((options.width, options.height), (640, 360));
+        assert_eq!(options.samples_per_pixel, 8);
+        assert_eq!(options.camera.look_from, Point::new(1, 2, 3));
+        assert_eq!(options.camera.look_at, Point::zero());
+        assert_eq!(
+            Options::from_scene(&SceneSettings::default()),
+            Options::default()
+        );
+    }
+
+    #[test]
+    fn test_aspect_ratio() {
+        let square = Options {
+            width: 400,
+            height: 400,
+            ..Options::default()
+        };
+        let size = |args: &[&str]| {
+            let options = options(args, square.clone()).unwrap();
+            (options.width, options.height)
+        };
+        assert_eq!(size(&[]), (400, 400));
+        assert_eq!(size(&["-w", "100"]), (100, 100));
+        assert_eq!(size(&["--height", "50"]), (50, 50));
+        assert_eq!(size(&["-w", "100", "--height", "50"]), (100, 50));
+    }
+
+    #[test]
+    fn test_flags() {
+        let options = options(
+            &[
+                "--width=640",
+                "-s",
+                "16",
+                "--max-depth",
+                "8",
+                "-t",
+                "2",
+                "--seed",
+                "42",
+                "--sampler=halton",
+                "--look-from",
+                "13, 2, 3",
+                "--fov=20",
+                "--aperture",
+                "0",
+                "-o",
+                "out.ppm",
+            ],
+            Options::default(),
+        )
+        .unwrap();
+        assert_eq!(options.exr_compression, ExrCompression::Zip);
+        assert_eq!((options.width, options.height), (640, 360));
+        assert_eq!(options.samples_per_pixel, 16);
+        assert_eq!(options.max_depth, 8);
+        assert_eq!(options.threads, 2);
+        assert_eq!(options.seed, 42);
+        assert_eq!(options.sampler, SamplerKind::Halton);
+        assert_eq!(options.camera.look_from, Point::new(13, 2, 3));
+        assert_eq!(options.camera.vertical_fov, 20.0);
+        assert_eq!(options.camera.aperture, 0.0);
+        assert_eq!(options.output, PathBuf::from("out.ppm"));
+        assert_eq!(options.render_settings().adaptive, None);
+        assert_eq!(options.render_settings().filter, Filter::default());
+    }
+
+    #[test]
+    fn test_filter() {
+        let filter = |args: &[&str]| {
+            options(args, Options::default())
+                .unwrap()
+                .render_settings()
+                .filter
+        };
+        assert_eq!(
+            filter(&["--filter", "mitchell"]),
+            Filter::new(FilterKind::Mitchell)
+        );
+        assert_eq!(
+            filter(&["--filter=lanczos", "--filter-radius", "2"]),
+            Filter::with_radius(FilterKind::Lanczos, 2.0)
+        );
+        assert_eq!(
+            filter(&["--filter-radius", "1"]),
+            Filter::with_radius(FilterKind::Gaussian, 1.0)
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let options = options(
+            &[
+                "--exposure=-2.5",
+                "--tonemap",
+                "reinhard-extended",
+                "--white-point",
+                "8",
+            ],
+            Options::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            options.display,
+            DisplayTransform {
+                exposure: -2.5,
+                tone_map: ToneMap::ExtendedReinhard,
+                white_point: 8.0,
+            }
+        );
+        let settings = SceneSettings {
+            tone_map: Some(ToneMap::Aces),
+            ..SceneSettings::default()
+        };
+        let options = Options::from_scene(&settings);
+        assert_eq!(options.display.tone_map, ToneMap::Aces);
+        assert_eq!(options.display.exposure, 0.0);
+    }
+
+    #[test]
+    fn test_adaptive() {
+        let options = options(
+            &[
+                "-s",
+                "16",
+                "--max-samples",
+                "256",
+                "--noise-threshold=0.05",
+                "--heatmap",
+                "counts.png",
+            ],
+            Options::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            options.render_settings().adaptive,
+            Some(AdaptiveSampling {
+                threshold: 0.05,
+                max_samples: 256,
+            })
+        );
+        assert_eq!(options.heatmap, Some(PathBuf::from("counts.png")));
+    }
+
+    #[test]
+    fn test_shutter() {
+        let options = options(
+            &["--shutter-open", "0.25", "--shutter-close=1"],
+            Options::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            (options.camera.shutter_open, options.camera.shutter_close),
+            (0.25, 1.0)
+        );
+        assert_eq!(options.camera.camera(1.0).shutter(), (0.25, 1.0));
+        let settings = SceneSettings {
+            shutter_close: Some(0.5),
+            ..SceneSettings::default()
+        };
+        let camera = Options::from_scene(&settings).camera;
+        assert_eq!((camera.shutter_open, camera.shutter_close), (0.0, 0.5));
+    }
+
+    #[test]
+    fn test_invalid() {
+        let error = |args: &[&str]| options(args, Options::default()).unwrap_err();
+        assert!(error(&["--samples", "0"]).contains("positive whole number"));
+        assert!(error(&["--width", "-3"]).contains("--width"));
+        assert!(error(&["--threads"]).contains("needs a value"));
+        assert!(error(&["--seed", "-1"]).contains("whole number"));
+        assert!(error(&["--sampler", "random"]).contains("unknown sampler"));
+        assert!(error(&["--bogus"]).contains("unknown option"));
+        assert!(error(&["--tonemap", "filmic"]).contains("unknown tone map"));
+        assert!(error(&["--exr-compression", "lzw"]).contains("unknown exr compression"));
+        assert!(error(&["--filter", "sinc"]).contains("unknown filter"));
+        assert!(error(&["--filter-radius", "-1"]).contains("--filter-radius"));
+        assert!(error(&["--white-point", "0"]).contains("--white-point"));
+        assert!(error(&["--fov", "180"]).contains("--fov"));
+        assert!(error(&["--look-at", "1,2"]).contains("three numbers"));
+        assert!(error(&["--look-at", "4,2,3"]).contains("different points"));
+        assert!(error(&["--vup", "4,2,3"]).contains("parallel"));
+        assert!(error(&["-o", "out.jpg"]).contains(".pfm or .exr"));
+        assert!(error(&["-s", "64", "--max-samples", "32"]).contains("less than --samples"));
+        assert!(error(&["--max-samples", "200", "--noise-threshold", "0"]).contains("positive"));
+        assert!(error(&["--heatmap", "counts.png"]).contains("--max-samples"));
+        assert!(error(&["--max-samples", "200", "--heatmap", "counts.tga"]).contains("heatmap"));
+        assert!(error(&["--shutter-open", "1"]).contains("--shutter-close"));
+        let panorama = Options {
+            width: 1000,
+            height: 100,
+            ..Options::default()
+        };
+        let error = options(&["--width", "4"], panorama).unwrap_err();
+        assert!(error.contains("--height"));
+    }
+}
@@ -0,0 +1,182 @@
+//! The floating point framebuffer samples are accumulated in.
+
+use crate::filter::*;
+use crate::math::*;
+use crate::output::*;
+use crate::types::*;
+
+/// Weighted sums of linear radiance and the sums of their weights, one per
+/// pixel, rows from the top down.
+///
+/// Nothing is clamped or gamma corrected, the pixel values are only divided
+/// through when they're read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Film {
+    pub width: usize,
+    pub height: usize,
+    radiance: Vec<Color>,
+    weights: Vec<Num>,
+}
+
+impl Film {
+    /// A film with no samples.
+    pub fn new(width: usize, height: usize) -> Film {
+        Film {
+            width,
+            height,
+            radiance: vec![Color::zero(); width * height],
+            weights: vec![0.0; width * height],
+        }
+    }
+
+    pub fn add_sample(&mut self, x: usize, y: usize, radiance: Color, weight: Num) {
+        let index = y * self.width + x;
+        self.radiance[index] = self.radiance[index] + radiance * weight;
+        self.weights[index] += weight;
+    }
+
+    /// Adds a sample taken `x` pixels from the film's left edge and `y` from
+    /// its top to every pixel whose center is within the filter's radius,
+    /// weighted by the filter.
+    ///
+    /// The support is half open, so with a one pixel box each sample lands
+    /// in exactly the pixel it was taken in.
+    pub fn splat(&mut self, x: Num, y: Num, radiance: Color, filter: &Filter) {
+        // pixels with centers in (position - radius, position + radius]
+        let range = |position: Num, size: usize| {
+            let first = Num::floor(position - 0.5 - filter.radius) as isize + 1;
+            let last = Num::floor(position - 0.5 + filter.radius) as isize;
+            (first.max(0), last.min(size as isize - 1))
+        };
+        let (first_x, last_x) = range(x, self.width);
+        let (first_y, last_y) = range(y, self.height);
+        for py in first_y..=last_y {
+            for px in first_x..=last_x {
+                let dx = px as Num + 0.5 - x;
+                let dy = py as Num + 0.5 - y;
+                let weight = filter.evaluate(dx, dy);
+                if weight != 0.0 {
+                    self.add_sample(px as usize, py as usize, radiance, weight);
+                }
+            }
+        }
+    }
+
+    /// Adds the samples of `other` onto the pixels starting `x` columns from
+    /// the left and `y` rows from the top.
+    pub fn merge(&mut self, other: &Film, x: usize, y: usize) {
+        assert!(
+            x + other.width <= self.width && y + other.height <= self.height,
+            "merged film doesn't fit"
+        );
+        for row in 0..other.height {
+            let start = (y + row) * self.width + x;
+            let other_start = row * other.width;
+            for column in 0..other.width {
+                let (index, other_index) = (start + column, other_start + column);
+                self.radiance[index] = self.radiance[index] + other.radiance[other_index];
+                self.weights[index] += other.weights[other_index];
+            }
+        }
+    }
+
+    /// Sum of the weights of the pixel's samples.
+    pub fn weight(&self, x: usize, y: usize) -> Num {
+        self.weights[y * self.width + x]
+    }
+
+    /// The weighted average of the pixel's samples, black if it has none.
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        let index = y * self.width + x;
+        resolve(self.radiance[index], self.weights[index])
+    }
+
+    /// The weighted averages of every pixel.
+    pub fn image(&self) -> Image {
+        Image {
+            width: self.width,
+            height: self.height,
+            pixels: self
+                .radiance
+                .iter()
+                .zip(&self.weights)
+                .map(|(&radiance, &weight)| resolve(radiance, weight))
+                .collect(),
+        }
+    }
+}
+
+fn resolve(radiance: Color, weight: Num) -> Color {
+    if weight > 0.0 {
+        radiance / weight
+    } else {
+        Color::zero()
+    }
+}
+
+#[cfg(test)]
+mod test_film {
+    use super::*;
+
+    #[test]
+    fn test_weighted_average() {
+        let mut film = Film::new(2, 2);
+        film.add_sample(1, 0, Color::new(1, 2, 3), 1.0);
+        film.add_sample(1, 0, Color::new(4, 8, 12), 3.0);
+        assert_eq!(film.weight(1, 0), 4.0);
+        assert_eq!(film.pixel(1, 0), Color::new(3.25, 6.5, 9.75));
+        // no samples, no division by zero
+        assert_eq!(film.pixel(0, 1), Color::zero());
+        // radiance isn't clamped
+        film.add_sample(0, 1, Color::new(100, 0, -1), 0.5);
+        let image = film.image();
+        assert_eq!(image.pixel(0, 1), Color::new(100, 0, -1));
+        assert_eq!(image.pixel(1, 0), Color::new(3.25, 6.5, 9.75));
+    }
+
+    #[test]
+    fn test_splat() {
+        // a one pixel box keeps every sample to the pixel it's in
+        let mut film = Film::new(3, 3);
+        let box_filter = Filter::new(FilterKind::Box);
+        film.splat(1.0, 1.999, Color::one(), &box_filter);
+        film.splat(1.7, 1.2, Color::new(3, 3, 3), &box_filter);
+        assert_eq!(film.weight(1, 1), 2.0);
+        assert_eq!(film.pixel(1, 1), Color::new(2, 2, 2));
+        assert_eq!(
+            film.image()
+                .pixels
+                .iter()
+                .filter(|&&p| p != Color::zero())
+                .count(),
+            1
+        );
+
+        // a tent of radius 1 spreads a sample at a corner over four pixels
+        let mut film = Film::new(3, 3);
+        let tent = Filter::new(FilterKind::Tent);
+        film.splat(2.0, 1.0, Color::one(), &tent);
+        for &(x, y) in &[(1, 0), (2, 0), (1, 1), (2, 1)] {
+            assert_eq!(film.weight(x, y), 0.25);
+        }
+        assert_eq!(film.weight(0, 0), 0.0);
+        assert_eq!(film.weight(1, 2), 0.0);
+        // samples off the film still reach the pixels near its edge
+        film.splat(-0.25, 0.5, Color::one(), &tent);
+        assert_eq!(film.weight(0, 0), 0.25);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut film = Film::new(3, 2);
+        film.add_sample(2, 1, Color::one(), 1.0);
+        let mut tile = Film::new(2, 1);
+        tile.add_sample(0, 0, Color::new(3, 3, 3), 2.0);
+        tile.add_sample(1, 0, Color::new(2, 4, 6), 1.0);
+        film.merge(&tile, 1, 1);
+        assert_eq!(film.pixel(1, 1), Color::new(3, 3, 3));
+        assert_eq!(film.weight(2, 1), 2.0);
+        assert_eq!(film.pixel(2, 1), Color::new(1.5, 2.5, 3.5));
+        assert_eq!(film.weight(0, 0), 0.0);
+    }
+}
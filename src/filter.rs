@@ -0,0 +1,216 @@
+//! Pixel reconstruction filters, weighing how much a sample counts towards
+//! the pixels around it.
+
+use crate::math::*;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterKind {
+    /// every sample within the radius counts the same
+    Box,
+    /// linear falloff to zero at the radius
+    Tent,
+    /// a Gaussian with a standard deviation of a third of the radius,
+    /// shifted down to reach zero at the radius
+    Gaussian,
+    /// the Mitchell–Netravali cubic with B = C = 1/3, stretched to the radius
+    Mitchell,
+    /// a sinc windowed by a sinc, with as many lobes as the radius
+    Lanczos,
+}
+
+impl FilterKind {
+    pub const ALL: [FilterKind; 5] = [
+        FilterKind::Box,
+        FilterKind::Tent,
+        FilterKind::Gaussian,
+        FilterKind::Mitchell,
+        FilterKind::Lanczos,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FilterKind::Box => "box",
+            FilterKind::Tent => "tent",
+            FilterKind::Gaussian => "gaussian",
+            FilterKind::Mitchell => "mitchell",
+            FilterKind::Lanczos => "lanczos",
+        }
+    }
+
+    /// The radius, in pixels, the filter is usually used with.
+    pub fn default_radius(self) -> Num {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+            FilterKind::Lanczos => 3.0,
+        }
+    }
+}
+
+impl fmt::Display for FilterKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for FilterKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<FilterKind, String> {
+        FilterKind::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.name() == name)
+            .ok_or_else(|| {
+                format!(
+                    "unknown filter `{}`, expected box, tent, gaussian, mitchell or lanczos",
+                    name
+                )
+            })
+    }
+}
+
+/// A separable filter that's zero beyond `radius` pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Filter {
+    pub kind: FilterKind,
+    pub radius: Num,
+}
+
+impl Default for Filter {
+    fn default() -> Filter {
+        Filter::new(FilterKind::Gaussian)
+    }
+}
+
+impl Filter {
+    /// The filter with its default radius.
+    pub fn new(kind: FilterKind) -> Filter {
+        Filter::with_radius(kind, kind.default_radius())
+    }
+
+    pub fn with_radius(kind: FilterKind, radius: Num) -> Filter {
+        assert!(radius > 0.0, "filter radius must be positive");
+        Filter { kind, radius }
+    }
+
+    /// The weight of a sample `dx` and `dy` pixels away from a pixel's center.
+    pub fn evaluate(&self, dx: Num, dy: Num) -> Num {
+        self.evaluate_1d(dx) * self.evaluate_1d(dy)
+    }
+
+    fn evaluate_1d(&self, offset: Num) -> Num {
+        let x = offset.abs();
+        let radius = self.radius;
+        if x > radius {
+            return 0.0;
+        }
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => 1.0 - x / radius,
+            FilterKind::Gaussian => {
+                let sigma = radius / 3.0;
+                let gaussian = |x: Num| Num::exp(-x * x / (2.0 * sigma * sigma));
+                gaussian(x) - gaussian(radius)
+            }
+            FilterKind::Mitchell => mitchell(2.0 * x / radius),
+            FilterKind::Lanczos => sinc(x) * sinc(x / radius),
+        }
+    }
+
+    /// How many pixels beyond its own a sample can reach.
+    pub fn margin(&self) -> usize {
+        Num::ceil(self.radius - 0.5).max(0.0) as usize
+    }
+}
+
+/// The Mitchell–Netravali cubic over [0, 2) with B = C = 1/3.
+fn mitchell(x: Num) -> Num {
+    let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+    let value = if x < 1.0 {
+        (12.0 - 9.0 * b - 6.0 * c) * x * x * x
+            + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+            + (6.0 - 2.0 * b)
+    } else {
+        (-b - 6.0 * c) * x * x * x
+            + (6.0 * b + 30.0 * c) * x * x
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c)
+    };
+    value / 6.0
+}
+
+fn sinc(x: Num) -> Num {
+    if x.abs() < 1e-5 {
+        1.0
+    } else {
+        Num::sin(PI * x) / (PI * x)
+    }
+}
+
+#[cfg(test)]
+mod test_filter {
+    use super::*;
+
+    #[test]
+    fn test_support() {
+        for &kind in &FilterKind::ALL {
+            for &radius in &[kind.default_radius(), 0.75, 2.5] {
+                let filter = Filter::with_radius(kind, radius);
+                assert!(filter.evaluate(0.0, 0.0) > 0.0, "{}", kind);
+                assert_eq!(filter.evaluate(radius * 1.001, 0.0), 0.0, "{}", kind);
+                assert_eq!(filter.evaluate(0.1, -radius - 0.1), 0.0, "{}", kind);
+                // symmetric
+                assert_eq!(filter.evaluate(0.3, 0.2), filter.evaluate(-0.3, -0.2));
+                // all but the box fall off continuously to the edge
+                if kind != FilterKind::Box {
+                    assert!(filter.evaluate(radius - 1e-4, 0.0).abs() < 1e-3, "{}", kind);
+                }
+            }
+        }
+        assert_eq!(Filter::new(FilterKind::Box).evaluate(0.49, -0.49), 1.0);
+    }
+
+    #[test]
+    fn test_shapes() {
+        let tent = Filter::with_radius(FilterKind::Tent, 2.0);
+        assert_eq!(tent.evaluate(1.0, 0.0), 0.5);
+        assert_eq!(tent.evaluate(1.0, 1.5), 0.125);
+        // the Mitchell filter's cubic pieces meet and it has negative lobes
+        assert!((mitchell(1.0 - 1e-9) - mitchell(1.0)).abs() < 1e-6);
+        assert!((mitchell(0.0) - 8.0 / 9.0).abs() < 1e-12);
+        let mitchell = Filter::new(FilterKind::Mitchell);
+        assert!(mitchell.evaluate(1.5, 0.0) < 0.0);
+        // Lanczos is zero at every whole pixel but the center
+        let lanczos = Filter::new(FilterKind::Lanczos);
+        assert!(lanczos.evaluate(1.0, 0.0).abs() < 1e-12);
+        assert!(lanczos.evaluate(1.5, 0.0) < 0.0);
+        let gaussian = Filter::new(FilterKind::Gaussian);
+        assert!(gaussian.evaluate(0.5, 0.0) < gaussian.evaluate(0.25, 0.0));
+    }
+
+    #[test]
+    fn test_margin() {
+        let margin = |kind, radius| Filter::with_radius(kind, radius).margin();
+        assert_eq!(margin(FilterKind::Box, 0.5), 0);
+        assert_eq!(margin(FilterKind::Tent, 1.0), 1);
+        assert_eq!(margin(FilterKind::Gaussian, 1.5), 1);
+        assert_eq!(margin(FilterKind::Mitchell, 2.0), 2);
+        assert_eq!(margin(FilterKind::Box, 0.25), 0);
+    }
+
+    #[test]
+    fn test_names() {
+        for &kind in &FilterKind::ALL {
+            assert_eq!(kind.to_string().parse(), Ok(kind));
+        }
+        assert!("sinc"
+            .parse::<FilterKind>()
+            .unwrap_err()
+            .contains("unknown filter"));
+    }
+}
@@ -0,0 +1,27 @@
+//! yart, yet another ray tracer.
+//!
+//! Scenes are built from the shapes in [`types`] and [`mesh`] with the
+//! materials in [`materials`] and the fog, smoke and fire in [`media`], or
+//! loaded with [`scene_file`], and rendered into an in-memory
+//! [`output::Image`] by a [`render::Renderer`]. The
+//! unclamped radiance is kept in a [`film::Film`] and can be written out as
+//! PFM or OpenEXR, or tone mapped for display with [`tonemap`].
+
+pub mod bvh;
+pub mod film;
+pub mod filter;
+pub mod materials;
+pub mod math;
+pub mod media;
+pub mod mesh;
+pub mod microfacet;
+pub mod output;
+pub mod render;
+pub mod sampler;
+pub mod scene_file;
+pub mod textures;
+pub mod tonemap;
+pub mod types;
+pub mod voxels;
+
+mod tiles;
@@ -1,150 +1,118 @@
-use crate::materials::*;
-use crate::math::vec3::*;
-use crate::math::*;
-use crate::types::*;
-use std::sync::mpsc;
+use crate::cli::*;
+use std::io::Write;
 use std::sync::Arc;
-
-pub mod materials;
-pub mod math;
-pub mod types;
-
-mod threads;
+use yart::bvh::*;
+use yart::materials::*;
+use yart::math::vec3::*;
+use yart::math::*;
+use yart::output::*;
+use yart::render::*;
+use yart::scene_file::*;
+use yart::tonemap::*;
+use yart::types::*;
+
+mod cli;
 
 fn main() {
-    let world = random_scene();
-    let world = Arc::new(world); // as Arc<dyn Hit + Send + Sync>;
-                                 // let ref world = make_threadsafe_hittable(Box::new(random_scene()));
-    std::fs::write("21-hello_hello.ppm", draw(world).as_bytes()).unwrap();
-}
-
-fn draw(object_ptr: HittablePtr) -> String {
-    let image_width = 1366;
-    let image_height = (((image_width as Num) * 9.0) / 16.0) as usize;
-    let samples_per_pixel = 100;
-    let max_depth = 50;
-    let thread_count = 4;
-
-    let camera_ptr = {
-        let look_from = Point::new(4, 2, 3);
-        let look_at = Point::new(0, 0, 0);
-        let vup = Vec3::unit_y();
-        let dist_to_focus = 10.0; // (look_from - look_at).magnitude();
-        let aperture = 0.1;
-        let aspect_ratio = image_width as Num / image_height as Num;
-
-        let camera = Camera::new(
-            look_from,
-            look_at,
-            vup,
-            aspect_ratio,
-            90.0,
-            aperture,
-            dist_to_focus,
-        );
-        Arc::new(camera)
+    let usage_error = |message: String| -> ! {
+        eprintln!("error: {}\n\nrun `yart --help` for usage", message);
+        std::process::exit(2);
     };
-
-    let (sender, reciever) = mpsc::channel();
-
-    let pool = crate::threads::ThreadPool::new(thread_count);
-
-    for h in (0..(image_height - 1)).rev() {
-        // reversed: top to bottom
-        let camera_ptr = camera_ptr.clone();
-        let object_ptr = object_ptr.clone();
-        let sender = sender.clone();
-        // a single thread for a single scan line
-        pool.execute(move || {
-            for w in 0..image_width {
-                // right to left
-                let mut pixel = Color::zero();
-                for _ in 0..samples_per_pixel {
-                    let object = object_ptr.clone();
-                    let u = ((w as Num) + random_num()) / (image_width - 1) as Num;
-                    let v = ((h as Num) + random_num()) / (image_height - 1) as Num;
-                    pixel = pixel + send_ray(object, camera_ptr.get_ray(u, v), max_depth);
-                }
-                sender.send((h, w, pixel)).unwrap();
-                // println!("h: {:?} - w: {:?} - u: {:?} - v: {:?}", h, w, u, v);
-            }
-        });
-    }
-    let mut ppm = PPM::new(image_width, image_height);
-    let mut scanlines_remaining = image_height;
-    loop {
-        let (h, w, pixel) = reciever.recv().unwrap();
-        ppm.set_pixel(w, h, pixel);
-        if h < scanlines_remaining {
-            print!("\rScanlines remaining: {}\n", h);
-            scanlines_remaining = h;
+    let invocation = match parse_args(std::env::args().skip(1)) {
+        Ok(Command::Render(invocation)) => invocation,
+        Ok(Command::Help) => {
+            print!("{}", USAGE);
+            return;
         }
-        if h == 0 && w == (image_width - 1) {
-            // if on last pixel
-            break;
-        }
-    }
-    ppm.print(samples_per_pixel)
-}
+        Err(message) => usage_error(message),
+    };
 
-fn send_ray(hittable: HittablePtr, ray: Ray, depth: i32) -> Color {
-    if depth <= 0 {
-        // no more light if at end of depth
-        return Color::zero();
-    }
-    // TODO: check out shaodw acne
-    match hittable.hit(&ray, 0.001, INFINITY) {
-        // if it hits the hittable, get color
-        Some(record) => match record.material.clone().scatter(ray, record) {
-            Some((deflected_ray, attenuation)) => {
-                attenuation * send_ray(hittable, deflected_ray, depth - 1)
+    let (scene, base_options) = match &invocation.scene {
+        Some(path) => match load_scene(path) {
+            Ok(description) => (
+                Some(description.scene),
+                Options::from_scene(&description.settings),
+            ),
+            Err(err) => {
+                eprintln!("error: couldn't load {}: {}", path.display(), err);
+                std::process::exit(1);
             }
-            None => Color::zero(),
         },
-        // else, the background gradient
-        None => {
-            let unit_direction = ray.direction.unit_vector();
-            let t = 0.5 * (unit_direction.y + 1.0);
-
-            (Color::one() * (1.0 - t)) + (Color::new(0.5, 0.7, 1.0) * t)
-            //^ white                     ^ blue
+        None => (None, Options::default()),
+    };
+    let options = match invocation.options(base_options) {
+        Ok(options) => options,
+        Err(message) => usage_error(message),
+    };
+    let scene = scene.unwrap_or_else(|| {
+        // the spheres are placed with the render's seed too
+        let world = Bvh::new(random_scene(&mut Rng::new(options.seed)));
+        Scene {
+            world: Arc::new(world), // as Arc<dyn Hit + Send + Sync>;
+            lights: HittablesList::new(),
+            background: Background::sky(),
+        }
+    });
+
+    let settings = options.render_settings();
+    let camera = options.camera.camera(settings.aspect_ratio());
+    let renderer = Renderer::new(settings).with_progress(|done, total| {
+        print!("\rTiles remaining: {:<8}", total - done);
+        let _ = std::io::stdout().flush();
+    });
+    let (image, stats) = renderer.render_with_stats(Arc::new(scene), camera);
+    println!(
+        "\rRendered in {:.2}s, {:.2}M rays/s",
+        stats.elapsed.as_secs_f64(),
+        stats.rays_per_second() / 1e6
+    );
+    // validated along with the rest of the options
+    let format = options.image_format(&options.output).unwrap();
+    if let Err(err) = image.save_as(&options.output, format, &options.display) {
+        eprintln!(
+            "error: couldn't write {}: {}",
+            options.output.display(),
+            err
+        );
+        std::process::exit(1);
+    }
+    if let Some(path) = &options.heatmap {
+        let heatmap = sample_heatmap(&stats.sample_counts, image.width, image.height);
+        let format = options.image_format(path).unwrap();
+        if let Err(err) = heatmap.save_as(path, format, &DisplayTransform::default()) {
+            eprintln!("error: couldn't write {}: {}", path.display(), err);
+            std::process::exit(1);
         }
     }
 }
 
-fn random_scene() -> HittablesList {
+fn random_scene(rng: &mut Rng) -> HittablesList {
     let mut world = HittablesList::new();
     world.push(Arc::new(Sphere {
         center: Vec3::new(0, -1000.0, 0),
         radius: 1000.0,
-        material: Arc::new(Lambertian {
-            albedo: Color::new(0.5, 0.5, 0.5),
-        }),
+        material: Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
     }));
 
     // let i = 1i32;
     for a in -11..11 {
         for b in -11..11 {
-            let choose_mat = random_num();
-            let center = Point::new(
-                a as Num + random_num() * 0.9,
-                0.2,
-                b as Num + 0.9 * random_num(),
-            );
+            let choose_mat = rng.num();
+            let center = Point::new(a as Num + rng.num() * 0.9, 0.2, b as Num + 0.9 * rng.num());
             if (center - Vec3::new(4, 0.2, 0)).magnitude() > 0.9 {
                 if choose_mat < 0.8 {
                     // diffuse
-                    let albedo: Color = random_vec3() * random_vec3();
+                    let albedo: Color = random_vec3(rng) * random_vec3(rng);
 
                     world.push(Arc::new(Sphere {
                         center,
                         radius: 0.2,
-                        material: Arc::new(Lambertian { albedo }),
+                        material: Arc::new(Lambertian::new(albedo)),
                     }));
                 } else if choose_mat < 0.95 {
                     // metal
-                    let albedo = random_vec3_rng(0.5, 1.0);
-                    let fuzz = random_num_rng(0.0, 0.5);
+                    let albedo = random_vec3_rng(rng, 0.5, 1.0);
+                    let fuzz = rng.num_range(0.0, 0.5);
                     world.push(Arc::new(Sphere {
                         center,
                         radius: 0.2,
@@ -168,9 +136,7 @@ fn random_scene() -> HittablesList {
     world.push(Arc::new(Sphere {
         center: Vec3::new(-4, 1, 0),
         radius: 1.0,
-        material: Arc::new(Lambertian {
-            albedo: Color::new(0.4, 0.2, 0.1),
-        }),
+        material: Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1))),
     }));
     world.push(Arc::new(Sphere {
         center: Vec3::new(4, 1, 0),
@@ -194,62 +160,3 @@ fn random_scene() -> HittablesList {
 
     world
 }
-
-// #[derive(Debug)]
-struct PPM {
-    width: usize,
-    height: usize,
-    pixels: Vec<Vec<Color>>,
-}
-use std::fmt;
-impl fmt::Debug for PPM {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "PPM {{ width: {}, height: {} }}",
-            self.width, self.height
-        )
-    }
-}
-
-impl PPM {
-    fn new(width: usize, height: usize) -> PPM {
-        let mut pixels = Vec::with_capacity(height);
-        for _ in 0..(height - 1) {
-            pixels.push(Vec::with_capacity(width));
-        }
-        PPM {
-            width,
-            height,
-            pixels,
-        }
-    }
-    fn set_pixel(&mut self, _w: usize, h: usize, color: Color) {
-        self.pixels[h].push(color);
-    }
-    fn print(self, samples_per_pixel: usize) -> String {
-        use std::fmt::Write;
-        let mut output = String::new();
-        write!(output, "P3\n{} {}\n255\n", self.width, self.height).unwrap();
-        output.reserve(self.width * self.height * 12);
-        for h in (0..(self.height - 1)).rev() {
-            for pixel in &self.pixels[h] {
-                // let pixel = self.pixels[&(h * w)];
-                let scale = 1.0 / samples_per_pixel as f64;
-                let r = Num::sqrt(pixel.x * scale);
-                let g = Num::sqrt(pixel.y * scale);
-                let b = Num::sqrt(pixel.z * scale);
-                // Write the translated [0,255] value of each color component.
-                write!(
-                    output,
-                    "{} {} {}\n",
-                    (256.0 * math::clamp_num(r, 0.0, 0.999)) as i32,
-                    (256.0 * math::clamp_num(g, 0.0, 0.999)) as i32,
-                    (256.0 * math::clamp_num(b, 0.0, 0.999)) as i32,
-                )
-                .unwrap();
-            }
-        }
-        output
-    }
-}
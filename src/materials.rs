@@ -1,11 +1,65 @@
 use crate::math::vec3::*;
 use crate::math::*;
+use crate::microfacet::*;
+use crate::sampler::*;
+use crate::textures::*;
 use crate::types::*;
 
 pub type MaterialPtr = std::sync::Arc<dyn Material + Send + Sync>;
 
 pub trait Material {
-    fn scatter(&self, ray_in: Ray, record: HitRecord) -> Option<(Ray, Color)>;
+    fn scatter(
+        &self,
+        ray_in: Ray,
+        record: HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color)>;
+    /// Radiance given off at the hit, on top of anything scattered.
+    fn emitted(&self, _ray_in: &Ray, _record: &HitRecord) -> Color {
+        Color::zero()
+    }
+    /// Whether `scatter` only picks from a handful of directions (mirrors,
+    /// glass), in which case `eval` and `pdf` are meaningless and lights
+    /// aren't sampled directly.
+    fn is_specular(&self) -> bool {
+        true
+    }
+    /// BSDF times the cosine term for light leaving along `direction`
+    /// towards `ray_in`'s origin.
+    fn eval(&self, _ray_in: &Ray, _record: &HitRecord, _direction: Vec3) -> Color {
+        Color::zero()
+    }
+    /// Solid angle density with which `scatter` picks `direction`.
+    fn pdf(&self, _ray_in: &Ray, _record: &HitRecord, _direction: Vec3) -> Num {
+        0.0
+    }
+}
+
+/// An emitter that doesn't reflect anything. Only its front face gives off light.
+pub struct DiffuseLight {
+    pub emit: TexturePtr,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> DiffuseLight {
+        DiffuseLight {
+            emit: SolidColor::shared(emit),
+        }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _: Ray, _: HitRecord, _: &mut dyn Sampler) -> Option<(Ray, Color)> {
+        None
+    }
+
+    fn emitted(&self, _: &Ray, record: &HitRecord) -> Color {
+        if record.front_face {
+            self.emit.value(record.u, record.v, record.position)
+        } else {
+            Color::zero()
+        }
+    }
 }
 
 pub struct Dielectric {
@@ -13,7 +67,12 @@ pub struct Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r_in: Ray, record: HitRecord) -> Option<(Ray, Color)> {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        record: HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color)> {
         let etai_over_etat = if record.front_face {
             1.0 / self.refraction_index
         } else {
@@ -29,7 +88,7 @@ impl Material for Dielectric {
             reflect(unit_direction, record.normal)
         } else {
             let reflect_prob = schlick(cos_theta, etai_over_etat);
-            if random_num() < reflect_prob {
+            if sampler.next_1d() < reflect_prob {
                 reflect(unit_direction, record.normal)
             } else {
                 refract(unit_direction, record.normal, etai_over_etat)
@@ -39,6 +98,7 @@ impl Material for Dielectric {
             Ray {
                 origin: record.position,
                 direction: next_direction,
+                time: r_in.time,
             },
             Color::one(),
         ))
@@ -47,7 +107,7 @@ impl Material for Dielectric {
 fn schlick(cosine: Num, ref_idx: Num) -> Num {
     let r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
     let r0 = r0 * r0;
-    return r0 + (1.0 - r0) * Num::powi(1.0 - cosine, 5);
+    r0 + (1.0 - r0) * Num::powi(1.0 - cosine, 5)
 }
 
 fn refract(uv: Vec3, normal: Vec3, etai_over_etat: Num) -> Vec3 {
@@ -58,7 +118,7 @@ fn refract(uv: Vec3, normal: Vec3, etai_over_etat: Num) -> Vec3 {
 }
 
 pub struct Metal {
-    pub albedo: Color,
+    pub albedo: TexturePtr,
     fuzz: Num,
 }
 
@@ -67,6 +127,9 @@ impl Metal {
         self.fuzz
     }
     pub fn new(albedo: Color, fuzz: Num) -> Metal {
+        Metal::textured(SolidColor::shared(albedo), fuzz)
+    }
+    pub fn textured(albedo: TexturePtr, fuzz: Num) -> Metal {
         Metal {
             albedo,
             fuzz: if fuzz < 1.0 { fuzz } else { 1.0 },
@@ -75,15 +138,21 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, r_in: Ray, record: HitRecord) -> Option<(Ray, Color)> {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        record: HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color)> {
         let reflected = reflect(r_in.direction.unit_vector(), record.normal);
         if reflected.dot(record.normal) > 0.0 {
             Some((
                 Ray {
                     origin: record.position,
-                    direction: reflected + (random_in_unit_sphere() * self.fuzz),
+                    direction: reflected + (random_in_unit_sphere(sampler) * self.fuzz),
+                    time: r_in.time,
                 },
-                self.albedo,
+                self.albedo.value(record.u, record.v, record.position),
             ))
         } else {
             None
@@ -96,47 +165,850 @@ fn reflect(vec: Vec3, normal: Vec3) -> Vec3 {
 }
 
 pub struct Lambertian {
-    pub albedo: Color,
+    pub albedo: TexturePtr,
+}
+
+impl Lambertian {
+    pub fn new(albedo: Color) -> Lambertian {
+        Lambertian::textured(SolidColor::shared(albedo))
+    }
+    pub fn textured(albedo: TexturePtr) -> Lambertian {
+        Lambertian { albedo }
+    }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _: Ray, record: HitRecord) -> Option<(Ray, Color)> {
-        let scatter_direction = record.normal + random_unit_vector();
+    fn scatter(
+        &self,
+        r_in: Ray,
+        record: HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color)> {
+        let scatter_direction = record.normal + random_unit_vector(sampler);
+        let albedo = self.albedo.value(record.u, record.v, record.position);
         Some((
             Ray {
                 origin: record.position,
                 direction: scatter_direction,
+                time: r_in.time,
             },
-            self.albedo,
+            albedo,
         ))
     }
+
+    fn is_specular(&self) -> bool {
+        false
+    }
+
+    fn eval(&self, ray_in: &Ray, record: &HitRecord, direction: Vec3) -> Color {
+        self.albedo.value(record.u, record.v, record.position) * self.pdf(ray_in, record, direction)
+    }
+
+    /// cosine weighted, which is what `normal + random_unit_vector()` gives
+    fn pdf(&self, _: &Ray, record: &HitRecord, direction: Vec3) -> Num {
+        let cosine = record.normal.dot(direction.unit_vector());
+        if cosine > 0.0 {
+            cosine / PI
+        } else {
+            0.0
+        }
+    }
 }
 
 // for lambertian diffuse
-fn random_unit_vector() -> Vec3 {
-    let mut rng = random_num_generator_rng();
-    let a = rng(0.0, 2.0 * crate::math::PI);
-    let z = rng(-1.0, 1.0);
-    let r = Num::sqrt(1.0 - z * z);
-    return Vec3::new(r * Num::cos(a), r * Num::sin(a), z);
+pub fn random_unit_vector(sampler: &mut dyn Sampler) -> Vec3 {
+    sample_unit_sphere(sampler.next_2d())
+}
+
+fn random_in_unit_sphere(sampler: &mut dyn Sampler) -> Vec3 {
+    let direction = sampler.next_2d();
+    sample_unit_ball(direction, sampler.next_1d())
+}
+
+/// A rough metal: a microfacet distribution of perfect mirrors, each
+/// reflecting as much as a conductor with the complex index of refraction
+/// `eta + i k` does.
+pub struct RoughConductor {
+    pub eta: Color,
+    pub k: Color,
+    pub microfacet: Microfacet,
+}
+
+impl RoughConductor {
+    pub fn new(eta: Color, k: Color, microfacet: Microfacet) -> RoughConductor {
+        RoughConductor { eta, k, microfacet }
+    }
+
+    pub fn preset(preset: ConductorPreset, microfacet: Microfacet) -> RoughConductor {
+        let (eta, k) = preset.eta_k();
+        RoughConductor::new(eta, k, microfacet)
+    }
+
+    /// The outgoing direction in the shading frame around the normal facing
+    /// the ray, `None` if the ray arrives from below it.
+    fn local_view(ray_in: &Ray, record: &HitRecord) -> Option<(Frame, Vec3)> {
+        let frame = Frame::new(record.normal);
+        let wo = frame.to_local(-ray_in.direction.unit_vector());
+        if wo.z > 0.0 {
+            Some((frame, wo))
+        } else {
+            None
+        }
+    }
+}
+
+impl Material for RoughConductor {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        record: HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color)> {
+        let (frame, wo) = RoughConductor::local_view(&r_in, &record)?;
+        let m = self.microfacet.sample_visible(wo, sampler.next_2d());
+        let wi = reflect_about(wo, m);
+        if wi.z <= 0.0 {
+            return None;
+        }
+        // eval over pdf, where D and most of G cancel out
+        let fresnel = fresnel_conductor(wo.dot(m), self.eta, self.k);
+        let weight = self.microfacet.g(wo, wi) / self.microfacet.g1(wo);
+        Some((
+            Ray {
+                origin: record.position,
+                direction: frame.to_world(wi),
+                time: r_in.time,
+            },
+            fresnel * weight,
+        ))
+    }
+
+    fn is_specular(&self) -> bool {
+        false
+    }
+
+    fn eval(&self, ray_in: &Ray, record: &HitRecord, direction: Vec3) -> Color {
+        let (frame, wo) = match RoughConductor::local_view(ray_in, record) {
+            Some(view) => view,
+            None => return Color::zero(),
+        };
+        let wi = frame.to_local(direction.unit_vector());
+        if wi.z <= 0.0 {
+            return Color::zero();
+        }
+        let m = (wo + wi).unit_vector();
+        let fresnel = fresnel_conductor(wo.dot(m), self.eta, self.k);
+        // D G F / (4 cos_o cos_i), times cos_i
+        fresnel * (self.microfacet.d(m) * self.microfacet.g(wo, wi) / (4.0 * wo.z))
+    }
+
+    /// visible normals, turned into reflected directions
+    fn pdf(&self, ray_in: &Ray, record: &HitRecord, direction: Vec3) -> Num {
+        let (frame, wo) = match RoughConductor::local_view(ray_in, record) {
+            Some(view) => view,
+            None => return 0.0,
+        };
+        let wi = frame.to_local(direction.unit_vector());
+        if wi.z <= 0.0 {
+            return 0.0;
+        }
+        let m = (wo + wi).unit_vector();
+        self.microfacet.visible_pdf(wo, m) / (4.0 * wo.dot(m))
+    }
+}
+
+/// Glass with microfacet reflection and transmission, smooth when it has no
+/// microfacets. Light travelling inside loses `absorption` per unit of
+/// distance, so thicker parts come out more deeply colored.
+///
+/// The radiance scaling by the squared ratio of indices is left out, it
+/// cancels out on the way back out of closed objects.
+pub struct RoughDielectric {
+    pub refraction_index: Num,
+    pub microfacet: Option<Microfacet>,
+    pub absorption: Color,
 }
 
-fn random_in_unit_sphere() -> Vec3 {
-    let mut rng = random_vec3_generator_range();
-    loop {
-        let vec = rng(-1.0, 1.0);
-        if vec.magnitude_squared() < 1.0 {
-            return vec;
+impl RoughDielectric {
+    pub fn new(refraction_index: Num, microfacet: Option<Microfacet>) -> RoughDielectric {
+        RoughDielectric {
+            refraction_index,
+            microfacet,
+            absorption: Color::zero(),
+        }
+    }
+
+    pub fn with_absorption(self, absorption: Color) -> RoughDielectric {
+        RoughDielectric { absorption, ..self }
+    }
+
+    /// Index of refraction across the surface over the one on the ray's side.
+    fn eta(&self, record: &HitRecord) -> Num {
+        if record.front_face {
+            self.refraction_index
+        } else {
+            1.0 / self.refraction_index
+        }
+    }
+
+    /// What's left of the light after the ray's trip to the hit, which only
+    /// loses any from inside.
+    fn transmittance(&self, ray_in: &Ray, record: &HitRecord) -> Color {
+        if record.front_face {
+            return Color::one();
         }
+        let distance = record.t * ray_in.direction.magnitude();
+        let a = self.absorption;
+        Color::new(
+            Num::exp(-a.x * distance),
+            Num::exp(-a.y * distance),
+            Num::exp(-a.z * distance),
+        )
+    }
+
+    /// The microfacet normal that turns `wo` into `wi`, by reflection when
+    /// they're on the same side and by refraction otherwise, with its
+    /// Fresnel reflectance. `None` when no facet facing `wo` could.
+    fn half_vector(wo: Vec3, wi: Vec3, eta: Num) -> Option<(Vec3, Num)> {
+        let reflection = wi.z > 0.0;
+        let m = if reflection { wo + wi } else { wo + wi * eta };
+        if m.magnitude_squared() == 0.0 {
+            return None;
+        }
+        let m = if m.z < 0.0 { -m } else { m }.unit_vector();
+        if wo.dot(m) <= 0.0 || (wi.dot(m) < 0.0) == reflection {
+            return None;
+        }
+        Some((m, fresnel_dielectric(wo.dot(m), eta)))
     }
 }
 
-fn random_in_hemisphere(normal: Vec3) -> Vec3 {
-    let in_unit_sphere = random_in_unit_sphere();
-    if in_unit_sphere.dot(normal) > 0.0 {
-        // In the same hemisphere as the normal
-        in_unit_sphere
-    } else {
-        -in_unit_sphere
+impl Material for RoughDielectric {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        record: HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color)> {
+        let (frame, wo) = RoughConductor::local_view(&r_in, &record)?;
+        let eta = self.eta(&record);
+        let m = match &self.microfacet {
+            Some(microfacet) => microfacet.sample_visible(wo, sampler.next_2d()),
+            None => Vec3::unit_z(),
+        };
+        // picking reflection by the Fresnel term cancels it from the weight
+        let wi = if sampler.next_1d() < fresnel_dielectric(wo.dot(m), eta) {
+            Some(reflect_about(wo, m)).filter(|wi| wi.z > 0.0)
+        } else {
+            refract_through(wo, m, eta).filter(|wi| wi.z < 0.0)
+        }?;
+        let weight = match &self.microfacet {
+            Some(microfacet) => microfacet.g(wo, wi) / microfacet.g1(wo),
+            None => 1.0,
+        };
+        Some((
+            Ray {
+                origin: record.position,
+                direction: frame.to_world(wi),
+                time: r_in.time,
+            },
+            self.transmittance(&r_in, &record) * weight,
+        ))
+    }
+
+    fn is_specular(&self) -> bool {
+        self.microfacet.is_none()
+    }
+
+    fn eval(&self, ray_in: &Ray, record: &HitRecord, direction: Vec3) -> Color {
+        let (microfacet, (frame, wo)) =
+            match (&self.microfacet, RoughConductor::local_view(ray_in, record)) {
+                (Some(microfacet), Some(view)) => (microfacet, view),
+                _ => return Color::zero(),
+            };
+        let eta = self.eta(record);
+        let wi = frame.to_local(direction.unit_vector());
+        let (m, fresnel) = match RoughDielectric::half_vector(wo, wi, eta) {
+            Some(half) => half,
+            None => return Color::zero(),
+        };
+        let dg = microfacet.d(m) * microfacet.g(wo, wi);
+        // BSDF times cos_i, with the Jacobian of refraction when transmitted
+        let value = if wi.z > 0.0 {
+            fresnel * dg / (4.0 * wo.z)
+        } else {
+            let denominator = wi.dot(m) + wo.dot(m) / eta;
+            (1.0 - fresnel) * dg * (wi.dot(m) * wo.dot(m)).abs()
+                / (denominator * denominator * wo.z)
+        };
+        self.transmittance(ray_in, record) * value
+    }
+
+    /// visible normals, reflected or refracted by the Fresnel term
+    fn pdf(&self, ray_in: &Ray, record: &HitRecord, direction: Vec3) -> Num {
+        let (microfacet, (frame, wo)) =
+            match (&self.microfacet, RoughConductor::local_view(ray_in, record)) {
+                (Some(microfacet), Some(view)) => (microfacet, view),
+                _ => return 0.0,
+            };
+        let eta = self.eta(record);
+        let wi = frame.to_local(direction.unit_vector());
+        let (m, fresnel) = match RoughDielectric::half_vector(wo, wi, eta) {
+            Some(half) => half,
+            None => return 0.0,
+        };
+        let visible = microfacet.visible_pdf(wo, m);
+        if wi.z > 0.0 {
+            fresnel * visible / (4.0 * wo.dot(m))
+        } else {
+            let denominator = wi.dot(m) + wo.dot(m) / eta;
+            (1.0 - fresnel) * visible * wi.dot(m).abs() / (denominator * denominator)
+        }
+    }
+}
+
+/// One material for most surfaces, after Burley's principled BSDF: a
+/// retro-reflective diffuse base with sheen, an anisotropic GGX specular
+/// layer that turns metallic, a clearcoat on top, and rough glass through
+/// it. All but the refraction index run from zero to one, and the defaults
+/// are Blender's.
+pub struct Principled {
+    pub base_color: TexturePtr,
+    pub metallic: Num,
+    pub roughness: Num,
+    /// reflectance at normal incidence for dielectrics, 0.5 being 4%
+    pub specular: Num,
+    /// how much dielectric highlights take on the base color
+    pub specular_tint: Num,
+    /// grazing retro-reflection, for cloth
+    pub sheen: Num,
+    pub sheen_tint: Num,
+    pub clearcoat: Num,
+    pub clearcoat_roughness: Num,
+    pub transmission: Num,
+    /// stretches the highlights around the y axis
+    pub anisotropic: Num,
+    pub refraction_index: Num,
+}
+
+impl Default for Principled {
+    fn default() -> Principled {
+        Principled {
+            base_color: SolidColor::shared(Color::new(0.8, 0.8, 0.8)),
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            specular_tint: 0.0,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_roughness: 0.03,
+            transmission: 0.0,
+            anisotropic: 0.0,
+            refraction_index: 1.45,
+        }
+    }
+}
+
+/// Everything about a hit on a `Principled` surface the lobes share.
+struct PrincipledHit {
+    frame: Frame,
+    wo: Vec3,
+    base: Color,
+    /// index of refraction across the surface over the one on the ray's side
+    eta: Num,
+    microfacet: Microfacet,
+    /// chances of sampling the diffuse, opaque specular, clearcoat and glass
+    /// lobes
+    lobes: [Num; 4],
+}
+
+/// The clearcoat's shadowing, fixed at a GGX alpha of 0.25.
+const CLEARCOAT_SHADOWING: Num = 0.5;
+
+fn schlick_weight(cosine: Num) -> Num {
+    clamp_num(1.0 - cosine, 0.0, 1.0).powi(5)
+}
+
+fn mix(a: Color, b: Color, t: Num) -> Color {
+    a * (1.0 - t) + b * t
+}
+
+impl Principled {
+    fn hit(&self, ray_in: &Ray, record: &HitRecord) -> Option<PrincipledHit> {
+        let frame = Frame::with_tangent(record.normal, Vec3::unit_y().cross(record.normal));
+        let wo = frame.to_local(-ray_in.direction.unit_vector());
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let base = self.base_color.value(record.u, record.v, record.position);
+        let eta = if record.front_face {
+            self.refraction_index
+        } else {
+            1.0 / self.refraction_index
+        };
+        let aspect = Num::sqrt(1.0 - 0.9 * self.anisotropic);
+        let alpha = self.roughness * self.roughness;
+        let microfacet = Microfacet::new(Distribution::Ggx, alpha / aspect, alpha * aspect);
+
+        // roughly in proportion to how much each lobe reflects
+        let glass = self.glass();
+        let specular = self.specular_color(base);
+        let specular = specular + (Color::one() - specular) * schlick_weight(wo.z);
+        let mut lobes = [
+            self.diffuse_weight() * (base.luminance() + self.sheen),
+            (1.0 - glass) * specular.luminance().min(1.0),
+            0.25 * self.clearcoat * (0.04 + 0.96 * schlick_weight(wo.z)),
+            glass,
+        ];
+        let total: Num = lobes.iter().sum();
+        if total <= 0.0 {
+            return None;
+        }
+        lobes.iter_mut().for_each(|lobe| *lobe /= total);
+        Some(PrincipledHit {
+            frame,
+            wo,
+            base,
+            eta,
+            microfacet,
+            lobes,
+        })
+    }
+
+    fn diffuse_weight(&self) -> Num {
+        (1.0 - self.metallic) * (1.0 - self.transmission)
+    }
+
+    fn glass(&self) -> Num {
+        (1.0 - self.metallic) * self.transmission
+    }
+
+    /// The base color's hue at unit luminance.
+    fn tint(base: Color) -> Color {
+        let luminance = base.luminance();
+        if luminance > 0.0 {
+            base / luminance
+        } else {
+            Color::one()
+        }
+    }
+
+    /// Specular reflectance at normal incidence of the opaque part.
+    fn specular_color(&self, base: Color) -> Color {
+        let tinted = mix(Color::one(), Principled::tint(base), self.specular_tint);
+        mix(tinted * (0.08 * self.specular), base, self.metallic)
+    }
+
+    fn clearcoat_alpha(&self) -> Num {
+        0.001 + 0.099 * self.clearcoat_roughness
+    }
+
+    /// BSDF times cos_i, over all the lobes.
+    fn value(&self, hit: &PrincipledHit, wi: Vec3) -> Color {
+        let (wo, base, eta) = (hit.wo, hit.base, hit.eta);
+        if wi.z <= 0.0 {
+            let (m, fresnel) = match RoughDielectric::half_vector(wo, wi, eta) {
+                Some(half) if self.glass() > 0.0 => half,
+                _ => return Color::zero(),
+            };
+            let denominator = wi.dot(m) + wo.dot(m) / eta;
+            let transmitted = (1.0 - fresnel)
+                * hit.microfacet.d(m)
+                * hit.microfacet.g(wo, wi)
+                * (wi.dot(m) * wo.dot(m)).abs()
+                / (denominator * denominator * wo.z);
+            let color = Color::new(base.x.sqrt(), base.y.sqrt(), base.z.sqrt());
+            return color * (self.glass() * transmitted);
+        }
+
+        let h = (wo + wi).unit_vector();
+        let cos_d = wi.dot(h);
+        let retro = 0.5 + 2.0 * self.roughness * cos_d * cos_d - 1.0;
+        let diffuse = base / PI
+            * ((1.0 + retro * schlick_weight(wi.z)) * (1.0 + retro * schlick_weight(wo.z)));
+        let sheen = mix(Color::one(), Principled::tint(base), self.sheen_tint)
+            * (self.sheen * schlick_weight(cos_d));
+        let mut value = (diffuse + sheen) * (self.diffuse_weight() * wi.z);
+
+        let specular = self.specular_color(base);
+        let fresnel = mix(
+            specular + (Color::one() - specular) * schlick_weight(cos_d),
+            Color::one() * fresnel_dielectric(cos_d, eta),
+            self.glass(),
+        );
+        let dg = hit.microfacet.d(h) * hit.microfacet.g(wo, wi);
+        value = value + fresnel * (dg / (4.0 * wo.z));
+
+        if self.clearcoat > 0.0 {
+            let shadowing = Microfacet::isotropic(Distribution::Ggx, CLEARCOAT_SHADOWING);
+            let coat = 0.25
+                * self.clearcoat
+                * (0.04 + 0.96 * schlick_weight(cos_d))
+                * gtr1_d(h.z, self.clearcoat_alpha())
+                * shadowing.g1(wo)
+                * shadowing.g1(wi)
+                / (4.0 * wo.z);
+            value = value + Color::one() * coat;
+        }
+        value
+    }
+
+    /// The chance of picking `wi`, over all the lobes.
+    fn density(&self, hit: &PrincipledHit, wi: Vec3) -> Num {
+        let [diffuse, specular, clearcoat, glass] = hit.lobes;
+        let wo = hit.wo;
+        if wi.z <= 0.0 {
+            return match RoughDielectric::half_vector(wo, wi, hit.eta) {
+                Some((m, fresnel)) => {
+                    let denominator = wi.dot(m) + wo.dot(m) / hit.eta;
+                    glass * (1.0 - fresnel) * hit.microfacet.visible_pdf(wo, m) * wi.dot(m).abs()
+                        / (denominator * denominator)
+                }
+                None => 0.0,
+            };
+        }
+        let h = (wo + wi).unit_vector();
+        let mut density = diffuse * wi.z / PI;
+        if wo.dot(h) > 0.0 {
+            // glass reflects as often as the Fresnel term says
+            let reflected = specular + glass * fresnel_dielectric(wo.dot(h), hit.eta);
+            density += reflected * hit.microfacet.visible_pdf(wo, h) / (4.0 * wo.dot(h));
+            density += clearcoat * gtr1_d(h.z, self.clearcoat_alpha()) * h.z / (4.0 * wo.dot(h));
+        }
+        density
+    }
+}
+
+impl Material for Principled {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        record: HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color)> {
+        let hit = self.hit(&r_in, &record)?;
+        let [diffuse, specular, clearcoat, _] = hit.lobes;
+        let (choice, u) = (sampler.next_1d(), sampler.next_2d());
+        let wo = hit.wo;
+        let (wi, transmitted) = if choice < diffuse {
+            let disk = sample_unit_disk(u);
+            let z = Num::sqrt(Num::max(0.0, 1.0 - disk.magnitude_squared()));
+            (Vec3::new(disk.x, disk.y, z), false)
+        } else if choice < diffuse + specular {
+            (
+                reflect_about(wo, hit.microfacet.sample_visible(wo, u)),
+                false,
+            )
+        } else if choice < diffuse + specular + clearcoat {
+            (
+                reflect_about(wo, sample_gtr1(self.clearcoat_alpha(), u)),
+                false,
+            )
+        } else {
+            // reflected or refracted, as the rough dielectric does
+            let m = hit.microfacet.sample_visible(wo, u);
+            if sampler.next_1d() < fresnel_dielectric(wo.dot(m), hit.eta) {
+                (reflect_about(wo, m), false)
+            } else {
+                (refract_through(wo, m, hit.eta)?, true)
+            }
+        };
+        // directions on the wrong side of the surface have nowhere to go
+        if (wi.z <= 0.0) != transmitted || wi.z == 0.0 {
+            return None;
+        }
+        let density = self.density(&hit, wi);
+        if density <= 0.0 {
+            return None;
+        }
+        Some((
+            Ray {
+                origin: record.position,
+                direction: hit.frame.to_world(wi),
+                time: r_in.time,
+            },
+            self.value(&hit, wi) / density,
+        ))
+    }
+
+    fn is_specular(&self) -> bool {
+        false
+    }
+
+    fn eval(&self, ray_in: &Ray, record: &HitRecord, direction: Vec3) -> Color {
+        match self.hit(ray_in, record) {
+            Some(hit) => self.value(&hit, hit.frame.to_local(direction.unit_vector())),
+            None => Color::zero(),
+        }
+    }
+
+    /// each lobe sampled in turn, chosen by how much it reflects
+    fn pdf(&self, ray_in: &Ray, record: &HitRecord, direction: Vec3) -> Num {
+        match self.hit(ray_in, record) {
+            Some(hit) => self.density(&hit, hit.frame.to_local(direction.unit_vector())),
+            None => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_materials {
+    use super::*;
+    use std::sync::Arc;
+
+    /// A hit on the xy plane at the origin by a ray arriving `degrees` from
+    /// its normal.
+    fn hit(material: MaterialPtr, degrees: Num) -> (Ray, HitRecord) {
+        let angle = degrees_to_radians(degrees);
+        let direction = Vec3::new(Num::sin(angle), 0.0, -Num::cos(angle));
+        let ray = Ray {
+            origin: -direction,
+            direction,
+            time: 0.0,
+        };
+        let record = HitRecord::new(Point::zero(), 1.0, &ray, Vec3::unit_z(), material);
+        (ray, record)
+    }
+
+    /// The same hit, from inside a surface whose outward normal is -z, after
+    /// the ray went `distance`.
+    fn hit_inside(material: MaterialPtr, degrees: Num, distance: Num) -> (Ray, HitRecord) {
+        let (ray, _) = hit(material.clone(), degrees);
+        let ray = Ray {
+            origin: ray.direction * -distance,
+            ..ray
+        };
+        let record = HitRecord::new(Point::zero(), distance, &ray, -Vec3::unit_z(), material);
+        (ray, record)
+    }
+
+    /// Integrates `f` over the sphere of directions with the midpoint rule.
+    fn integrate_sphere<F: Fn(Vec3) -> Num>(f: F) -> Num {
+        let (thetas, phis) = (600, 300);
+        let (d_theta, d_phi) = (PI / thetas as Num, 2.0 * PI / phis as Num);
+        let mut sum = 0.0;
+        for i in 0..thetas {
+            let theta = (i as Num + 0.5) * d_theta;
+            for j in 0..phis {
+                let phi = (j as Num + 0.5) * d_phi;
+                let w = Vec3::new(
+                    Num::sin(theta) * Num::cos(phi),
+                    Num::sin(theta) * Num::sin(phi),
+                    Num::cos(theta),
+                );
+                sum += f(w) * Num::sin(theta) * d_theta * d_phi;
+            }
+        }
+        sum
+    }
+
+    /// Checks the scattered weights are `eval` over `pdf`, and returns the
+    /// mean weight and the fraction of samples that weren't absorbed.
+    fn check_sampling(material: &MaterialPtr, ray: &Ray, record: &HitRecord) -> (Color, Num) {
+        let mut sampler = IndependentSampler::new(7);
+        let count = 20_000;
+        let (mut total, mut scattered) = (Color::zero(), 0);
+        for index in 0..count {
+            sampler.start_sample(0, 0, index);
+            let (next, weight) = match material.scatter(*ray, record.clone(), &mut sampler) {
+                Some(scatter) => scatter,
+                None => continue,
+            };
+            scattered += 1;
+            total = total + weight / count as Num;
+            let pdf = material.pdf(ray, record, next.direction);
+            assert!(pdf > 0.0, "sampled a direction with no density");
+            let expected = material.eval(ray, record, next.direction) / pdf;
+            assert!(
+                (expected - weight).magnitude() < 1e-6 * (1.0 + weight.magnitude()),
+                "{} != {}",
+                expected,
+                weight
+            );
+        }
+        (total, scattered as Num / count as Num)
+    }
+
+    #[test]
+    fn test_rough_conductor() {
+        for &distribution in &Distribution::ALL {
+            for &roughness in &[0.2, 0.7] {
+                let microfacet = Microfacet::isotropic(distribution, roughness);
+                let gold: MaterialPtr =
+                    Arc::new(RoughConductor::preset(ConductorPreset::Gold, microfacet));
+                for &degrees in &[0.0, 45.0, 80.0] {
+                    let (ray, record) = hit(gold.clone(), degrees);
+                    let (reflected, kept) = check_sampling(&gold, &ray, &record);
+                    // what isn't lost below the surface is in the density,
+                    // where the lobe's wide enough to integrate
+                    if roughness > 0.5 {
+                        let density = integrate_sphere(|w| gold.pdf(&ray, &record, w));
+                        assert!((density - kept).abs() < 2e-2, "{} != {}", density, kept);
+                    }
+                    // it's yellow, and reflects no more than comes in
+                    assert!(reflected.x > reflected.z && reflected.x <= 1.0);
+                }
+            }
+        }
+        // nearly smooth, it reflects as much as the Fresnel term says
+        let smooth = RoughConductor::preset(
+            ConductorPreset::Silver,
+            Microfacet::isotropic(Distribution::Ggx, 0.01),
+        );
+        let (eta, k) = ConductorPreset::Silver.eta_k();
+        let smooth: MaterialPtr = Arc::new(smooth);
+        let (ray, record) = hit(smooth.clone(), 30.0);
+        let (reflected, _) = check_sampling(&smooth, &ray, &record);
+        let fresnel = fresnel_conductor(Num::cos(degrees_to_radians(30.0)), eta, k);
+        assert!((reflected - fresnel).magnitude() < 1e-3);
+        // nothing comes from below the surface
+        let below = Vec3::new(0.3, 0.2, -1.0);
+        assert_eq!(smooth.eval(&ray, &record, below), Color::zero());
+        assert_eq!(smooth.pdf(&ray, &record, below), 0.0);
+    }
+
+    #[test]
+    fn test_principled() {
+        let red = || SolidColor::shared(Color::new(0.8, 0.2, 0.1));
+        let materials = vec![
+            Principled::default(),
+            Principled {
+                base_color: red(),
+                metallic: 1.0,
+                roughness: 0.8,
+                anisotropic: 0.8,
+                ..Principled::default()
+            },
+            Principled {
+                base_color: red(),
+                roughness: 0.7,
+                sheen: 1.0,
+                clearcoat: 1.0,
+                clearcoat_roughness: 1.0,
+                ..Principled::default()
+            },
+            Principled {
+                roughness: 0.7,
+                metallic: 0.3,
+                transmission: 1.0,
+                ..Principled::default()
+            },
+        ];
+        for material in materials {
+            let material: MaterialPtr = Arc::new(material);
+            for &degrees in &[0.0, 45.0, 80.0] {
+                for &inside in &[false, true] {
+                    let (ray, record) = if inside {
+                        hit_inside(material.clone(), degrees, 1.0)
+                    } else {
+                        hit(material.clone(), degrees)
+                    };
+                    let (reflected, kept) = check_sampling(&material, &ray, &record);
+                    let density = integrate_sphere(|w| material.pdf(&ray, &record, w));
+                    assert!((density - kept).abs() < 2e-2, "{} != {}", density, kept);
+                    // Burley's model isn't quite energy conserving, the sheen
+                    // adds on top of the rest
+                    assert!(reflected.x < 1.2, "{}", reflected);
+                }
+            }
+        }
+
+        // a smooth metal reflects its base color
+        let metal: MaterialPtr = Arc::new(Principled {
+            base_color: red(),
+            metallic: 1.0,
+            roughness: 0.05,
+            ..Principled::default()
+        });
+        let (ray, record) = hit(metal.clone(), 30.0);
+        let (reflected, _) = check_sampling(&metal, &ray, &record);
+        assert!(
+            (reflected - Color::new(0.8, 0.2, 0.1)).magnitude() < 1e-2,
+            "{}",
+            reflected
+        );
+        // and white glass is the rough dielectric
+        for &roughness in &[0.0, 0.6] {
+            let glass: MaterialPtr = Arc::new(Principled {
+                base_color: SolidColor::shared(Color::one()),
+                roughness,
+                transmission: 1.0,
+                ..Principled::default()
+            });
+            let microfacet = Microfacet::isotropic(Distribution::Ggx, roughness);
+            let dielectric: MaterialPtr = Arc::new(RoughDielectric::new(1.45, Some(microfacet)));
+            for &degrees in &[0.0, 45.0, 70.0] {
+                for &inside in &[false, true] {
+                    let (ray, record) = if inside {
+                        hit_inside(glass.clone(), degrees, 1.0)
+                    } else {
+                        hit(glass.clone(), degrees)
+                    };
+                    let (passed, _) = check_sampling(&glass, &ray, &record);
+                    let (expected, _) = check_sampling(&dielectric, &ray, &record);
+                    assert!(
+                        (passed - expected).magnitude() < 3e-2,
+                        "{} {} {}: {} != {}",
+                        roughness,
+                        degrees,
+                        inside,
+                        passed,
+                        expected
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_rough_dielectric() {
+        for &roughness in &[0.3, 0.7] {
+            let microfacet = Microfacet::isotropic(Distribution::Ggx, roughness);
+            let glass: MaterialPtr = Arc::new(RoughDielectric::new(1.5, Some(microfacet)));
+            for &degrees in &[0.0, 30.0, 60.0, 85.0] {
+                for &inside in &[false, true] {
+                    let (ray, record) = if inside {
+                        hit_inside(glass.clone(), degrees, 1.0)
+                    } else {
+                        hit(glass.clone(), degrees)
+                    };
+                    assert_eq!(record.front_face, !inside);
+                    let (passed, kept) = check_sampling(&glass, &ray, &record);
+                    // only shadowing between microfacets loses anything
+                    assert!(passed.x <= kept + 1e-9 && passed.x > 0.75 * kept);
+                    if roughness > 0.5 {
+                        let density = integrate_sphere(|w| glass.pdf(&ray, &record, w));
+                        assert!((density - kept).abs() < 2e-2, "{} != {}", density, kept);
+                    }
+                }
+            }
+        }
+
+        // smooth glass splits by the Fresnel term and absorbs by distance
+        let absorption = Color::new(0.1, 0.5, 2.0);
+        let glass: MaterialPtr =
+            Arc::new(RoughDielectric::new(1.5, None).with_absorption(absorption));
+        assert!(glass.is_specular());
+        let (ray, record) = hit(glass.clone(), 0.0);
+        let mut sampler = IndependentSampler::new(3);
+        let (count, mut reflected) = (20_000, 0);
+        for index in 0..count {
+            sampler.start_sample(0, 0, index);
+            let (next, weight) = glass.scatter(ray, record.clone(), &mut sampler).unwrap();
+            assert_eq!(weight, Color::one());
+            if next.direction.z > 0.0 {
+                reflected += 1;
+            }
+        }
+        assert!((reflected as Num / count as Num - 0.04).abs() < 5e-3);
+        let (ray, record) = hit_inside(glass.clone(), 0.0, 2.0);
+        let (_, weight) = glass.scatter(ray, record, &mut sampler).unwrap();
+        let expected = Color::new(Num::exp(-0.2), Num::exp(-1.0), Num::exp(-4.0));
+        assert!((weight - expected).magnitude() < 1e-12);
+        // and everything past the critical angle stays inside
+        let (ray, record) = hit_inside(glass.clone(), 60.0, 1.0);
+        let (next, _) = glass.scatter(ray, record, &mut sampler).unwrap();
+        assert!(next.direction.z > 0.0);
     }
 }
@@ -1,5 +1,4 @@
-use core::ops::{Add, Div, Mul, Neg, Sub};
-use rand::Rng;
+use core::ops::{Add, Div, Index, Mul, Neg, Sub};
 use std::f64;
 
 pub type Num = f64;
@@ -22,39 +21,87 @@ pub fn clamp_num(value: Num, min: Num, max: Num) -> Num {
 pub fn degrees_to_radians(degrees: Num) -> Num {
     degrees * PI / 180.0
 }
-use rand::rngs::ThreadRng;
 
-pub fn get_rand_generator() -> rand::rngs::ThreadRng {
-    static mut OPTION: Option<ThreadRng> = None;
-    // option
-    unsafe {
-        match OPTION {
-            Some(rng) => rng,
-            None => {
-                let rng = rand::thread_rng();
-                OPTION = Some(rng);
-                rng
-            }
-        }
+/// Multiple importance sampling weight (power heuristic, beta = 2) for a
+/// sample drawn with density `pdf` against an alternative strategy's `other_pdf`.
+pub fn power_heuristic(pdf: Num, other_pdf: Num) -> Num {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
     }
 }
-pub fn random_num_generator() -> impl FnMut() -> Num {
-    let mut rng = rand::thread_rng();
-    move || rng.gen()
-}
 
-pub fn random_num_generator_rng() -> impl FnMut(Num, Num) -> Num {
-    let mut rng = rand::thread_rng();
-    move |min, max| rng.gen_range(min, max)
+/// A PCG32 random number generator (O'Neill, "PCG: A Family of Simple Fast
+/// Space-Efficient Statistically Good Algorithms for Random Number
+/// Generation").
+///
+/// Every source of randomness takes one of these explicitly, so a render is
+/// fully determined by its seed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rng {
+    state: u64,
+    increment: u64,
 }
 
-pub fn random_num() -> Num {
-    get_rand_generator().gen()
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng::with_stream(seed, 0)
+    }
+
+    /// One of 2^63 independent sequences for the same seed.
+    pub fn with_stream(seed: u64, stream: u64) -> Rng {
+        let mut rng = Rng {
+            state: 0,
+            increment: (stream << 1) | 1,
+        };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    /// The generator for sample `sample` of pixel (`x`, `y`), which doesn't
+    /// depend on which thread renders it or in what order.
+    pub fn for_sample(seed: u64, x: usize, y: usize, sample: usize) -> Rng {
+        let stream = mix_bits(mix_bits(mix_bits(x as u64) ^ y as u64) ^ sample as u64);
+        Rng::with_stream(seed, stream)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(self.increment);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+
+    /// Uniform in [0, 1).
+    pub fn num(&mut self) -> Num {
+        self.next_u32() as Num * (1.0 / 4_294_967_296.0)
+    }
+
+    /// Uniform in [min, max).
+    pub fn num_range(&mut self, min: Num, max: Num) -> Num {
+        min + (max - min) * self.num()
+    }
+
+    /// Uniform in [0, count).
+    pub fn index(&mut self, count: usize) -> usize {
+        usize::min((self.num() * count as Num) as usize, count - 1)
+    }
 }
 
-pub fn random_num_rng(min: Num, max: Num) -> Num {
-    get_rand_generator().gen_range(min, max)
+/// The SplitMix64 finalizer, scrambling the bits of `value`.
+pub fn mix_bits(value: u64) -> u64 {
+    let mut value = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^ (value >> 31)
 }
+
 pub mod vec3 {
     use super::*;
 
@@ -146,6 +193,37 @@ pub mod vec3 {
                 z: (self.x * other.y) - (self.y * other.x),
             }
         }
+
+        /// component-wise minimum
+        pub fn min(self, other: Vec3) -> Vec3 {
+            Vec3 {
+                x: Num::min(self.x, other.x),
+                y: Num::min(self.y, other.y),
+                z: Num::min(self.z, other.z),
+            }
+        }
+
+        /// component-wise maximum
+        pub fn max(self, other: Vec3) -> Vec3 {
+            Vec3 {
+                x: Num::max(self.x, other.x),
+                y: Num::max(self.y, other.y),
+                z: Num::max(self.z, other.z),
+            }
+        }
+    }
+
+    impl Index<usize> for Vec3 {
+        type Output = Num;
+
+        fn index(&self, axis: usize) -> &Num {
+            match axis {
+                0 => &self.x,
+                1 => &self.y,
+                2 => &self.z,
+                _ => panic!("Vec3 axis out of range: {}", axis),
+            }
+        }
     }
 
     impl std::fmt::Display for Vec3 {
@@ -223,40 +301,543 @@ pub mod vec3 {
         }
     }
 
-    pub fn random_vec3_generator() -> impl FnMut() -> Vec3 {
-        let mut rng = rand::thread_rng();
-        move || Vec3 {
-            x: rng.gen(),
-            y: rng.gen(),
-            z: rng.gen(),
+    pub fn random_vec3(rng: &mut Rng) -> Vec3 {
+        Vec3 {
+            x: rng.num(),
+            y: rng.num(),
+            z: rng.num(),
         }
     }
 
-    pub fn random_vec3_generator_range() -> impl FnMut(Num, Num) -> Vec3 {
-        let mut rng = rand::thread_rng();
-        move |min, max| Vec3 {
-            x: rng.gen_range(min, max),
-            y: rng.gen_range(min, max),
-            z: rng.gen_range(min, max),
+    pub fn random_vec3_rng(rng: &mut Rng, min: Num, max: Num) -> Vec3 {
+        Vec3 {
+            x: rng.num_range(min, max),
+            y: rng.num_range(min, max),
+            z: rng.num_range(min, max),
         }
     }
 
-    pub fn random_vec3() -> Vec3 {
-        Vec3 {
-            x: get_rand_generator().gen(),
-            y: get_rand_generator().gen(),
-            z: get_rand_generator().gen(),
+    /// Two unit vectors forming an orthonormal basis with the unit vector `w`
+    /// (Duff et al., "Building an Orthonormal Basis, Revisited").
+    pub fn orthonormal_basis(w: Vec3) -> (Vec3, Vec3) {
+        let sign = Num::copysign(1.0, w.z);
+        let a = -1.0 / (sign + w.z);
+        let b = w.x * w.y * a;
+        (
+            Vec3::new(1.0 + sign * w.x * w.x * a, sign * b, -sign * w.x),
+            Vec3::new(b, sign + w.y * w.y * a, -w.y),
+        )
+    }
+}
+
+pub mod transform {
+    use super::vec3::*;
+    use super::*;
+
+    /// A row-major 4x4 matrix, acting on column vectors.
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    pub struct Matrix4 {
+        pub m: [[Num; 4]; 4],
+    }
+
+    impl Matrix4 {
+        pub const IDENTITY: Matrix4 = Matrix4 {
+            m: [
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        };
+
+        pub fn new(m: [[Num; 4]; 4]) -> Matrix4 {
+            Matrix4 { m }
+        }
+
+        pub fn transpose(&self) -> Matrix4 {
+            let mut m = [[0.0; 4]; 4];
+            for (row, values) in m.iter_mut().enumerate() {
+                for (column, value) in values.iter_mut().enumerate() {
+                    *value = self.m[column][row];
+                }
+            }
+            Matrix4 { m }
+        }
+
+        /// Gauss-Jordan elimination with partial pivoting, `None` if the
+        /// matrix is singular.
+        pub fn inverse(&self) -> Option<Matrix4> {
+            let mut a = self.m;
+            let mut inverse = Matrix4::IDENTITY.m;
+            for column in 0..4 {
+                let pivot = (column..4)
+                    .max_by(|&i, &j| a[i][column].abs().total_cmp(&a[j][column].abs()))
+                    .unwrap();
+                if a[pivot][column].abs() < 1e-12 {
+                    return None;
+                }
+                a.swap(column, pivot);
+                inverse.swap(column, pivot);
+                let scale = 1.0 / a[column][column];
+                for k in 0..4 {
+                    a[column][k] *= scale;
+                    inverse[column][k] *= scale;
+                }
+                for row in 0..4 {
+                    if row != column {
+                        let factor = a[row][column];
+                        for k in 0..4 {
+                            a[row][k] -= factor * a[column][k];
+                            inverse[row][k] -= factor * inverse[column][k];
+                        }
+                    }
+                }
+            }
+            Some(Matrix4 { m: inverse })
         }
     }
 
-    pub fn random_vec3_rng(min: Num, max: Num) -> Vec3 {
-        Vec3 {
-            x: get_rand_generator().gen_range(min, max),
-            y: get_rand_generator().gen_range(min, max),
-            z: get_rand_generator().gen_range(min, max),
+    impl Mul for Matrix4 {
+        type Output = Matrix4;
+
+        fn mul(self, other: Matrix4) -> Matrix4 {
+            let mut m = [[0.0; 4]; 4];
+            for (row, values) in m.iter_mut().enumerate() {
+                for (column, value) in values.iter_mut().enumerate() {
+                    *value = (0..4).map(|k| self.m[row][k] * other.m[k][column]).sum();
+                }
+            }
+            Matrix4 { m }
+        }
+    }
+
+    /// An affine transform, kept together with its inverse.
+    #[derive(PartialEq, Debug, Clone, Copy)]
+    pub struct Transform {
+        pub matrix: Matrix4,
+        pub inverse: Matrix4,
+    }
+
+    impl Default for Transform {
+        fn default() -> Transform {
+            Transform::identity()
+        }
+    }
+
+    impl Transform {
+        pub fn identity() -> Transform {
+            Transform {
+                matrix: Matrix4::IDENTITY,
+                inverse: Matrix4::IDENTITY,
+            }
+        }
+
+        /// `None` if the matrix can't be inverted.
+        pub fn from_matrix(matrix: Matrix4) -> Option<Transform> {
+            Some(Transform {
+                matrix,
+                inverse: matrix.inverse()?,
+            })
+        }
+
+        pub fn translate(offset: Vec3) -> Transform {
+            let matrix = |v: Vec3| {
+                Matrix4::new([
+                    [1.0, 0.0, 0.0, v.x],
+                    [0.0, 1.0, 0.0, v.y],
+                    [0.0, 0.0, 1.0, v.z],
+                    [0.0, 0.0, 0.0, 1.0],
+                ])
+            };
+            Transform {
+                matrix: matrix(offset),
+                inverse: matrix(-offset),
+            }
+        }
+
+        /// Scales each axis by the matching component, which can't be zero.
+        pub fn scale(factors: Vec3) -> Transform {
+            assert!(
+                factors.x != 0.0 && factors.y != 0.0 && factors.z != 0.0,
+                "scale factors can't be zero"
+            );
+            let matrix = |v: Vec3| {
+                Matrix4::new([
+                    [v.x, 0.0, 0.0, 0.0],
+                    [0.0, v.y, 0.0, 0.0],
+                    [0.0, 0.0, v.z, 0.0],
+                    [0.0, 0.0, 0.0, 1.0],
+                ])
+            };
+            Transform {
+                matrix: matrix(factors),
+                inverse: matrix(Vec3::new(1.0 / factors.x, 1.0 / factors.y, 1.0 / factors.z)),
+            }
+        }
+
+        /// Rotates counterclockwise by `degrees` when looking down `axis`
+        /// towards the origin.
+        pub fn rotate(axis: Vec3, degrees: Num) -> Transform {
+            let a = axis.unit_vector();
+            let (sin, cos) = Num::sin_cos(degrees_to_radians(degrees));
+            let t = 1.0 - cos;
+            let matrix = Matrix4::new([
+                [
+                    t * a.x * a.x + cos,
+                    t * a.x * a.y - sin * a.z,
+                    t * a.x * a.z + sin * a.y,
+                    0.0,
+                ],
+                [
+                    t * a.x * a.y + sin * a.z,
+                    t * a.y * a.y + cos,
+                    t * a.y * a.z - sin * a.x,
+                    0.0,
+                ],
+                [
+                    t * a.x * a.z - sin * a.y,
+                    t * a.y * a.z + sin * a.x,
+                    t * a.z * a.z + cos,
+                    0.0,
+                ],
+                [0.0, 0.0, 0.0, 1.0],
+            ]);
+            // rotations are orthogonal
+            Transform {
+                matrix,
+                inverse: matrix.transpose(),
+            }
+        }
+
+        pub fn inverse(&self) -> Transform {
+            Transform {
+                matrix: self.inverse,
+                inverse: self.matrix,
+            }
+        }
+
+        /// This transform followed by `next`.
+        pub fn then(&self, next: &Transform) -> Transform {
+            Transform {
+                matrix: next.matrix * self.matrix,
+                inverse: self.inverse * next.inverse,
+            }
+        }
+
+        pub fn point(&self, p: Vec3) -> Vec3 {
+            let m = &self.matrix.m;
+            let x = m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3];
+            let y = m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3];
+            let z = m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3];
+            let w = m[3][0] * p.x + m[3][1] * p.y + m[3][2] * p.z + m[3][3];
+            if w == 1.0 {
+                Vec3::new(x, y, z)
+            } else {
+                Vec3::new(x, y, z) / w
+            }
+        }
+
+        /// How much the transform scales volumes, negative if it mirrors
+        /// them.
+        pub fn determinant(&self) -> Num {
+            let m = &self.matrix.m;
+            m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+                - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+                + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+        }
+
+        /// Transforms a direction, ignoring the translation.
+        pub fn vector(&self, v: Vec3) -> Vec3 {
+            let m = &self.matrix.m;
+            Vec3::new(
+                m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+                m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+                m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+            )
+        }
+
+        /// Transforms a surface normal with the inverse transpose, so it
+        /// stays perpendicular to the transformed surface. The result isn't
+        /// normalized.
+        pub fn normal(&self, n: Vec3) -> Vec3 {
+            let m = &self.inverse.m;
+            Vec3::new(
+                m[0][0] * n.x + m[1][0] * n.y + m[2][0] * n.z,
+                m[0][1] * n.x + m[1][1] * n.y + m[2][1] * n.z,
+                m[0][2] * n.x + m[1][2] * n.y + m[2][2] * n.z,
+            )
+        }
+    }
+
+    /// A transform that changes over time, from `start` at `start_time` to
+    /// `end` at `end_time`, holding still before and after.
+    ///
+    /// Both ends are split into a translation, a rotation and a scale, which
+    /// are interpolated separately so rotating objects don't shear on the way.
+    /// The scales can't be blended between a mirrored end and one that isn't
+    /// without flattening the object to nothing somewhere in between.
+    #[derive(Debug, Clone, Copy)]
+    pub struct MotionTransform {
+        pub start: Transform,
+        pub end: Transform,
+        pub start_time: Num,
+        pub end_time: Num,
+        start_parts: Decomposed,
+        end_parts: Decomposed,
+    }
+
+    impl MotionTransform {
+        /// `None` if only one of the ends is mirrored.
+        pub fn new(
+            start: Transform,
+            end: Transform,
+            start_time: Num,
+            end_time: Num,
+        ) -> Option<Self> {
+            assert!(start_time <= end_time, "motion can't end before it starts");
+            let mut end_parts = Decomposed::new(&end.matrix);
+            let start_parts = Decomposed::new(&start.matrix);
+            // take the shorter way around
+            if start_parts.rotation.dot(&end_parts.rotation) < 0.0 {
+                end_parts.rotation = end_parts.rotation.scaled(-1.0);
+            }
+            let motion = MotionTransform {
+                start,
+                end,
+                start_time,
+                end_time,
+                start_parts,
+                end_parts,
+            };
+            let flips = (start.determinant() < 0.0) != (end.determinant() < 0.0);
+            if motion.is_moving() && flips {
+                return None;
+            }
+            Some(motion)
+        }
+
+        /// A transform that doesn't move.
+        pub fn fixed(transform: Transform) -> Self {
+            MotionTransform::new(transform, transform, 0.0, 0.0)
+                .expect("a transform holding still can't flip")
+        }
+
+        pub fn is_moving(&self) -> bool {
+            self.start != self.end && self.start_time < self.end_time
+        }
+
+        /// The transform at `time`.
+        pub fn at(&self, time: Num) -> Transform {
+            if !self.is_moving() || time <= self.start_time {
+                return self.start;
+            }
+            if time >= self.end_time {
+                return self.end;
+            }
+            let t = (time - self.start_time) / (self.end_time - self.start_time);
+            let (a, b) = (&self.start_parts, &self.end_parts);
+            let translation = a.translation * (1.0 - t) + b.translation * t;
+            let rotation = a.rotation.slerp(&b.rotation, t);
+            let mut scale = [[0.0; 3]; 3];
+            for (row, values) in scale.iter_mut().enumerate() {
+                for (column, value) in values.iter_mut().enumerate() {
+                    *value = a.scale[row][column] * (1.0 - t) + b.scale[row][column] * t;
+                }
+            }
+            // both ends mirror or neither does, so the blend can't flatten
+            let scale =
+                Transform::from_matrix(embed(scale)).expect("the scale can't pass through zero");
+            let rotation = rotation.to_matrix();
+            let rotation = Transform {
+                matrix: rotation,
+                inverse: rotation.transpose(),
+            };
+            scale
+                .then(&rotation)
+                .then(&Transform::translate(translation))
+        }
+
+        /// The largest angle, in radians, the rotation turns through.
+        pub fn rotation_angle(&self) -> Num {
+            let cosine = self.start_parts.rotation.dot(&self.end_parts.rotation);
+            2.0 * Num::acos(clamp_num(cosine.abs(), 0.0, 1.0))
+        }
+    }
+
+    /// A 3x3 matrix in the upper left of an affine transform.
+    fn embed(m: [[Num; 3]; 3]) -> Matrix4 {
+        Matrix4::new([
+            [m[0][0], m[0][1], m[0][2], 0.0],
+            [m[1][0], m[1][1], m[1][2], 0.0],
+            [m[2][0], m[2][1], m[2][2], 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// An affine matrix as translation * rotation * scale, where the scale
+    /// is symmetric and may hold a reflection.
+    #[derive(Debug, Clone, Copy)]
+    struct Decomposed {
+        translation: Vec3,
+        rotation: Quaternion,
+        scale: [[Num; 3]; 3],
+    }
+
+    impl Decomposed {
+        fn new(matrix: &Matrix4) -> Decomposed {
+            let m = &matrix.m;
+            let translation = Vec3::new(m[0][3], m[1][3], m[2][3]);
+            let mut linear = [[0.0; 3]; 3];
+            for (row, values) in linear.iter_mut().enumerate() {
+                values.copy_from_slice(&m[row][..3]);
+            }
+            // a reflection goes into the scale, keeping the rotation proper
+            let sign = if determinant(&linear) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            // polar decomposition: average with the inverse transpose until
+            // only the rotation is left
+            let mut rotation = linear.map(|row| row.map(|value| value * sign));
+            for _ in 0..100 {
+                let inverse = match embed(rotation).inverse() {
+                    Some(inverse) => inverse,
+                    None => break,
+                };
+                let mut next = [[0.0; 3]; 3];
+                let mut change: Num = 0.0;
+                for (row, values) in next.iter_mut().enumerate() {
+                    for (column, value) in values.iter_mut().enumerate() {
+                        *value = 0.5 * (rotation[row][column] + inverse.m[column][row]);
+                        change = change.max((*value - rotation[row][column]).abs());
+                    }
+                }
+                rotation = next;
+                if change < 1e-12 {
+                    break;
+                }
+            }
+            // scale = rotation^T * linear
+            let mut scale = [[0.0; 3]; 3];
+            for (row, values) in scale.iter_mut().enumerate() {
+                for (column, value) in values.iter_mut().enumerate() {
+                    *value = (0..3).map(|k| rotation[k][row] * linear[k][column]).sum();
+                }
+            }
+            Decomposed {
+                translation,
+                rotation: Quaternion::from_matrix(&rotation),
+                scale,
+            }
+        }
+    }
+
+    fn determinant(m: &[[Num; 3]; 3]) -> Num {
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    /// A unit quaternion standing for a rotation.
+    #[derive(Debug, Clone, Copy)]
+    struct Quaternion {
+        w: Num,
+        v: Vec3,
+    }
+
+    impl Quaternion {
+        /// From a proper rotation matrix, after Shoemake.
+        fn from_matrix(m: &[[Num; 3]; 3]) -> Quaternion {
+            let trace = m[0][0] + m[1][1] + m[2][2];
+            if trace > 0.0 {
+                let s = 2.0 * Num::sqrt(trace + 1.0);
+                return Quaternion {
+                    w: 0.25 * s,
+                    v: Vec3::new(
+                        (m[2][1] - m[1][2]) / s,
+                        (m[0][2] - m[2][0]) / s,
+                        (m[1][0] - m[0][1]) / s,
+                    ),
+                };
+            }
+            // the largest diagonal element keeps the square root well away
+            // from zero
+            let i = if m[1][1] > m[0][0] { 1 } else { 0 };
+            let i = if m[2][2] > m[i][i] { 2 } else { i };
+            let (j, k) = ((i + 1) % 3, (i + 2) % 3);
+            let s = 2.0 * Num::sqrt(1.0 + m[i][i] - m[j][j] - m[k][k]);
+            let mut v = [0.0; 3];
+            v[i] = 0.25 * s;
+            v[j] = (m[j][i] + m[i][j]) / s;
+            v[k] = (m[k][i] + m[i][k]) / s;
+            Quaternion {
+                w: (m[k][j] - m[j][k]) / s,
+                v: Vec3::new(v[0], v[1], v[2]),
+            }
+        }
+
+        fn to_matrix(self) -> Matrix4 {
+            let (w, Vec3 { x, y, z }) = (self.w, self.v);
+            Matrix4::new([
+                [
+                    1.0 - 2.0 * (y * y + z * z),
+                    2.0 * (x * y - z * w),
+                    2.0 * (x * z + y * w),
+                    0.0,
+                ],
+                [
+                    2.0 * (x * y + z * w),
+                    1.0 - 2.0 * (x * x + z * z),
+                    2.0 * (y * z - x * w),
+                    0.0,
+                ],
+                [
+                    2.0 * (x * z - y * w),
+                    2.0 * (y * z + x * w),
+                    1.0 - 2.0 * (x * x + y * y),
+                    0.0,
+                ],
+                [0.0, 0.0, 0.0, 1.0],
+            ])
+        }
+
+        fn dot(&self, other: &Quaternion) -> Num {
+            self.w * other.w + self.v.dot(other.v)
+        }
+
+        fn scaled(self, factor: Num) -> Quaternion {
+            Quaternion {
+                w: self.w * factor,
+                v: self.v * factor,
+            }
+        }
+
+        fn add(self, other: Quaternion) -> Quaternion {
+            Quaternion {
+                w: self.w + other.w,
+                v: self.v + other.v,
+            }
+        }
+
+        fn normalized(self) -> Quaternion {
+            self.scaled(1.0 / Num::sqrt(self.dot(&self)))
+        }
+
+        fn slerp(&self, other: &Quaternion, t: Num) -> Quaternion {
+            let cosine = self.dot(other);
+            if cosine > 0.9995 {
+                // nearly parallel, a straight line is as good and stable
+                return self.scaled(1.0 - t).add(other.scaled(t)).normalized();
+            }
+            let theta = Num::acos(clamp_num(cosine, -1.0, 1.0)) * t;
+            let perpendicular = other.add(self.scaled(-cosine)).normalized();
+            self.scaled(Num::cos(theta))
+                .add(perpendicular.scaled(Num::sin(theta)))
         }
     }
 }
+
 #[cfg(test)]
 mod test_vector3 {
     use super::vec3::*;
@@ -289,4 +870,174 @@ mod test_vector3 {
     fn test_cross() {
         assert_eq!(Vec3::unit_x().cross(Vec3::unit_y()), Vec3::unit_z());
     }
+
+    #[test]
+    fn test_orthonormal_basis() {
+        for &w in &[
+            Vec3::unit_z(),
+            -Vec3::unit_z(),
+            Vec3::new(1, 2, 3).unit_vector(),
+        ] {
+            let (u, v) = orthonormal_basis(w);
+            assert!((u.magnitude() - 1.0).abs() < 1e-12);
+            assert!((v.magnitude() - 1.0).abs() < 1e-12);
+            assert!(u.dot(v).abs() < 1e-12);
+            assert!((u.cross(v) - w).magnitude() < 1e-12);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_rng {
+    use super::*;
+
+    #[test]
+    fn test_pcg32_reference() {
+        // from the PCG reference implementation's demo
+        let mut rng = Rng::with_stream(42, 54);
+        let outputs: Vec<u32> = (0..6).map(|_| rng.next_u32()).collect();
+        assert_eq!(
+            outputs,
+            [0xa15c02b7, 0x7b47f409, 0xba1d3330, 0x83d2f293, 0xbfa4784b, 0xcbed606e]
+        );
+    }
+
+    #[test]
+    fn test_streams() {
+        let first = |mut rng: Rng| (0..4).map(|_| rng.num()).collect::<Vec<_>>();
+        assert_eq!(first(Rng::new(7)), first(Rng::new(7)));
+        assert_ne!(first(Rng::new(7)), first(Rng::new(8)));
+        assert_eq!(
+            first(Rng::for_sample(1, 3, 4, 5)),
+            first(Rng::for_sample(1, 3, 4, 5))
+        );
+        assert_ne!(
+            first(Rng::for_sample(1, 3, 4, 5)),
+            first(Rng::for_sample(1, 4, 3, 5))
+        );
+        let mut rng = Rng::new(0);
+        for _ in 0..1000 {
+            let num = rng.num();
+            assert!((0.0..1.0).contains(&num));
+            assert!(rng.index(3) < 3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_transform {
+    use super::transform::*;
+    use super::vec3::*;
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).magnitude() < 1e-9, "{} != {}", a, b);
+    }
+
+    fn matrices_close(a: &Matrix4, b: &Matrix4) -> bool {
+        (0..4).all(|row| (0..4).all(|column| (a.m[row][column] - b.m[row][column]).abs() < 1e-9))
+    }
+
+    #[test]
+    fn test_basic_transforms() {
+        let point = Vec3::new(1, 2, 3);
+        assert_close(
+            Transform::translate(Vec3::new(1, 0, -1)).point(point),
+            Vec3::new(2, 2, 2),
+        );
+        // directions don't move
+        assert_close(
+            Transform::translate(Vec3::new(1, 0, -1)).vector(point),
+            point,
+        );
+        assert_close(
+            Transform::scale(Vec3::new(2, 1, -1)).point(point),
+            Vec3::new(2, 2, -3),
+        );
+        assert_close(
+            Transform::rotate(Vec3::unit_z(), 90.0).point(Vec3::unit_x()),
+            Vec3::unit_y(),
+        );
+        assert_close(
+            Transform::rotate(Vec3::unit_y(), 90.0).point(Vec3::unit_z()),
+            Vec3::unit_x(),
+        );
+    }
+
+    #[test]
+    fn test_compose_and_invert() {
+        let transform = Transform::scale(Vec3::new(2, 3, 4))
+            .then(&Transform::rotate(Vec3::new(1, 1, 0), 30.0))
+            .then(&Transform::translate(Vec3::new(5, -2, 1)));
+        let point = Vec3::new(0.5, -1, 2);
+        let expected = Transform::translate(Vec3::new(5, -2, 1)).point(
+            Transform::rotate(Vec3::new(1, 1, 0), 30.0)
+                .point(Transform::scale(Vec3::new(2, 3, 4)).point(point)),
+        );
+        assert_close(transform.point(point), expected);
+        assert_close(transform.inverse().point(transform.point(point)), point);
+        assert!(matrices_close(
+            &(transform.matrix * transform.inverse),
+            &Matrix4::IDENTITY
+        ));
+        // the stored inverse matches a numerically computed one
+        assert!(matrices_close(
+            &transform.matrix.inverse().unwrap(),
+            &transform.inverse
+        ));
+        assert!((transform.determinant() - 24.0).abs() < 1e-9);
+        assert!((transform.inverse().determinant() - 1.0 / 24.0).abs() < 1e-12);
+        let from_matrix = Transform::from_matrix(transform.matrix).unwrap();
+        assert!(matrices_close(&from_matrix.inverse, &transform.inverse));
+        let mut singular = Matrix4::IDENTITY;
+        singular.m[2][2] = 0.0;
+        assert_eq!(Transform::from_matrix(singular), None);
+    }
+
+    #[test]
+    fn test_normals() {
+        // squashing a 45 degree slope makes it flatter, the normal steeper
+        let squash = Transform::scale(Vec3::new(1, 0.5, 1));
+        let tangent = Vec3::new(1, 1, 0);
+        let normal = Vec3::new(-1, 1, 0);
+        let transformed = squash.normal(normal);
+        assert!(squash.vector(tangent).dot(transformed).abs() < 1e-12);
+        assert!(transformed.y > transformed.x.abs());
+    }
+
+    #[test]
+    fn test_motion() {
+        let start = Transform::scale(Vec3::new(1, 2, 1));
+        let end = Transform::scale(Vec3::new(3, 2, 1))
+            .then(&Transform::rotate(Vec3::unit_z(), 90.0))
+            .then(&Transform::translate(Vec3::new(4, 0, 0)));
+        let motion = MotionTransform::new(start, end, 1.0, 3.0).unwrap();
+        assert!(motion.is_moving());
+        // the ends are reproduced, and held outside the interval
+        let point = Vec3::new(1, 1, 1);
+        assert_close(motion.at(1.0).point(point), start.point(point));
+        assert_close(motion.at(-5.0).point(point), start.point(point));
+        assert_close(motion.at(3.0).point(point), end.point(point));
+        assert_close(motion.at(2.999999999).point(point), end.point(point));
+        // halfway it's scaled halfway and turned halfway, without shearing
+        let halfway = Transform::scale(Vec3::new(2, 2, 1))
+            .then(&Transform::rotate(Vec3::unit_z(), 45.0))
+            .then(&Transform::translate(Vec3::new(2, 0, 0)));
+        assert!(matrices_close(&motion.at(2.0).matrix, &halfway.matrix));
+        assert!(matrices_close(
+            &(motion.at(1.5).matrix * motion.at(1.5).inverse),
+            &Matrix4::IDENTITY
+        ));
+        assert!((motion.rotation_angle() - std::f64::consts::FRAC_PI_2).abs() < 1e-9);
+
+        // reflections survive the decomposition
+        let mirrored = Transform::scale(Vec3::new(-1, 1, 1));
+        let motion = MotionTransform::new(mirrored, mirrored, 0.0, 1.0).unwrap();
+        assert_close(motion.at(0.5).point(point), Vec3::new(-1, 1, 1));
+        // but turning inside out would squash through zero halfway
+        assert!(MotionTransform::new(start, mirrored, 0.0, 1.0).is_none());
+        assert!(MotionTransform::new(mirrored, start, 0.0, 1.0).is_none());
+        // which doesn't matter when there's no time to move in
+        assert!(MotionTransform::new(start, mirrored, 1.0, 1.0).is_some());
+        assert!(!MotionTransform::fixed(start).is_moving());
+    }
 }
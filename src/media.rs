@@ -0,0 +1,665 @@
+//! Participating media: fog, smoke and haze that scatter light throughout
+//! their volume rather than at a surface.
+//!
+//! A medium stops the rays traced through it with [`Hit::sample_hit`] at
+//! the points where they scatter, drawn with the render's sampler, handing
+//! the integrator a [`VolumeScatter`] material there whose phase function
+//! picks where the light goes next. Rays that pass through unscattered
+//! carry on past it, and shadow rays are dimmed by the medium's
+//! transmittance.
+//!
+//! Media whose density varies, like the voxel grids of [`GridMedium`], are
+//! tracked against a majorant: a constant density bounding theirs, whose
+//! collisions are easy to sample and are kept as real ones in proportion to
+//! the actual density there (delta tracking), or weigh the light by the
+//! chance they're not (ratio tracking).
+
+use crate::materials::*;
+use crate::math::vec3::*;
+use crate::math::*;
+use crate::microfacet::Frame;
+use crate::sampler::*;
+use crate::textures::*;
+use crate::types::*;
+use crate::voxels::*;
+use std::sync::Arc;
+
+/// How light scattering in a medium spreads out from the way it was going.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PhaseFunction {
+    /// evenly in every direction
+    Isotropic,
+    /// Henyey and Greenstein's lobe, where the mean cosine `g` from -1 to 1
+    /// leans it backwards or forwards
+    HenyeyGreenstein(Num),
+}
+
+impl PhaseFunction {
+    /// The phase function with mean cosine `g`, isotropic at zero.
+    pub fn with_anisotropy(g: Num) -> PhaseFunction {
+        if g == 0.0 {
+            PhaseFunction::Isotropic
+        } else {
+            PhaseFunction::HenyeyGreenstein(clamp_num(g, -0.99, 0.99))
+        }
+    }
+
+    /// Density of scattering `cos_theta` away from the direction of travel.
+    pub fn value(self, cos_theta: Num) -> Num {
+        match self {
+            PhaseFunction::Isotropic => 1.0 / (4.0 * PI),
+            PhaseFunction::HenyeyGreenstein(g) => {
+                let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+                (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+            }
+        }
+    }
+
+    /// A unit direction scattered from the unit direction of travel
+    /// `direction`, picked in proportion to `value`.
+    pub fn sample(self, direction: Vec3, (u1, u2): (Num, Num)) -> Vec3 {
+        let cos_theta = match self {
+            PhaseFunction::Isotropic => 1.0 - 2.0 * u1,
+            PhaseFunction::HenyeyGreenstein(g) => {
+                let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u1);
+                clamp_num((1.0 + g * g - s * s) / (2.0 * g), -1.0, 1.0)
+            }
+        };
+        let sin_theta = Num::sqrt(1.0 - cos_theta * cos_theta);
+        let phi = 2.0 * PI * u2;
+        Frame::new(direction).to_world(Vec3::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ))
+    }
+}
+
+/// The material at a scattering event in a medium: `albedo` of the light
+/// is scattered, the rest absorbed.
+pub struct VolumeScatter {
+    pub albedo: TexturePtr,
+    pub phase: PhaseFunction,
+}
+
+impl VolumeScatter {
+    pub fn new(albedo: Color, phase: PhaseFunction) -> VolumeScatter {
+        VolumeScatter::textured(SolidColor::shared(albedo), phase)
+    }
+
+    pub fn textured(albedo: TexturePtr, phase: PhaseFunction) -> VolumeScatter {
+        VolumeScatter { albedo, phase }
+    }
+
+    fn cos_theta(ray_in: &Ray, direction: Vec3) -> Num {
+        ray_in.direction.unit_vector().dot(direction.unit_vector())
+    }
+}
+
+impl Material for VolumeScatter {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        record: HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color)> {
+        let direction = self
+            .phase
+            .sample(r_in.direction.unit_vector(), sampler.next_2d());
+        Some((
+            Ray {
+                origin: record.position,
+                direction,
+                time: r_in.time,
+            },
+            self.albedo.value(record.u, record.v, record.position),
+        ))
+    }
+
+    fn is_specular(&self) -> bool {
+        false
+    }
+
+    /// there's no surface, so no cosine either
+    fn eval(&self, ray_in: &Ray, record: &HitRecord, direction: Vec3) -> Color {
+        self.albedo.value(record.u, record.v, record.position)
+            * self
+                .phase
+                .value(VolumeScatter::cos_theta(ray_in, direction))
+    }
+
+    fn pdf(&self, ray_in: &Ray, _: &HitRecord, direction: Vec3) -> Num {
+        self.phase
+            .value(VolumeScatter::cos_theta(ray_in, direction))
+    }
+}
+
+/// Where a medium is.
+enum Extent {
+    /// inside a closed shape, scattering with the shape's material
+    Inside(HittablePtr),
+    /// all around, scattering with the material
+    Everywhere(MaterialPtr),
+}
+
+/// A medium of uniform density, where rays scatter after a distance that's
+/// exponentially distributed with mean `1 / density`.
+pub struct ConstantMedium {
+    extent: Extent,
+    density: Num,
+}
+
+impl ConstantMedium {
+    /// The medium filling `boundary`, which must be convex, scattering with
+    /// the material of its surface.
+    pub fn new(boundary: HittablePtr, density: Num) -> ConstantMedium {
+        ConstantMedium {
+            extent: Extent::Inside(boundary),
+            density,
+        }
+    }
+
+    /// An atmosphere filling the whole scene. Nothing gets through it
+    /// forever, so rays that leave the scene never see the background.
+    pub fn everywhere(density: Num, material: MaterialPtr) -> ConstantMedium {
+        ConstantMedium {
+            extent: Extent::Everywhere(material),
+            density,
+        }
+    }
+
+    pub fn density(&self) -> Num {
+        self.density
+    }
+
+    /// Where the ray is inside the medium within `[t_min, t_max]`, and the
+    /// material it scatters with there.
+    fn span(&self, ray: &Ray, t_min: Num, t_max: Num) -> Option<(Num, Num, MaterialPtr)> {
+        let (entry, exit, material) = match &self.extent {
+            Extent::Inside(boundary) => {
+                let entry = boundary.hit(ray, -INFINITY, INFINITY)?;
+                let exit = boundary.hit(ray, entry.t + 0.0001, INFINITY)?;
+                (entry.t, exit.t, entry.material)
+            }
+            Extent::Everywhere(material) => (-INFINITY, INFINITY, material.clone()),
+        };
+        let (entry, exit) = (entry.max(t_min), exit.min(t_max));
+        if entry >= exit {
+            return None;
+        }
+        Some((entry, exit, material))
+    }
+}
+
+impl Hit for ConstantMedium {
+    /// There's no surface.
+    fn hit(&self, _ray: &Ray, _t_min: Num, _t_max: Num) -> Option<HitRecord> {
+        None
+    }
+
+    fn sample_hit(
+        &self,
+        ray: &Ray,
+        t_min: Num,
+        t_max: Num,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
+        let (entry, exit, material) = self.span(ray, t_min, t_max)?;
+        let speed = ray.direction.magnitude();
+        let distance = -Num::ln(1.0 - sampler.next_1d()) / self.density;
+        if distance > (exit - entry) * speed {
+            return None;
+        }
+        let t = entry + distance / speed;
+        // any normal will do, there's no surface
+        Some(HitRecord::new(ray.at(t), t, ray, -ray.direction, material))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        match &self.extent {
+            Extent::Inside(boundary) => boundary.bounding_box(),
+            Extent::Everywhere(_) => None,
+        }
+    }
+
+    /// Beer-Lambert, exactly.
+    fn transmittance(&self, ray: &Ray, t_min: Num, t_max: Num, _sampler: &mut dyn Sampler) -> Num {
+        match self.span(ray, t_min, t_max) {
+            Some((entry, exit, _)) => {
+                Num::exp(-self.density * (exit - entry) * ray.direction.magnitude())
+            }
+            None => 1.0,
+        }
+    }
+}
+
+/// A medium with the densities of a voxel grid, times `density_scale`, in
+/// the grid's bounds. Grids with temperatures glow like a black body where
+/// light is absorbed, by `emission` times `blackbody`.
+pub struct GridMedium {
+    grid: Arc<VoxelGrid>,
+    density_scale: Num,
+    albedo: Color,
+    emission: Num,
+    material: MaterialPtr,
+}
+
+impl GridMedium {
+    pub fn new(
+        grid: Arc<VoxelGrid>,
+        density_scale: Num,
+        albedo: Color,
+        phase: PhaseFunction,
+    ) -> GridMedium {
+        GridMedium {
+            grid,
+            density_scale,
+            albedo,
+            emission: 0.0,
+            material: Arc::new(VolumeScatter::new(albedo, phase)),
+        }
+    }
+
+    pub fn with_emission(self, emission: Num) -> GridMedium {
+        GridMedium { emission, ..self }
+    }
+
+    fn majorant(&self) -> Num {
+        self.grid.max_density() * self.density_scale
+    }
+
+    /// The material for a collision at `position`, glowing with what the
+    /// absorbed part gives off.
+    fn material_at(&self, position: Point) -> MaterialPtr {
+        let temperature = self.grid.temperature(position);
+        if self.emission <= 0.0 || temperature <= 0.0 {
+            return self.material.clone();
+        }
+        let radiance = (Color::one() - self.albedo) * self.emission * blackbody(temperature);
+        Arc::new(Emitting {
+            radiance,
+            material: self.material.clone(),
+        })
+    }
+}
+
+impl Hit for GridMedium {
+    /// There's no surface.
+    fn hit(&self, _ray: &Ray, _t_min: Num, _t_max: Num) -> Option<HitRecord> {
+        None
+    }
+
+    /// Delta tracking.
+    fn sample_hit(
+        &self,
+        ray: &Ray,
+        t_min: Num,
+        t_max: Num,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
+        let (mut t, exit) = self.grid.bounds().overlap(ray, t_min, t_max)?;
+        let majorant = self.majorant();
+        if majorant <= 0.0 {
+            return None;
+        }
+        let step = majorant * ray.direction.magnitude();
+        loop {
+            t -= Num::ln(1.0 - sampler.next_1d()) / step;
+            if t >= exit {
+                return None;
+            }
+            let position = ray.at(t);
+            if sampler.next_1d() * majorant < self.grid.density(position) * self.density_scale {
+                let material = self.material_at(position);
+                return Some(HitRecord::new(position, t, ray, -ray.direction, material));
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.grid.bounds())
+    }
+
+    /// Ratio tracking.
+    fn transmittance(&self, ray: &Ray, t_min: Num, t_max: Num, sampler: &mut dyn Sampler) -> Num {
+        let (mut t, exit) = match self.grid.bounds().overlap(ray, t_min, t_max) {
+            Some(span) => span,
+            None => return 1.0,
+        };
+        let majorant = self.majorant();
+        if majorant <= 0.0 {
+            return 1.0;
+        }
+        let step = majorant * ray.direction.magnitude();
+        let mut transmittance = 1.0;
+        loop {
+            t -= Num::ln(1.0 - sampler.next_1d()) / step;
+            if t >= exit {
+                return transmittance;
+            }
+            let density = self.grid.density(ray.at(t)) * self.density_scale;
+            transmittance *= 1.0 - density / majorant;
+        }
+    }
+}
+
+/// Another material, glowing with `radiance` as well.
+struct Emitting {
+    radiance: Color,
+    material: MaterialPtr,
+}
+
+impl Material for Emitting {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        record: HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Ray, Color)> {
+        self.material.scatter(r_in, record, sampler)
+    }
+
+    fn emitted(&self, _ray_in: &Ray, _record: &HitRecord) -> Color {
+        self.radiance
+    }
+
+    fn is_specular(&self) -> bool {
+        self.material.is_specular()
+    }
+
+    fn eval(&self, ray_in: &Ray, record: &HitRecord, direction: Vec3) -> Color {
+        self.material.eval(ray_in, record, direction)
+    }
+
+    fn pdf(&self, ray_in: &Ray, record: &HitRecord, direction: Vec3) -> Num {
+        self.material.pdf(ray_in, record, direction)
+    }
+}
+
+/// Temperature of a black body whose glow `blackbody` gives unit luminance,
+/// about that of a wood fire.
+pub const BLACKBODY_REFERENCE: Num = 1500.0;
+
+/// Linear sRGB of the light a black body at `temperature` kelvin gives off,
+/// relative to one at `BLACKBODY_REFERENCE`, so it brightens steeply as it
+/// heats up, from dull red through orange to white.
+pub fn blackbody(temperature: Num) -> Color {
+    if temperature <= 0.0 {
+        return Color::zero();
+    }
+    let [x, y, z] = blackbody_xyz(temperature);
+    let reference = blackbody_xyz(BLACKBODY_REFERENCE)[1];
+    // colors too deep for sRGB are clipped
+    let rgb = Color::new(
+        3.240_454_2 * x - 1.537_138_5 * y - 0.498_531_4 * z,
+        -0.969_266 * x + 1.876_010_8 * y + 0.041_556 * z,
+        0.055_643_4 * x - 0.204_025_9 * y + 1.057_225_2 * z,
+    );
+    rgb.max(Color::zero()) / reference
+}
+
+/// CIE XYZ of Planck's law over the visible wavelengths, leaving out the
+/// constant factors.
+fn blackbody_xyz(temperature: Num) -> [Num; 3] {
+    // hc / k, in nanometer kelvin
+    const SECOND_RADIATION: Num = 1.438_776_9e7;
+    let mut xyz = [0.0; 3];
+    for step in 0..=80 {
+        let wavelength = 380.0 + 5.0 * step as Num;
+        let planck = 1.0
+            / (wavelength.powi(5)
+                * (Num::exp(SECOND_RADIATION / (wavelength * temperature)) - 1.0));
+        for (total, matching) in xyz.iter_mut().zip(&color_matching(wavelength)) {
+            *total += planck * matching;
+        }
+    }
+    xyz
+}
+
+/// The CIE 1931 color matching functions, by Wyman, Sloan and Shirley's
+/// multi-lobe Gaussian fit.
+fn color_matching(wavelength: Num) -> [Num; 3] {
+    let lobe = |mean: Num, below: Num, above: Num| {
+        let spread = if wavelength < mean { below } else { above };
+        let x = (wavelength - mean) / spread;
+        Num::exp(-0.5 * x * x)
+    };
+    [
+        1.056 * lobe(599.8, 37.9, 31.0) + 0.362 * lobe(442.0, 16.0, 26.7)
+            - 0.065 * lobe(501.1, 20.4, 26.2),
+        0.821 * lobe(568.8, 46.9, 40.5) + 0.286 * lobe(530.9, 16.3, 31.1),
+        1.217 * lobe(437.0, 11.8, 36.0) + 0.681 * lobe(459.0, 26.0, 13.8),
+    ]
+}
+
+#[cfg(test)]
+mod test_media {
+    use super::*;
+    use std::sync::Arc;
+
+    fn gray() -> MaterialPtr {
+        Arc::new(VolumeScatter::new(
+            Color::new(0.5, 0.5, 0.5),
+            PhaseFunction::Isotropic,
+        ))
+    }
+
+    #[test]
+    fn test_phase_functions() {
+        let mut sampler = IndependentSampler::new(1);
+        for &g in &[0.0, 0.7, -0.4] {
+            let phase = PhaseFunction::with_anisotropy(g);
+            // it's a density over the sphere
+            let steps = 2000;
+            let total: Num = (0..steps)
+                .map(|i| {
+                    let theta = (i as Num + 0.5) * PI / steps as Num;
+                    phase.value(theta.cos()) * 2.0 * PI * theta.sin() * PI / steps as Num
+                })
+                .sum();
+            assert!((total - 1.0).abs() < 1e-4, "{}: {}", g, total);
+            // whose samples lean forwards by g on average
+            let direction = Vec3::new(1, 2, 2).unit_vector();
+            let count = 20_000;
+            let mean: Num = (0..count)
+                .map(|index| {
+                    sampler.start_sample(0, 0, index);
+                    let scattered = phase.sample(direction, sampler.next_2d());
+                    assert!((scattered.magnitude() - 1.0).abs() < 1e-9);
+                    scattered.dot(direction) / count as Num
+                })
+                .sum();
+            assert!((mean - g).abs() < 1e-2, "{}: {}", g, mean);
+        }
+        assert_eq!(
+            PhaseFunction::with_anisotropy(0.0),
+            PhaseFunction::Isotropic
+        );
+        assert_eq!(
+            PhaseFunction::with_anisotropy(1.0),
+            PhaseFunction::HenyeyGreenstein(0.99)
+        );
+    }
+
+    #[test]
+    fn test_constant_medium() {
+        let cube: HittablePtr = Arc::new(Cube::new(Point::zero(), 2.0, gray()));
+        let medium = ConstantMedium::new(cube, 0.5);
+        // rays through the cube get through as often as the transmittance
+        let count = 20_000;
+        let mut rng = Rng::new(2);
+        let mut sampler = IndependentSampler::new(2);
+        let ray = Ray {
+            origin: Point::new(0.3, -0.2, 5.0),
+            direction: Vec3::new(0, 0, -2),
+            time: 0.0,
+        };
+        // there's no surface to hit, only a medium to scatter in
+        assert!(medium.hit(&ray, 0.001, INFINITY).is_none());
+        let mut through = 0;
+        let mut distances = Vec::new();
+        for _ in 0..count {
+            match medium.sample_hit(&ray, 0.001, INFINITY, &mut sampler) {
+                Some(record) => {
+                    assert!(record.t > 2.0 && record.t < 3.0, "{}", record.t);
+                    assert!(record.front_face);
+                    let albedo = record.material.eval(&ray, &record, Vec3::unit_x());
+                    assert!((albedo.x - 0.5 / (4.0 * PI)).abs() < 1e-12);
+                    distances.push(record.t);
+                }
+                None => through += 1,
+            }
+        }
+        // the same ray scatters all over
+        let (nearest, furthest) = distances
+            .iter()
+            .fold((INFINITY, -INFINITY), |(low, high), &t| {
+                (low.min(t), high.max(t))
+            });
+        assert!(
+            nearest < 2.01 && furthest > 2.99,
+            "{} {}",
+            nearest,
+            furthest
+        );
+        let transmittance = through as Num / count as Num;
+        assert!(
+            (transmittance - Num::exp(-1.0)).abs() < 1e-2,
+            "{}",
+            transmittance
+        );
+
+        // from inside, the medium starts at the ray, and stops at t_max
+        let mut inside = 0;
+        for _ in 0..count {
+            let ray = Ray {
+                origin: Point::new(rng.num_range(-0.1, 0.1), rng.num_range(-0.1, 0.1), 0.0),
+                direction: Vec3::unit_x(),
+                time: rng.num(),
+            };
+            if medium.sample_hit(&ray, 0.0, 0.5, &mut sampler).is_some() {
+                inside += 1;
+            }
+        }
+        let scattered = inside as Num / count as Num;
+        assert!(
+            (scattered - (1.0 - Num::exp(-0.25))).abs() < 1e-2,
+            "{}",
+            scattered
+        );
+        assert_eq!(
+            medium.bounding_box(),
+            Some(Aabb {
+                min: Point::new(-1, -1, -1),
+                max: Point::new(1, 1, 1)
+            })
+        );
+
+        // an atmosphere scatters everything eventually, on average after the
+        // mean free path
+        let atmosphere = ConstantMedium::everywhere(0.1, gray());
+        assert!(atmosphere.bounding_box().is_none());
+        let mut total = 0.0;
+        for _ in 0..count {
+            let ray = Ray {
+                origin: Point::new(rng.num(), rng.num(), rng.num()),
+                direction: Vec3::unit_y(),
+                time: 0.0,
+            };
+            let record = atmosphere.sample_hit(&ray, 0.0, INFINITY, &mut sampler);
+            total += record.unwrap().t / count as Num;
+        }
+        assert!((total - 10.0).abs() < 0.3, "{}", total);
+        // while shadow rays get the transmittance exactly
+        let ray = Ray {
+            origin: Point::new(0, 0, 5),
+            direction: Vec3::new(0, 0, -2),
+            time: 0.0,
+        };
+        let mut transmittance =
+            |t_min, t_max| medium.transmittance(&ray, t_min, t_max, &mut sampler);
+        assert!((transmittance(0.0, INFINITY) - Num::exp(-1.0)).abs() < 1e-12);
+        assert!((transmittance(2.5, 2.75) - Num::exp(-0.25)).abs() < 1e-12);
+        assert_eq!(transmittance(0.0, 1.0), 1.0);
+    }
+
+    #[test]
+    fn test_grid_medium() {
+        // density ramping from 0 to 2 along x, adding up to 1 across it, and
+        // hotter along y
+        let bounds = Aabb::new(Point::zero(), Point::new(1, 1, 1));
+        let density = vec![0.0, 2.0, 0.0, 2.0, 0.0, 2.0, 0.0, 2.0];
+        let temperature = vec![
+            1000.0, 1000.0, 3000.0, 3000.0, 1000.0, 1000.0, 3000.0, 3000.0,
+        ];
+        let grid = VoxelGrid::new([2, 2, 2], bounds, density, Some(temperature)).unwrap();
+        let albedo = Color::new(0.5, 0.5, 0.5);
+        let medium = GridMedium::new(Arc::new(grid), 0.5, albedo, PhaseFunction::Isotropic)
+            .with_emission(2.0);
+        assert_eq!(medium.bounding_box(), Some(bounds));
+
+        let mut rng = Rng::new(3);
+        let mut sampler = IndependentSampler::new(3);
+        let count = 50_000;
+        let (mut through, mut transmittance) = (0, 0.0);
+        for _ in 0..count {
+            let ray = Ray {
+                origin: Point::new(-1.0, rng.num(), rng.num()),
+                direction: Vec3::new(3, 0, 0),
+                time: 0.0,
+            };
+            match medium.sample_hit(&ray, 0.001, INFINITY, &mut sampler) {
+                // collisions only where there's something to collide with
+                Some(record) => assert!(record.position.x > 0.25 && record.position.x < 1.0),
+                None => through += 1,
+            }
+            transmittance +=
+                medium.transmittance(&ray, 0.001, INFINITY, &mut sampler) / count as Num;
+            // which is all there is beyond the grid
+            assert_eq!(medium.transmittance(&ray, 0.7, 2.0, &mut sampler), 1.0);
+        }
+        let expected = Num::exp(-0.5);
+        let through = through as Num / count as Num;
+        assert!((through - expected).abs() < 1e-2, "{}", through);
+        assert!((transmittance - expected).abs() < 1e-2, "{}", transmittance);
+
+        // the absorbed half glows with the temperature
+        let ray = Ray {
+            origin: Point::new(0.9, 0.75, -1.0),
+            direction: Vec3::unit_z(),
+            time: 0.0,
+        };
+        assert!(medium.hit(&ray, 0.001, INFINITY).is_none());
+        let record = (0..1000)
+            .find_map(|_| medium.sample_hit(&ray, 0.001, INFINITY, &mut sampler))
+            .unwrap();
+        let emitted = record.material.emitted(&ray, &record);
+        assert!(
+            (emitted - blackbody(3000.0) * 1.0).magnitude() < 1e-9,
+            "{}",
+            emitted
+        );
+    }
+
+    #[test]
+    fn test_blackbody() {
+        assert_eq!(blackbody(0.0), Color::zero());
+        assert!((blackbody(BLACKBODY_REFERENCE).luminance() - 1.0).abs() < 0.05);
+        // hotter is brighter, and bluer
+        let temperatures = [800.0, 1500.0, 3000.0, 6500.0, 12000.0];
+        for pair in temperatures.windows(2) {
+            let (cool, hot) = (blackbody(pair[0]), blackbody(pair[1]));
+            assert!(hot.luminance() > cool.luminance());
+            assert!(hot.z / hot.x >= cool.z / cool.x);
+        }
+        let fire = blackbody(1500.0);
+        assert!(fire.x > fire.y && fire.y > fire.z);
+        // around the temperature of daylight it's nearly white
+        let daylight = blackbody(6500.0);
+        let (low, high) = (
+            daylight.x.min(daylight.y).min(daylight.z),
+            daylight.x.max(daylight.z),
+        );
+        assert!(high / low < 1.2, "{}", daylight);
+    }
+}
@@ -0,0 +1,410 @@
+use crate::bvh::*;
+use crate::materials::*;
+use crate::math::vec3::*;
+use crate::math::*;
+use crate::types::*;
+use std::fmt;
+use std::path::Path;
+
+/// Indices of a single face corner into a mesh's vertex attribute arrays.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeshVertex {
+    pub position: usize,
+    pub tex_coord: Option<usize>,
+    pub normal: Option<usize>,
+}
+
+pub type MeshFace = [MeshVertex; 3];
+
+/// A triangle mesh whose faces share their vertex attributes.
+///
+/// The faces are indexed by a BVH built on construction, so the mesh is
+/// immutable afterwards.
+pub struct Mesh {
+    positions: Vec<Point>,
+    normals: Vec<Vec3>,
+    tex_coords: Vec<(Num, Num)>,
+    faces: Vec<MeshFace>,
+    material: MaterialPtr,
+    tree: BvhTree,
+}
+
+impl Mesh {
+    /// # Panics
+    ///
+    /// Panics if a face refers to a missing vertex attribute.
+    pub fn new(
+        positions: Vec<Point>,
+        normals: Vec<Vec3>,
+        tex_coords: Vec<(Num, Num)>,
+        faces: Vec<MeshFace>,
+        material: MaterialPtr,
+    ) -> Mesh {
+        let bounds: Vec<Aabb> = faces
+            .iter()
+            .map(|face| {
+                let corners: Vec<Point> = face.iter().map(|v| positions[v.position]).collect();
+                Aabb::from_points(&corners)
+            })
+            .collect();
+        Mesh {
+            positions,
+            normals,
+            tex_coords,
+            faces,
+            material,
+            tree: BvhTree::new(&bounds),
+        }
+    }
+
+    pub fn positions(&self) -> &[Point] {
+        &self.positions
+    }
+
+    pub fn normals(&self) -> &[Vec3] {
+        &self.normals
+    }
+
+    pub fn tex_coords(&self) -> &[(Num, Num)] {
+        &self.tex_coords
+    }
+
+    pub fn faces(&self) -> &[MeshFace] {
+        &self.faces
+    }
+
+    fn face_points(&self, face: &MeshFace) -> (Point, Point, Point) {
+        (
+            self.positions[face[0].position],
+            self.positions[face[1].position],
+            self.positions[face[2].position],
+        )
+    }
+
+    /// Vertex normals of the face, only if every corner has one.
+    fn face_normals(&self, face: &MeshFace) -> Option<[Vec3; 3]> {
+        Some([
+            self.normals[face[0].normal?],
+            self.normals[face[1].normal?],
+            self.normals[face[2].normal?],
+        ])
+    }
+
+    /// Texture coordinates of the face, only if every corner has one.
+    fn face_tex_coords(&self, face: &MeshFace) -> Option<[(Num, Num); 3]> {
+        Some([
+            self.tex_coords[face[0].tex_coord?],
+            self.tex_coords[face[1].tex_coord?],
+            self.tex_coords[face[2].tex_coord?],
+        ])
+    }
+
+    fn hit_face(&self, face: &MeshFace, ray: &Ray, t_min: Num, t_max: Num) -> Option<HitRecord> {
+        let (p1, p2, p3) = self.face_points(face);
+        let (t, b1, b2) = intersect_triangle(ray, p1, p2, p3, t_min, t_max)?;
+        let geometric_normal = (p2 - p1).cross(p3 - p1).unit_vector();
+        let record = HitRecord::new(ray.at(t), t, ray, geometric_normal, self.material.clone())
+            .with_barycentric(b1, b2, self.face_normals(face));
+        match self.face_tex_coords(face) {
+            Some([t1, t2, t3]) => {
+                let b0 = 1.0 - b1 - b2;
+                let u = t1.0 * b0 + t2.0 * b1 + t3.0 * b2;
+                let v = t1.1 * b0 + t2.1 * b1 + t3.1 * b2;
+                Some(record.with_uv(u, v))
+            }
+            None => Some(record),
+        }
+    }
+}
+
+impl Hit for Mesh {
+    fn hit(&self, ray: &Ray, t_min: Num, t_max: Num) -> Option<HitRecord> {
+        self.tree.hit(ray, t_min, t_max, |face, t_min, t_max| {
+            self.hit_face(&self.faces[face], ray, t_min, t_max)
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.tree.bounding_box()
+    }
+}
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(std::io::Error),
+    /// a malformed statement, with its 1-based line number
+    Parse {
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io(err) => write!(f, "couldn't read obj file: {}", err),
+            ObjError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+impl From<std::io::Error> for ObjError {
+    fn from(err: std::io::Error) -> ObjError {
+        ObjError::Io(err)
+    }
+}
+
+/// Loads a Wavefront OBJ file as a single mesh using the given material.
+pub fn load_obj<P: AsRef<Path>>(path: P, material: MaterialPtr) -> Result<Mesh, ObjError> {
+    let source = std::fs::read_to_string(path)?;
+    parse_obj(&source, material)
+}
+
+/// Parses the geometry statements (`v`, `vt`, `vn` and `f`) of an OBJ file.
+///
+/// Quads and larger polygons are fan-triangulated, so they are expected to be
+/// convex. Grouping, smoothing and material statements are ignored.
+pub fn parse_obj(source: &str, material: MaterialPtr) -> Result<Mesh, ObjError> {
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut tex_coords = Vec::new();
+    let mut faces = Vec::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let error = |message: String| ObjError::Parse {
+            line: line_number,
+            message,
+        };
+        // strip comments
+        let line = match line.find('#') {
+            Some(start) => &line[..start],
+            None => line,
+        };
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let arguments: Vec<&str> = tokens.collect();
+        match keyword {
+            "v" => {
+                let coords = parse_numbers(&arguments, 3, 4).map_err(error)?;
+                positions.push(Point::new(coords[0], coords[1], coords[2]));
+            }
+            "vn" => {
+                let coords = parse_numbers(&arguments, 3, 3).map_err(error)?;
+                let normal = Vec3::new(coords[0], coords[1], coords[2]);
+                if normal.magnitude_squared() == 0.0 {
+                    return Err(error("a normal can't be zero".to_string()));
+                }
+                normals.push(normal.unit_vector());
+            }
+            "vt" => {
+                let coords = parse_numbers(&arguments, 1, 3).map_err(error)?;
+                tex_coords.push((coords[0], coords.get(1).copied().unwrap_or(0.0)));
+            }
+            "f" => {
+                if arguments.len() < 3 {
+                    return Err(error(format!(
+                        "a face needs at least 3 vertices, found {}",
+                        arguments.len()
+                    )));
+                }
+                let vertices = arguments
+                    .iter()
+                    .map(|vertex| {
+                        parse_face_vertex(vertex, positions.len(), tex_coords.len(), normals.len())
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(error)?;
+                for i in 1..(vertices.len() - 1) {
+                    faces.push([vertices[0], vertices[i], vertices[i + 1]]);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(Mesh::new(positions, normals, tex_coords, faces, material))
+}
+
+fn parse_numbers(arguments: &[&str], min: usize, max: usize) -> Result<Vec<Num>, String> {
+    if arguments.len() < min || arguments.len() > max {
+        return Err(format!(
+            "expected {} to {} numbers, found {}",
+            min,
+            max,
+            arguments.len()
+        ));
+    }
+    arguments
+        .iter()
+        .map(|argument| {
+            // `parse` also takes "nan" and "inf", which have no place in a mesh
+            match argument.parse::<Num>() {
+                Ok(number) if number.is_finite() => Ok(number),
+                _ => Err(format!("invalid number `{}`", argument)),
+            }
+        })
+        .collect()
+}
+
+/// Parses a `v`, `v/vt`, `v//vn` or `v/vt/vn` face corner.
+fn parse_face_vertex(
+    vertex: &str,
+    position_count: usize,
+    tex_coord_count: usize,
+    normal_count: usize,
+) -> Result<MeshVertex, String> {
+    let mut parts = vertex.split('/');
+    let position = parts.next().unwrap_or("");
+    let tex_coord = parts.next().filter(|part| !part.is_empty());
+    let normal = parts.next().filter(|part| !part.is_empty());
+    if parts.next().is_some() {
+        return Err(format!("invalid face vertex `{}`", vertex));
+    }
+    Ok(MeshVertex {
+        position: resolve_index(position, position_count, "vertex")?,
+        tex_coord: match tex_coord {
+            Some(index) => Some(resolve_index(index, tex_coord_count, "texture coordinate")?),
+            None => None,
+        },
+        normal: match normal {
+            Some(index) => Some(resolve_index(index, normal_count, "normal")?),
+            None => None,
+        },
+    })
+}
+
+/// Turns a 1-based (or negative, relative to the end) OBJ index into a
+/// 0-based one.
+fn resolve_index(index: &str, count: usize, kind: &str) -> Result<usize, String> {
+    let value: i64 = index
+        .parse()
+        .map_err(|_| format!("invalid {} index `{}`", kind, index))?;
+    let resolved = if value < 0 {
+        count as i64 + value
+    } else {
+        value - 1
+    };
+    if value == 0 || resolved < 0 || resolved >= count as i64 {
+        Err(format!(
+            "{} index {} is out of range ({} defined so far)",
+            kind, value, count
+        ))
+    } else {
+        Ok(resolved as usize)
+    }
+}
+
+#[cfg(test)]
+mod test_obj {
+    use super::*;
+    use std::sync::Arc;
+
+    fn material() -> MaterialPtr {
+        Arc::new(Lambertian::new(Color::one()))
+    }
+
+    const QUAD: &str = "
+# a unit quad on the xy plane
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+f 1/1/1 2/2/1 3/3/1 4/4/1
+";
+
+    #[test]
+    fn test_quad_is_triangulated() {
+        let mesh = parse_obj(QUAD, material()).unwrap();
+        assert_eq!(mesh.positions().len(), 4);
+        assert_eq!(mesh.tex_coords().len(), 4);
+        assert_eq!(mesh.normals().len(), 1);
+        assert_eq!(mesh.faces().len(), 2);
+        let corners: Vec<usize> = mesh.faces()[1].iter().map(|v| v.position).collect();
+        assert_eq!(corners, vec![0, 2, 3]);
+        assert_eq!(mesh.faces()[0][1].tex_coord, Some(1));
+        assert_eq!(mesh.faces()[0][1].normal, Some(0));
+    }
+
+    #[test]
+    fn test_vertex_formats_and_relative_indices() {
+        let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nf -3//1 -2//1 -1//1\nf 1 2 3\n";
+        let mesh = parse_obj(source, material()).unwrap();
+        assert_eq!(
+            mesh.faces()[0],
+            mesh.faces()[1].map(|v| MeshVertex {
+                normal: Some(0),
+                ..v
+            })
+        );
+        assert_eq!(mesh.faces()[1][2].tex_coord, None);
+    }
+
+    #[test]
+    fn test_errors_report_line_numbers() {
+        let bad_number = parse_obj("v 0 0 0\nv 1 x 0\n", material());
+        match bad_number {
+            Err(ObjError::Parse { line, .. }) => assert_eq!(line, 2),
+            _ => panic!("expected a parse error"),
+        }
+        for source in &[
+            "v 0 0 0\nv nan 0 0\n",
+            "v 0 0 0\nvn 0 inf 0\n",
+            "v 0 0 0\nvt 0 -infinity\n",
+        ] {
+            match parse_obj(source, material()) {
+                Err(ObjError::Parse { line, message }) => {
+                    assert_eq!(line, 2);
+                    assert!(message.contains("invalid number"), "{}", message);
+                }
+                _ => panic!("expected a parse error for {:?}", source),
+            }
+        }
+        let bad_index = parse_obj("v 0 0 0\nv 1 0 0\n\nf 1 2 3\n", material());
+        match bad_index {
+            Err(ObjError::Parse { line, message }) => {
+                assert_eq!(line, 4);
+                assert!(message.contains("out of range"));
+            }
+            _ => panic!("expected a parse error"),
+        }
+        let degenerate = parse_obj("v 0 0 0\nv 1 0 0\nf 1 2\n", material());
+        assert!(degenerate.is_err());
+        let zero_normal = parse_obj("v 0 0 0\nvn 0 0 1\nvn 0 0 0\n", material());
+        match zero_normal {
+            Err(ObjError::Parse { line, message }) => {
+                assert_eq!(line, 3);
+                assert!(message.contains("normal"));
+            }
+            _ => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn test_mesh_hit() {
+        let mesh = parse_obj(QUAD, material()).unwrap();
+        let ray = Ray {
+            origin: Point::new(0.75, 0.25, 2),
+            direction: -Vec3::unit_z(),
+            time: 0.0,
+        };
+        let record = mesh.hit(&ray, 0.001, INFINITY).unwrap();
+        assert_eq!(record.t, 2.0);
+        assert_eq!(record.normal, Vec3::unit_z());
+        let miss = Ray {
+            origin: Point::new(1.5, 0.5, 2),
+            direction: -Vec3::unit_z(),
+            time: 0.0,
+        };
+        assert!(mesh.hit(&miss, 0.001, INFINITY).is_none());
+    }
+}
@@ -0,0 +1,709 @@
+//! Microfacet distributions and Fresnel terms for rough surfaces.
+//!
+//! Everything works in a local shading frame with the surface normal along
+//! +z; [`Frame`] converts to and from world space.
+
+use crate::math::vec3::*;
+use crate::math::*;
+use crate::types::*;
+use std::fmt;
+use std::str::FromStr;
+
+/// An orthonormal basis around a shading normal.
+#[derive(Debug, Clone, Copy)]
+pub struct Frame {
+    pub s: Vec3,
+    pub t: Vec3,
+    pub n: Vec3,
+}
+
+impl Frame {
+    /// A frame around the unit vector `n`.
+    pub fn new(n: Vec3) -> Frame {
+        let (s, t) = orthonormal_basis(n);
+        Frame { s, t, n }
+    }
+
+    /// A frame around the unit vector `n` whose first axis is as close to
+    /// `tangent` as it can be, or any frame when they're parallel.
+    pub fn with_tangent(n: Vec3, tangent: Vec3) -> Frame {
+        let s = tangent - n * tangent.dot(n);
+        if s.magnitude_squared() < 1e-12 {
+            return Frame::new(n);
+        }
+        let s = s.unit_vector();
+        Frame {
+            s,
+            t: n.cross(s),
+            n,
+        }
+    }
+
+    pub fn to_local(&self, v: Vec3) -> Vec3 {
+        Vec3::new(v.dot(self.s), v.dot(self.t), v.dot(self.n))
+    }
+
+    pub fn to_world(&self, v: Vec3) -> Vec3 {
+        self.s * v.x + self.t * v.y + self.n * v.z
+    }
+}
+
+/// Mirrors `v` about `normal`, keeping it on the same side.
+pub fn reflect_about(v: Vec3, normal: Vec3) -> Vec3 {
+    normal * (2.0 * v.dot(normal)) - v
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Distribution {
+    /// Trowbridge-Reitz, with long tails that keep highlights glowing
+    Ggx,
+    /// a Gaussian distribution of slopes, with tighter highlights
+    Beckmann,
+}
+
+impl Distribution {
+    pub const ALL: [Distribution; 2] = [Distribution::Ggx, Distribution::Beckmann];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Distribution::Ggx => "ggx",
+            Distribution::Beckmann => "beckmann",
+        }
+    }
+}
+
+impl fmt::Display for Distribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Distribution {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Distribution, String> {
+        Distribution::ALL
+            .iter()
+            .copied()
+            .find(|distribution| distribution.name() == name)
+            .ok_or_else(|| format!("unknown distribution `{}`, expected ggx or beckmann", name))
+    }
+}
+
+/// below this the distributions get too peaked to evaluate reliably
+const MIN_ALPHA: Num = 1e-3;
+
+/// A distribution of microfacet normals, stretched by `alpha_x` and `alpha_y`
+/// along the frame's s and t axes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Microfacet {
+    pub distribution: Distribution,
+    pub alpha_x: Num,
+    pub alpha_y: Num,
+}
+
+impl Microfacet {
+    pub fn new(distribution: Distribution, alpha_x: Num, alpha_y: Num) -> Microfacet {
+        Microfacet {
+            distribution,
+            alpha_x: alpha_x.max(MIN_ALPHA),
+            alpha_y: alpha_y.max(MIN_ALPHA),
+        }
+    }
+
+    /// The same in every direction, with the perceptually linear roughness
+    /// in [0, 1] squared into the distribution's width.
+    pub fn isotropic(distribution: Distribution, roughness: Num) -> Microfacet {
+        let alpha = roughness * roughness;
+        Microfacet::new(distribution, alpha, alpha)
+    }
+
+    /// Density of microfacet normals per unit projected area.
+    pub fn d(&self, m: Vec3) -> Num {
+        let cos_theta = m.z;
+        if cos_theta <= 0.0 {
+            return 0.0;
+        }
+        let cos2 = cos_theta * cos_theta;
+        // tan^2 times (cos^2 phi / alpha_x^2 + sin^2 phi / alpha_y^2)
+        let stretched = (m.x * m.x / (self.alpha_x * self.alpha_x)
+            + m.y * m.y / (self.alpha_y * self.alpha_y))
+            / cos2;
+        let cos4 = cos2 * cos2;
+        match self.distribution {
+            Distribution::Ggx => {
+                let e = 1.0 + stretched;
+                1.0 / (PI * self.alpha_x * self.alpha_y * cos4 * e * e)
+            }
+            Distribution::Beckmann => {
+                Num::exp(-stretched) / (PI * self.alpha_x * self.alpha_y * cos4)
+            }
+        }
+    }
+
+    /// Smith's auxiliary function: the masked area of microfacets facing
+    /// `w` over the area of those facing it and visible.
+    pub fn lambda(&self, w: Vec3) -> Num {
+        // tan^2 times the width of the distribution seen from w's azimuth
+        let alpha2_tan2 = (w.x * w.x * self.alpha_x * self.alpha_x
+            + w.y * w.y * self.alpha_y * self.alpha_y)
+            / (w.z * w.z);
+        if alpha2_tan2 == 0.0 {
+            return 0.0;
+        }
+        match self.distribution {
+            Distribution::Ggx => 0.5 * (Num::sqrt(1.0 + alpha2_tan2) - 1.0),
+            Distribution::Beckmann => {
+                let a = 1.0 / Num::sqrt(alpha2_tan2);
+                if a >= 1.6 {
+                    0.0
+                } else {
+                    (1.0 - 1.259 * a + 0.396 * a * a) / (3.535 * a + 2.181 * a * a)
+                }
+            }
+        }
+    }
+
+    /// Fraction of microfacets visible from `w`.
+    pub fn g1(&self, w: Vec3) -> Num {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Fraction of microfacets visible from both `wo` and `wi`, with the
+    /// masking and shadowing correlated by height.
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> Num {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Density of the normals visible from `wo`, which `sample_visible`
+    /// picks normals with.
+    pub fn visible_pdf(&self, wo: Vec3, m: Vec3) -> Num {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * wo.dot(m).max(0.0) * self.d(m) / wo.z
+    }
+
+    /// A microfacet normal visible from `wo`, which must be above the
+    /// surface, picked in proportion to its projected area.
+    pub fn sample_visible(&self, wo: Vec3, (u1, u2): (Num, Num)) -> Vec3 {
+        // stretch the view to where the distribution is the unit one
+        let stretched = Vec3::new(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z).unit_vector();
+        let (slope_x, slope_y) = match self.distribution {
+            Distribution::Ggx => return self.sample_visible_ggx(stretched, (u1, u2)),
+            Distribution::Beckmann => {
+                let (x, y) = beckmann_slopes(stretched.z, u1, u2);
+                // rotate to the view's azimuth
+                let sin2 = 1.0 - stretched.z * stretched.z;
+                let (cos_phi, sin_phi) = if sin2 > 0.0 {
+                    let sin_theta = Num::sqrt(sin2);
+                    (stretched.x / sin_theta, stretched.y / sin_theta)
+                } else {
+                    (1.0, 0.0)
+                };
+                (cos_phi * x - sin_phi * y, sin_phi * x + cos_phi * y)
+            }
+        };
+        Vec3::new(-self.alpha_x * slope_x, -self.alpha_y * slope_y, 1.0).unit_vector()
+    }
+
+    /// Heitz, "Sampling the GGX Distribution of Visible Normals", 2018.
+    fn sample_visible_ggx(&self, stretched: Vec3, (u1, u2): (Num, Num)) -> Vec3 {
+        let v = stretched;
+        let length2 = v.x * v.x + v.y * v.y;
+        let t1 = if length2 > 0.0 {
+            Vec3::new(-v.y, v.x, 0.0) / Num::sqrt(length2)
+        } else {
+            Vec3::unit_x()
+        };
+        let t2 = v.cross(t1);
+        // a point on the disk, squeezed to the part of it that's visible
+        let r = Num::sqrt(u1);
+        let phi = 2.0 * PI * u2;
+        let p1 = r * Num::cos(phi);
+        let s = 0.5 * (1.0 + v.z);
+        let p2 = (1.0 - s) * Num::sqrt(1.0 - p1 * p1) + s * r * Num::sin(phi);
+        let height = Num::sqrt((1.0 - p1 * p1 - p2 * p2).max(0.0));
+        let n = t1 * p1 + t2 * p2 + v * height;
+        Vec3::new(self.alpha_x * n.x, self.alpha_y * n.y, n.z.max(0.0)).unit_vector()
+    }
+}
+
+/// Slopes of a visible normal of the unit Beckmann distribution seen at
+/// `cos_theta` in the xz plane, after Jakob's implementation in pbrt of
+/// Heitz and d'Eon, "Importance Sampling Microfacet-Based BSDFs using the
+/// Distribution of Visible Normals", 2014.
+fn beckmann_slopes(cos_theta: Num, u1: Num, u2: Num) -> (Num, Num) {
+    if cos_theta > 0.9999 {
+        // looking straight down every normal is visible
+        let r = Num::sqrt(-Num::ln(1.0 - u1));
+        let phi = 2.0 * PI * u2;
+        return (r * Num::cos(phi), r * Num::sin(phi));
+    }
+    let sin_theta = Num::sqrt((1.0 - cos_theta * cos_theta).max(0.0));
+    let tan_theta = sin_theta / cos_theta;
+    let cot_theta = 1.0 / tan_theta;
+    let inv_sqrt_pi = 1.0 / Num::sqrt(PI);
+
+    // invert the cumulative distribution of the x slope by bisection and
+    // Newton steps, starting from a fitted guess
+    let (mut low, mut high) = (-1.0, erf(cot_theta));
+    let sample_x = u1.max(1e-6);
+    let theta = Num::acos(cos_theta);
+    let fit = 1.0 + theta * (-0.876 + theta * (0.4265 - 0.0594 * theta));
+    let mut b = high - (1.0 + high) * Num::powf(1.0 - sample_x, fit);
+    let normalization =
+        1.0 / (1.0 + high + inv_sqrt_pi * tan_theta * Num::exp(-cot_theta * cot_theta));
+    for _ in 0..10 {
+        if !(b >= low && b <= high) {
+            b = 0.5 * (low + high);
+        }
+        let inverse = erf_inv(b);
+        let value = normalization
+            * (1.0 + b + inv_sqrt_pi * tan_theta * Num::exp(-inverse * inverse))
+            - sample_x;
+        if value.abs() < 1e-7 {
+            break;
+        }
+        if value > 0.0 {
+            high = b;
+        } else {
+            low = b;
+        }
+        let derivative = normalization * (1.0 - inverse * tan_theta);
+        b -= value / derivative;
+    }
+    (erf_inv(b), erf_inv(2.0 * u2.max(1e-6) - 1.0))
+}
+
+/// Burley's generalized Trowbridge-Reitz distribution with an exponent of
+/// one, whose long tails give clearcoats their haze, for normals `cos_theta`
+/// from the macro normal. `alpha` must be below one.
+pub fn gtr1_d(cos_theta: Num, alpha: Num) -> Num {
+    if cos_theta <= 0.0 {
+        return 0.0;
+    }
+    let a2 = alpha * alpha;
+    (a2 - 1.0) / (PI * a2.ln() * (1.0 + (a2 - 1.0) * cos_theta * cos_theta))
+}
+
+/// A normal picked in proportion to `gtr1_d` times its cosine.
+pub fn sample_gtr1(alpha: Num, (u1, u2): (Num, Num)) -> Vec3 {
+    let a2 = alpha * alpha;
+    let cos2 = clamp_num((1.0 - a2.powf(1.0 - u1)) / (1.0 - a2), 0.0, 1.0);
+    let sin_theta = Num::sqrt(1.0 - cos2);
+    let phi = 2.0 * PI * u2;
+    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos2.sqrt())
+}
+
+/// The error function, to within 1.2e-7 (Numerical Recipes' `erfc`).
+pub fn erf(x: Num) -> Num {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let polynomial = -z * z - 1.26551223
+        + t * (1.00002368
+            + t * (0.37409196
+                + t * (0.09678418
+                    + t * (-0.18628806
+                        + t * (0.27886807
+                            + t * (-1.13520398
+                                + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277))))))));
+    let complement = t * Num::exp(polynomial);
+    if x >= 0.0 {
+        1.0 - complement
+    } else {
+        complement - 1.0
+    }
+}
+
+/// The inverse of [`erf`] on (-1, 1), from Giles' approximation polished
+/// with two Newton steps.
+pub fn erf_inv(y: Num) -> Num {
+    let y = clamp_num(y, -1.0 + 1e-15, 1.0 - 1e-15);
+    let w = -Num::ln((1.0 - y) * (1.0 + y));
+    let mut x = if w < 5.0 {
+        let w = w - 2.5;
+        let mut p = 2.81022636e-08;
+        for &c in &[
+            3.43273939e-07,
+            -3.5233877e-06,
+            -4.39150654e-06,
+            0.00021858087,
+            -0.00125372503,
+            -0.00417768164,
+            0.246640727,
+            1.50140941,
+        ] {
+            p = c + p * w;
+        }
+        p * y
+    } else {
+        let w = Num::sqrt(w) - 3.0;
+        let mut p = -0.000200214257;
+        for &c in &[
+            0.000100950558,
+            0.00134934322,
+            -0.00367342844,
+            0.00573950773,
+            -0.0076224613,
+            0.00943887047,
+            1.00167406,
+            2.83297682,
+        ] {
+            p = c + p * w;
+        }
+        p * y
+    };
+    for _ in 0..2 {
+        let error = erf(x) - y;
+        x -= error / (2.0 / Num::sqrt(PI) * Num::exp(-x * x));
+    }
+    x
+}
+
+/// Refracts `v` through a surface with normal `normal` on `v`'s side, where
+/// `eta` is the index of refraction across the surface over the one on
+/// `v`'s side. `None` on total internal reflection.
+pub fn refract_through(v: Vec3, normal: Vec3, eta: Num) -> Option<Vec3> {
+    let cos_i = v.dot(normal);
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = Num::sqrt(1.0 - sin2_t);
+    Some(-v / eta + normal * (cos_i / eta - cos_t))
+}
+
+/// Unpolarized reflectance of a dielectric for light arriving at
+/// `cos_theta` from the normal, where `eta` is the index of refraction
+/// across the surface over the one the light comes from. One on total
+/// internal reflection.
+pub fn fresnel_dielectric(cos_theta: Num, eta: Num) -> Num {
+    let cos_i = clamp_num(cos_theta, 0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = Num::sqrt(1.0 - sin2_t);
+    let parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    0.5 * (parallel * parallel + perpendicular * perpendicular)
+}
+
+/// Unpolarized reflectance of a conductor with complex index of refraction
+/// `eta + i k` relative to the outside, per channel, for light arriving at
+/// `cos_theta` from the normal.
+pub fn fresnel_conductor(cos_theta: Num, eta: Color, k: Color) -> Color {
+    let channel = |eta: Num, k: Num| {
+        let cos2 = clamp_num(cos_theta, 0.0, 1.0).powi(2);
+        let sin2 = 1.0 - cos2;
+        let (eta2, k2) = (eta * eta, k * k);
+        let t0 = eta2 - k2 - sin2;
+        let a2_plus_b2 = Num::sqrt(t0 * t0 + 4.0 * eta2 * k2);
+        let a = Num::sqrt((0.5 * (a2_plus_b2 + t0)).max(0.0));
+        let t1 = a2_plus_b2 + cos2;
+        let t2 = 2.0 * a * cos_theta.max(0.0);
+        let rs = (t1 - t2) / (t1 + t2);
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        0.5 * (rs + rp)
+    };
+    Color::new(
+        channel(eta.x, k.x),
+        channel(eta.y, k.y),
+        channel(eta.z, k.z),
+    )
+}
+
+/// Measured conductors, with their complex indices of refraction sampled at
+/// red (650nm), green (550nm) and blue (450nm) wavelengths.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConductorPreset {
+    Gold,
+    Copper,
+    Aluminium,
+    Silver,
+}
+
+impl ConductorPreset {
+    pub const ALL: [ConductorPreset; 4] = [
+        ConductorPreset::Gold,
+        ConductorPreset::Copper,
+        ConductorPreset::Aluminium,
+        ConductorPreset::Silver,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ConductorPreset::Gold => "gold",
+            ConductorPreset::Copper => "copper",
+            ConductorPreset::Aluminium => "aluminium",
+            ConductorPreset::Silver => "silver",
+        }
+    }
+
+    /// The real and imaginary parts of the index of refraction.
+    pub fn eta_k(self) -> (Color, Color) {
+        match self {
+            ConductorPreset::Gold => (
+                Color::new(0.143, 0.374, 1.442),
+                Color::new(3.983, 2.385, 1.603),
+            ),
+            ConductorPreset::Copper => (
+                Color::new(0.200, 0.924, 1.102),
+                Color::new(3.912, 2.452, 2.142),
+            ),
+            ConductorPreset::Aluminium => (
+                Color::new(1.657, 0.880, 0.521),
+                Color::new(9.224, 6.270, 4.837),
+            ),
+            ConductorPreset::Silver => (
+                Color::new(0.155, 0.117, 0.138),
+                Color::new(4.828, 3.122, 2.147),
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ConductorPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for ConductorPreset {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<ConductorPreset, String> {
+        ConductorPreset::ALL
+            .iter()
+            .copied()
+            .find(|preset| preset.name() == name)
+            .ok_or_else(|| {
+                format!(
+                    "unknown metal `{}`, expected gold, copper, aluminium or silver",
+                    name
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod test_microfacet {
+    use super::*;
+    use crate::sampler::*;
+
+    fn distributions() -> Vec<Microfacet> {
+        let mut all = Vec::new();
+        for &distribution in &Distribution::ALL {
+            all.push(Microfacet::new(distribution, 0.3, 0.3));
+            all.push(Microfacet::new(distribution, 0.6, 0.6));
+            all.push(Microfacet::new(distribution, 0.2, 0.5));
+        }
+        all
+    }
+
+    /// Integrates `f` over the upper hemisphere with the midpoint rule.
+    fn integrate<F: Fn(Vec3) -> Num>(f: F) -> Num {
+        let (thetas, phis) = (400, 200);
+        let (d_theta, d_phi) = (0.5 * PI / thetas as Num, 2.0 * PI / phis as Num);
+        let mut sum = 0.0;
+        for i in 0..thetas {
+            let theta = (i as Num + 0.5) * d_theta;
+            for j in 0..phis {
+                let phi = (j as Num + 0.5) * d_phi;
+                let w = Vec3::new(
+                    Num::sin(theta) * Num::cos(phi),
+                    Num::sin(theta) * Num::sin(phi),
+                    Num::cos(theta),
+                );
+                sum += f(w) * Num::sin(theta) * d_theta * d_phi;
+            }
+        }
+        sum
+    }
+
+    fn direction(theta_degrees: Num, phi_degrees: Num) -> Vec3 {
+        let (theta, phi) = (
+            degrees_to_radians(theta_degrees),
+            degrees_to_radians(phi_degrees),
+        );
+        Vec3::new(
+            Num::sin(theta) * Num::cos(phi),
+            Num::sin(theta) * Num::sin(phi),
+            Num::cos(theta),
+        )
+    }
+
+    #[test]
+    fn test_normalized() {
+        for microfacet in distributions() {
+            // the projected microfacet area adds up to the surface's
+            let projected = integrate(|m| microfacet.d(m) * m.z);
+            assert!(
+                (projected - 1.0).abs() < 2e-3,
+                "{:?}: {}",
+                microfacet,
+                projected
+            );
+            // and so do the visible normals, which checks lambda against d
+            for &(theta, phi) in &[(0.0, 0.0), (40.0, 30.0), (75.0, 200.0)] {
+                let wo = direction(theta, phi);
+                let visible = integrate(|m| microfacet.visible_pdf(wo, m));
+                assert!(
+                    (visible - 1.0).abs() < 1e-2,
+                    "{:?}: {}",
+                    microfacet,
+                    visible
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_gtr1() {
+        let mut sampler = IndependentSampler::new(5);
+        for &alpha in &[0.1, 0.3] {
+            let projected = integrate(|m| gtr1_d(m.z, alpha) * m.z);
+            assert!((projected - 1.0).abs() < 2e-3, "{}: {}", alpha, projected);
+            // samples land as often as the projected area says
+            let count = 20_000;
+            let samples: Vec<Vec3> = (0..count)
+                .map(|index| {
+                    sampler.start_sample(0, 0, index);
+                    sample_gtr1(alpha, sampler.next_2d())
+                })
+                .collect();
+            for &cos_theta in &[0.99, 0.9, 0.5] {
+                let expected = integrate(|m| {
+                    if m.z > cos_theta {
+                        gtr1_d(m.z, alpha) * m.z
+                    } else {
+                        0.0
+                    }
+                });
+                let found = samples.iter().filter(|m| m.z > cos_theta).count();
+                let fraction = found as Num / count as Num;
+                assert!(
+                    (fraction - expected).abs() < 1e-2,
+                    "{} != {}",
+                    fraction,
+                    expected
+                );
+            }
+        }
+        // frames keep to the tangent they're given
+        let n = Vec3::new(1.0, 1.0, 0.0).unit_vector();
+        let frame = Frame::with_tangent(n, Vec3::unit_x());
+        assert!((frame.s - Vec3::new(1.0, -1.0, 0.0).unit_vector()).magnitude() < 1e-12);
+        assert!(
+            (frame.to_world(frame.to_local(Vec3::new(1, 2, 3))) - Vec3::new(1, 2, 3)).magnitude()
+                < 1e-12
+        );
+        assert_eq!(Frame::with_tangent(n, n).n, n);
+    }
+
+    #[test]
+    fn test_visible_sampling() {
+        let mut sampler = IndependentSampler::new(3);
+        for microfacet in distributions() {
+            for &(theta, phi) in &[(10.0, 0.0), (60.0, 120.0)] {
+                let wo = direction(theta, phi);
+                // the sampled normals' mean matches the density's
+                let expected = Vec3::new(
+                    integrate(|m| m.x * microfacet.visible_pdf(wo, m)),
+                    integrate(|m| m.y * microfacet.visible_pdf(wo, m)),
+                    integrate(|m| m.z * microfacet.visible_pdf(wo, m)),
+                );
+                let count = 50_000;
+                let mut mean = Vec3::zero();
+                for index in 0..count {
+                    sampler.start_sample(0, 0, index);
+                    let m = microfacet.sample_visible(wo, sampler.next_2d());
+                    assert!(m.z >= 0.0 && wo.dot(m) >= -1e-9);
+                    mean = mean + m / count as Num;
+                }
+                assert!(
+                    (mean - expected).magnitude() < 1e-2,
+                    "{:?} at {}: {} != {}",
+                    microfacet,
+                    theta,
+                    mean,
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_erf() {
+        assert!(erf(0.0).abs() < 1e-7);
+        assert!((erf(0.5) - 0.5204998778).abs() < 2e-7);
+        assert!((erf(-1.5) + 0.9661051465).abs() < 2e-7);
+        for &y in &[-0.999, -0.5, 0.0, 0.1, 0.9, 0.99999] {
+            assert!((erf(erf_inv(y)) - y).abs() < 1e-9, "{}", y);
+        }
+    }
+
+    #[test]
+    fn test_fresnel() {
+        // at normal incidence ((n - 1)^2 + k^2) / ((n + 1)^2 + k^2)
+        let (eta, k) = (Color::new(0.2, 1.5, 1.0), Color::new(3.0, 0.0, 2.0));
+        let normal = fresnel_conductor(1.0, eta, k);
+        let expected = |n: Num, k: Num| ((n - 1.0).powi(2) + k * k) / ((n + 1.0).powi(2) + k * k);
+        assert!((normal.x - expected(0.2, 3.0)).abs() < 1e-12);
+        assert!((normal.y - expected(1.5, 0.0)).abs() < 1e-12);
+        assert!((normal.z - expected(1.0, 2.0)).abs() < 1e-12);
+        // everything is reflected at grazing angles
+        let grazing = fresnel_conductor(0.0, eta, k);
+        assert!((grazing - Color::one()).magnitude() < 1e-9);
+        // gold is yellow, silver is bright and neutral
+        let (eta, k) = ConductorPreset::Gold.eta_k();
+        let gold = fresnel_conductor(1.0, eta, k);
+        assert!(gold.x > 0.9 && gold.z < 0.5);
+        let (eta, k) = ConductorPreset::Silver.eta_k();
+        let silver = fresnel_conductor(1.0, eta, k);
+        assert!(silver.x > 0.9 && silver.z > 0.9);
+    }
+
+    #[test]
+    fn test_dielectric() {
+        // glass reflects 4% head on, everything past the critical angle
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-12);
+        assert!((fresnel_dielectric(1.0, 1.0 / 1.5) - 0.04).abs() < 1e-12);
+        assert_eq!(fresnel_dielectric(0.5, 1.0 / 1.5), 1.0);
+        assert!((fresnel_dielectric(0.0, 1.5) - 1.0).abs() < 1e-12);
+        // no reflection at Brewster's angle for the parallel polarization,
+        // which leaves half the perpendicular one
+        let brewster = Num::atan(1.5);
+        let cos_b = Num::cos(brewster);
+        let cos_t = Num::sqrt(1.0 - (1.0 - cos_b * cos_b) / 2.25);
+        let perpendicular = (cos_b - 1.5 * cos_t) / (cos_b + 1.5 * cos_t);
+        assert!((fresnel_dielectric(cos_b, 1.5) - 0.5 * perpendicular.powi(2)).abs() < 1e-12);
+
+        // refraction follows Snell's law and can be undone
+        let v = direction(40.0, 70.0);
+        let refracted = refract_through(v, Vec3::unit_z(), 1.5).unwrap();
+        assert!((refracted.magnitude() - 1.0).abs() < 1e-12);
+        let sin_t = Num::sqrt(1.0 - refracted.z * refracted.z);
+        assert!((sin_t * 1.5 - degrees_to_radians(40.0).sin()).abs() < 1e-12);
+        let back = refract_through(refracted, -Vec3::unit_z(), 1.0 / 1.5).unwrap();
+        assert!((back - v).magnitude() < 1e-12);
+        assert!(refract_through(direction(60.0, 0.0), Vec3::unit_z(), 1.0 / 1.5).is_none());
+    }
+
+    #[test]
+    fn test_names() {
+        for &distribution in &Distribution::ALL {
+            assert_eq!(distribution.to_string().parse(), Ok(distribution));
+        }
+        for &preset in &ConductorPreset::ALL {
+            assert_eq!(preset.to_string().parse(), Ok(preset));
+        }
+        assert!("brass"
+            .parse::<ConductorPreset>()
+            .unwrap_err()
+            .contains("unknown metal"));
+    }
+}
@@ -0,0 +1,522 @@
+use crate::math::*;
+use crate::tonemap::*;
+use crate::types::*;
+use flate2::write::ZlibEncoder;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// A rendered image of averaged linear colors, rows from the top down.
+///
+/// The 8 bit formats go through a [`DisplayTransform`] when written, PFM and
+/// OpenEXR keep the linear values as 32 bit floats.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+impl Image {
+    /// A black image.
+    pub fn new(width: usize, height: usize) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![Color::zero(); width * height],
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: Color) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    /// Writes the image in the format matching the path's extension, with
+    /// the default display transform.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.save_with(path, &DisplayTransform::default())
+    }
+
+    /// Like `save`, showing 8 bit formats with `display`.
+    pub fn save_with<P: AsRef<Path>>(&self, path: P, display: &DisplayTransform) -> io::Result<()> {
+        let path = path.as_ref();
+        let format = ImageFormat::from_path(path).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "unsupported output format for {}, use .png, .ppm, .pfm or .exr",
+                    path.display()
+                ),
+            )
+        })?;
+        self.save_as(path, format, display)
+    }
+
+    /// Writes the image in `format`, whatever the path's extension.
+    pub fn save_as<P: AsRef<Path>>(
+        &self,
+        path: P,
+        format: ImageFormat,
+        display: &DisplayTransform,
+    ) -> io::Result<()> {
+        let file = std::fs::File::create(path)?;
+        format.encode_with(self, display, io::BufWriter::new(file))
+    }
+}
+
+/// Colors each pixel by how many samples it took, from dark blue for the
+/// fewest through green and yellow to red for the most.
+///
+/// `counts` are the per-pixel sample counts, rows from the top down.
+pub fn sample_heatmap(counts: &[usize], width: usize, height: usize) -> Image {
+    assert_eq!(counts.len(), width * height, "one count per pixel");
+    let min = counts.iter().copied().min().unwrap_or(0);
+    let max = counts.iter().copied().max().unwrap_or(0);
+    let ramp = [
+        Color::new(0, 0, 0.2),
+        Color::new(0, 0.2, 1),
+        Color::new(0, 1, 0.2),
+        Color::new(1, 1, 0),
+        Color::new(1, 0, 0),
+    ];
+    let pixels = counts
+        .iter()
+        .map(|&count| {
+            let t = if max > min {
+                (count - min) as Num / (max - min) as Num
+            } else {
+                0.0
+            };
+            let position = t * (ramp.len() - 1) as Num;
+            let index = usize::min(position as usize, ramp.len() - 2);
+            let fraction = position - index as Num;
+            ramp[index] * (1.0 - fraction) + ramp[index + 1] * fraction
+        })
+        .collect();
+    Image {
+        width,
+        height,
+        pixels,
+    }
+}
+
+/// Clips a linear color to [0, 1] and encodes it to 8 bit sRGB.
+pub fn to_rgb8(color: Color) -> [u8; 3] {
+    DisplayTransform::default().to_rgb8(color)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    Png,
+    /// binary (P6) PPM
+    Ppm,
+    /// plain text (P3) PPM
+    PlainPpm,
+    /// little endian RGB Portable Float Map
+    Pfm,
+    /// scanline OpenEXR with 32 bit float R, G and B channels
+    Exr(ExrCompression),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExrCompression {
+    None,
+    /// zlib, 16 scanlines per block
+    Zip,
+}
+
+impl ExrCompression {
+    pub const ALL: [ExrCompression; 2] = [ExrCompression::None, ExrCompression::Zip];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ExrCompression::None => "none",
+            ExrCompression::Zip => "zip",
+        }
+    }
+}
+
+impl fmt::Display for ExrCompression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for ExrCompression {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<ExrCompression, String> {
+        ExrCompression::ALL
+            .iter()
+            .copied()
+            .find(|compression| compression.name() == name)
+            .ok_or_else(|| format!("unknown exr compression `{}`, expected none or zip", name))
+    }
+}
+
+impl ImageFormat {
+    /// `.png`, `.ppm` (binary), `.pfm` or `.exr` (ZIP compressed); plain PPM
+    /// and uncompressed EXR have to be asked for explicitly, the latter with
+    /// `with_exr_compression`.
+    pub fn from_path(path: &Path) -> Option<ImageFormat> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(ImageFormat::Png),
+            "ppm" => Some(ImageFormat::Ppm),
+            "pfm" => Some(ImageFormat::Pfm),
+            "exr" => Some(ImageFormat::Exr(ExrCompression::Zip)),
+            _ => None,
+        }
+    }
+
+    /// OpenEXR with `compression` instead, other formats unchanged.
+    pub fn with_exr_compression(self, compression: ExrCompression) -> ImageFormat {
+        match self {
+            ImageFormat::Exr(_) => ImageFormat::Exr(compression),
+            format => format,
+        }
+    }
+
+    pub fn encode<W: Write>(self, image: &Image, out: W) -> io::Result<()> {
+        self.encode_with(image, &DisplayTransform::default(), out)
+    }
+
+    /// Like `encode`, showing 8 bit formats with `display`.
+    pub fn encode_with<W: Write>(
+        self,
+        image: &Image,
+        display: &DisplayTransform,
+        mut out: W,
+    ) -> io::Result<()> {
+        let rgb = image
+            .pixels
+            .iter()
+            .flat_map(|&pixel| display.to_rgb8(pixel));
+        match self {
+            ImageFormat::Png => {
+                let mut encoder =
+                    png::Encoder::new(&mut out, image.width as u32, image.height as u32);
+                encoder.set_color(png::ColorType::Rgb);
+                encoder.set_depth(png::BitDepth::Eight);
+                let mut writer = encoder.write_header()?;
+                writer.write_image_data(&rgb.collect::<Vec<u8>>())?;
+                writer.finish()?;
+            }
+            ImageFormat::Ppm => {
+                write!(out, "P6\n{} {}\n255\n", image.width, image.height)?;
+                out.write_all(&rgb.collect::<Vec<u8>>())?;
+            }
+            ImageFormat::PlainPpm => {
+                write!(out, "P3\n{} {}\n255\n", image.width, image.height)?;
+                for pixel in &image.pixels {
+                    let [r, g, b] = display.to_rgb8(*pixel);
+                    writeln!(out, "{} {} {}", r, g, b)?;
+                }
+            }
+            ImageFormat::Pfm => {
+                // a negative scale means little endian, rows go from the bottom up
+                write!(out, "PF\n{} {}\n-1.0\n", image.width, image.height)?;
+                let mut bytes = Vec::with_capacity(image.pixels.len() * 12);
+                for row in image.pixels.chunks(image.width).rev() {
+                    for pixel in row {
+                        for &channel in &[pixel.x, pixel.y, pixel.z] {
+                            bytes.extend_from_slice(&(channel as f32).to_le_bytes());
+                        }
+                    }
+                }
+                out.write_all(&bytes)?;
+            }
+            ImageFormat::Exr(compression) => encode_exr(image, compression, &mut out)?,
+        }
+        out.flush()
+    }
+}
+
+/// Writes a single part scanline OpenEXR file.
+///
+/// The offset table in front of the pixel data needs every block's size, so
+/// the blocks are all compressed before anything is written.
+fn encode_exr<W: Write>(image: &Image, compression: ExrCompression, out: &mut W) -> io::Result<()> {
+    let (width, height) = (image.width as i32, image.height as i32);
+    let mut header = Vec::new();
+    // magic number, then version 2 with no flags set: single part scanline
+    header.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+    let mut attribute = |name: &str, kind: &str, value: &[u8]| {
+        header.extend_from_slice(name.as_bytes());
+        header.push(0);
+        header.extend_from_slice(kind.as_bytes());
+        header.push(0);
+        header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+        header.extend_from_slice(value);
+    };
+    // channels are stored in alphabetical order, each as 32 bit floats
+    let mut channels = Vec::new();
+    for name in &["B", "G", "R"] {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        // pixel type FLOAT, linear flag and reserved bytes, x and y sampling
+        for value in &[2i32, 0, 1, 1] {
+            channels.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    channels.push(0);
+    attribute("channels", "chlist", &channels);
+    let (compression_id, lines_per_block) = match compression {
+        ExrCompression::None => (0, 1),
+        ExrCompression::Zip => (3, 16),
+    };
+    attribute("compression", "compression", &[compression_id]);
+    let window: Vec<u8> = [0, 0, width - 1, height - 1]
+        .iter()
+        .flat_map(|value: &i32| value.to_le_bytes())
+        .collect();
+    attribute("dataWindow", "box2i", &window);
+    attribute("displayWindow", "box2i", &window);
+    // increasing y
+    attribute("lineOrder", "lineOrder", &[0]);
+    attribute("pixelAspectRatio", "float", &1f32.to_le_bytes());
+    attribute("screenWindowCenter", "v2f", &[0; 8]);
+    attribute("screenWindowWidth", "float", &1f32.to_le_bytes());
+    header.push(0);
+
+    let blocks = image
+        .pixels
+        .chunks(image.width * lines_per_block)
+        .map(|lines| {
+            // each line holds all of its B values, then G, then R
+            let mut data = Vec::with_capacity(lines.len() * 12);
+            for line in lines.chunks(image.width) {
+                for &channel in &[2, 1, 0] {
+                    for pixel in line {
+                        data.extend_from_slice(&(pixel[channel] as f32).to_le_bytes());
+                    }
+                }
+            }
+            match compression {
+                ExrCompression::None => Ok(data),
+                ExrCompression::Zip => zip_block(data),
+            }
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+
+    out.write_all(&header)?;
+    let mut offset = (header.len() + 8 * blocks.len()) as u64;
+    for block in &blocks {
+        out.write_all(&offset.to_le_bytes())?;
+        // the block's first y and size come before its data
+        offset += 8 + block.len() as u64;
+    }
+    for (index, block) in blocks.iter().enumerate() {
+        out.write_all(&((index * lines_per_block) as i32).to_le_bytes())?;
+        out.write_all(&(block.len() as i32).to_le_bytes())?;
+        out.write_all(block)?;
+    }
+    Ok(())
+}
+
+/// OpenEXR's ZIP compression: the bytes are split into even and odd halves,
+/// delta encoded and then deflated. Blocks that don't shrink are stored as is.
+fn zip_block(data: Vec<u8>) -> io::Result<Vec<u8>> {
+    let half = data.len().div_ceil(2);
+    let mut reordered = vec![0; data.len()];
+    for (index, &byte) in data.iter().enumerate() {
+        let position = if index % 2 == 0 {
+            index / 2
+        } else {
+            half + index / 2
+        };
+        reordered[position] = byte;
+    }
+    // the first byte is kept, every other one is stored as the difference to its predecessor
+    let mut previous = reordered.first().copied().unwrap_or(0);
+    for byte in reordered.iter_mut().skip(1) {
+        let original = *byte;
+        *byte = original.wrapping_sub(previous).wrapping_add(128);
+        previous = original;
+    }
+    let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&reordered)?;
+    let compressed = encoder.finish()?;
+    Ok(if compressed.len() < data.len() {
+        compressed
+    } else {
+        data
+    })
+}
+
+#[cfg(test)]
+mod test_output {
+    use super::*;
+
+    fn image() -> Image {
+        let mut image = Image::new(2, 1);
+        image.set_pixel(0, 0, Color::new(1, 0.25, 0));
+        image.set_pixel(1, 0, Color::new(4, -1, 0.0625));
+        image
+    }
+
+    #[test]
+    fn test_to_rgb8() {
+        // sRGB encoded, not gamma 2
+        assert_eq!(to_rgb8(Color::new(0.25, 1, 0)), [137, 255, 0]);
+        // out of range values are clamped
+        assert_eq!(to_rgb8(Color::new(2, -1, 0.0625)), [255, 0, 70]);
+    }
+
+    #[test]
+    fn test_encode_with_display() {
+        let display = DisplayTransform {
+            exposure: 1.0,
+            tone_map: ToneMap::Reinhard,
+            ..DisplayTransform::default()
+        };
+        let mut plain = Vec::new();
+        ImageFormat::PlainPpm
+            .encode_with(&image(), &display, &mut plain)
+            .unwrap();
+        // one stop brighter, then the luminance is compressed; the unmapped
+        // image encodes to 255 137 0 and 255 0 70
+        assert_eq!(
+            String::from_utf8(plain).unwrap(),
+            "P3\n2 1\n255\n255 144 0\n255 0 88\n"
+        );
+        // float formats ignore the display transform
+        let (mut linear, mut mapped) = (Vec::new(), Vec::new());
+        ImageFormat::Pfm.encode(&image(), &mut linear).unwrap();
+        ImageFormat::Pfm
+            .encode_with(&image(), &display, &mut mapped)
+            .unwrap();
+        assert_eq!(linear, mapped);
+    }
+
+    #[test]
+    fn test_ppm() {
+        let mut plain = Vec::new();
+        ImageFormat::PlainPpm.encode(&image(), &mut plain).unwrap();
+        assert_eq!(
+            String::from_utf8(plain).unwrap(),
+            "P3\n2 1\n255\n255 137 0\n255 0 70\n"
+        );
+        let mut binary = Vec::new();
+        ImageFormat::Ppm.encode(&image(), &mut binary).unwrap();
+        assert_eq!(binary, b"P6\n2 1\n255\n\xff\x89\x00\xff\x00\x46".to_vec());
+    }
+
+    #[test]
+    fn test_png() {
+        let mut bytes = Vec::new();
+        ImageFormat::Png.encode(&image(), &mut bytes).unwrap();
+        let decoder = png::Decoder::new(&bytes[..]);
+        let mut reader = decoder.read_info().unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).unwrap();
+        assert_eq!((info.width, info.height), (2, 1));
+        assert_eq!(&buffer[..6], &[255, 137, 0, 255, 0, 70]);
+    }
+
+    #[test]
+    fn test_pfm() {
+        let mut bytes = Vec::new();
+        ImageFormat::Pfm.encode(&image(), &mut bytes).unwrap();
+        let header = b"PF\n2 1\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+        let floats: Vec<f32> = bytes[header.len()..]
+            .chunks(4)
+            .map(|float| f32::from_le_bytes([float[0], float[1], float[2], float[3]]))
+            .collect();
+        // linear and unclamped
+        assert_eq!(floats, vec![1.0, 0.25, 0.0, 4.0, -1.0, 0.0625]);
+
+        // rows are stored from the bottom up
+        let mut column = Image::new(1, 2);
+        column.set_pixel(0, 0, Color::one());
+        let mut bytes = Vec::new();
+        ImageFormat::Pfm.encode(&column, &mut bytes).unwrap();
+        assert_eq!(&bytes[(bytes.len() - 12)..], &[0, 0, 128, 63].repeat(3)[..]);
+    }
+
+    #[test]
+    fn test_exr() {
+        use exr::prelude::{read, ReadChannels, ReadLayers, Vec2};
+        // enough rows for more than one ZIP block, with a partial one at the end
+        let mut hdr = Image::new(7, 37);
+        for y in 0..hdr.height {
+            for x in 0..hdr.width {
+                let color = Color::new(x as Num * 10.5, y as Num / 8.0, 0.001 * (x * y) as Num);
+                hdr.set_pixel(x, y, color);
+            }
+        }
+        let mut sizes = Vec::new();
+        for &compression in &[ExrCompression::None, ExrCompression::Zip] {
+            let mut bytes = Vec::new();
+            ImageFormat::Exr(compression)
+                .encode(&hdr, &mut bytes)
+                .unwrap();
+            sizes.push(bytes.len());
+            let decoded = read()
+                .no_deep_data()
+                .largest_resolution_level()
+                .rgba_channels(
+                    |size, _| vec![[0.0f32; 3]; size.width() * size.height()],
+                    |pixels: &mut Vec<[f32; 3]>, position, (r, g, b, _): (f32, f32, f32, f32)| {
+                        pixels[position.y() * 7 + position.x()] = [r, g, b];
+                    },
+                )
+                .first_valid_layer()
+                .all_attributes()
+                .from_buffered(std::io::Cursor::new(bytes))
+                .unwrap();
+            let layer = &decoded.layer_data;
+            assert_eq!(layer.size, Vec2(7, 37));
+            for (pixel, decoded) in hdr.pixels.iter().zip(&layer.channel_data.pixels) {
+                let expected = [pixel.x as f32, pixel.y as f32, pixel.z as f32];
+                assert_eq!(*decoded, expected, "{:?}", compression);
+            }
+        }
+        assert!(sizes[1] < sizes[0], "ZIP didn't compress: {:?}", sizes);
+    }
+
+    #[test]
+    fn test_sample_heatmap() {
+        let heatmap = sample_heatmap(&[16, 64, 112], 3, 1);
+        assert_eq!(heatmap.pixel(0, 0), Color::new(0, 0, 0.2));
+        assert_eq!(heatmap.pixel(1, 0), Color::new(0, 1, 0.2));
+        assert_eq!(heatmap.pixel(2, 0), Color::new(1, 0, 0));
+        // a flat image gets the coolest color
+        let flat = sample_heatmap(&[8; 4], 2, 2);
+        assert!(flat
+            .pixels
+            .iter()
+            .all(|&pixel| pixel == Color::new(0, 0, 0.2)));
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        let format = |path: &str| ImageFormat::from_path(Path::new(path));
+        assert_eq!(format("out.png"), Some(ImageFormat::Png));
+        assert_eq!(format("renders/OUT.PPM"), Some(ImageFormat::Ppm));
+        assert_eq!(format("out.pfm"), Some(ImageFormat::Pfm));
+        assert_eq!(
+            format("out.exr"),
+            Some(ImageFormat::Exr(ExrCompression::Zip))
+        );
+        assert_eq!(format("out.jpg"), None);
+        assert_eq!(format("out"), None);
+
+        let uncompressed = |format: ImageFormat| format.with_exr_compression(ExrCompression::None);
+        assert_eq!(
+            uncompressed(ImageFormat::Exr(ExrCompression::Zip)),
+            ImageFormat::Exr(ExrCompression::None)
+        );
+        assert_eq!(uncompressed(ImageFormat::Png), ImageFormat::Png);
+        for &compression in &ExrCompression::ALL {
+            assert_eq!(compression.name().parse(), Ok(compression));
+        }
+        assert!("lzw".parse::<ExrCompression>().is_err());
+    }
+}
@@ -0,0 +1,978 @@
+//! Turning a scene into an image.
+
+use crate::film::*;
+use crate::filter::*;
+use crate::math::*;
+use crate::output::*;
+use crate::sampler::*;
+use crate::tiles::*;
+use crate::types::*;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How an image is rendered; the scene and camera are given separately.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderSettings {
+    pub width: usize,
+    pub height: usize,
+    pub samples_per_pixel: usize,
+    /// maximum number of bounces per path
+    pub max_depth: usize,
+    pub threads: usize,
+    /// every pixel sample draws its random numbers from a generator seeded
+    /// by this and its position, so a seed always gives the same image
+    pub seed: u64,
+    pub sampler: SamplerKind,
+    /// `Some` to keep sampling pixels that are still noisy after
+    /// `samples_per_pixel` samples
+    pub adaptive: Option<AdaptiveSampling>,
+    /// how samples are weighed into the pixels around them
+    pub filter: Filter,
+}
+
+/// Adaptive sampling: after the base samples, a pixel gets more in batches
+/// until the standard error of its mean luminance drops below `threshold`
+/// times the mean (or 0.01 for dark pixels), or it has `max_samples`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveSampling {
+    pub threshold: Num,
+    pub max_samples: usize,
+}
+
+/// Samples added to a noisy pixel between error checks.
+const ADAPTIVE_BATCH: usize = 8;
+
+impl RenderSettings {
+    /// The aspect ratio a camera needs to match the image.
+    pub fn aspect_ratio(&self) -> Num {
+        self.width as Num / self.height as Num
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> RenderSettings {
+        RenderSettings {
+            width: 1366,
+            height: 768,
+            samples_per_pixel: 100,
+            max_depth: 50,
+            threads: available_threads(),
+            seed: 0,
+            sampler: SamplerKind::Sobol,
+            adaptive: None,
+            filter: Filter::default(),
+        }
+    }
+}
+
+type ProgressFn = dyn Fn(usize, usize) + Send + Sync;
+
+/// Renders scenes with a fixed set of settings.
+///
+/// ```
+/// use std::sync::Arc;
+/// use yart::math::vec3::*;
+/// use yart::render::*;
+/// use yart::types::*;
+///
+/// let settings = RenderSettings {
+///     width: 8,
+///     height: 4,
+///     samples_per_pixel: 1,
+///     ..RenderSettings::default()
+/// };
+/// let scene = Scene {
+///     world: Arc::new(HittablesList::new()),
+///     lights: HittablesList::new(),
+///     background: Background::sky(),
+/// };
+/// let camera = Camera::new(
+///     Point::zero(),
+///     Point::new(0, 0, -1),
+///     Vec3::unit_y(),
+///     settings.aspect_ratio(),
+///     90.0,
+///     0.0,
+///     1.0,
+/// );
+/// let image = Renderer::new(settings).render(Arc::new(scene), camera);
+/// assert_eq!((image.width, image.height), (8, 4));
+/// ```
+pub struct Renderer {
+    settings: RenderSettings,
+    progress: Option<Arc<ProgressFn>>,
+}
+
+impl Renderer {
+    /// # Panics
+    ///
+    /// If the image is empty or there are no threads to render it with.
+    pub fn new(settings: RenderSettings) -> Renderer {
+        assert!(settings.width > 0 && settings.height > 0, "empty image");
+        assert!(settings.threads > 0, "no threads to render with");
+        Renderer {
+            settings,
+            progress: None,
+        }
+    }
+
+    /// Calls `progress` with the number of finished and total tiles as each
+    /// tile completes.
+    pub fn with_progress<F>(self, progress: F) -> Renderer
+    where
+        F: Fn(usize, usize) + Send + Sync + 'static,
+    {
+        Renderer {
+            progress: Some(Arc::new(progress)),
+            ..self
+        }
+    }
+
+    pub fn settings(&self) -> &RenderSettings {
+        &self.settings
+    }
+
+    /// Renders the scene as seen by `camera` into a framebuffer of averaged
+    /// linear radiance.
+    pub fn render(&self, scene: Arc<Scene>, camera: Camera) -> Image {
+        self.render_with_stats(scene, camera).0
+    }
+
+    /// Like `render`, also counting the rays traced on the way.
+    pub fn render_with_stats(&self, scene: Arc<Scene>, camera: Camera) -> (Image, RenderStats) {
+        let (film, stats) = self.render_film(scene, camera);
+        (film.image(), stats)
+    }
+
+    /// Renders into a float film, keeping the accumulated radiance and
+    /// weights rather than the resolved image.
+    ///
+    /// The image is split into tiles which the worker threads take as they
+    /// go. A tile's samples spread into its neighbours' pixels too, so the
+    /// tiles are only added onto the film once all are done, always in the
+    /// same order, keeping the sums the same whichever thread finished first.
+    pub fn render_film(&self, scene: Arc<Scene>, camera: Camera) -> (Film, RenderStats) {
+        let start = Instant::now();
+        let settings = &self.settings;
+        let tiles = split_tiles(settings.width, settings.height, TILE_SIZE);
+        let tile_count = tiles.len();
+        let queues = TileQueues::new(tiles, settings.threads);
+        let pixel_count = settings.width * settings.height;
+        let columns = settings.width.div_ceil(TILE_SIZE);
+        let finished = Mutex::new((
+            (0..tile_count).map(|_| None).collect::<Vec<_>>(),
+            vec![0; pixel_count],
+        ));
+        let tiles_done = AtomicUsize::new(0);
+        let rays = AtomicU64::new(0);
+
+        thread::scope(|scope| {
+            for worker in 0..settings.threads {
+                let (queues, finished, tiles_done, rays) = (&queues, &finished, &tiles_done, &rays);
+                let (scene, camera) = (&scene, &camera);
+                scope.spawn(move || {
+                    let mut sampler = settings
+                        .sampler
+                        .create(settings.seed, settings.samples_per_pixel);
+                    let mut counts = Vec::with_capacity(TILE_SIZE * TILE_SIZE);
+                    let margin = settings.filter.margin();
+                    while let Some(tile) = queues.next(worker) {
+                        // samples near the edges also land in the neighbouring tiles' pixels
+                        let film_x = tile.x.saturating_sub(margin);
+                        let film_y = tile.y.saturating_sub(margin);
+                        let mut tile_film = Film::new(
+                            usize::min(tile.x + tile.width + margin, settings.width) - film_x,
+                            usize::min(tile.y + tile.height + margin, settings.height) - film_y,
+                        );
+                        counts.clear();
+                        let mut tile_rays = 0;
+                        for y in tile.y..(tile.y + tile.height) {
+                            for x in tile.x..(tile.x + tile.width) {
+                                let count = self.render_pixel(
+                                    scene,
+                                    camera,
+                                    &mut *sampler,
+                                    (x, y),
+                                    (&mut tile_film, film_x, film_y),
+                                    &mut tile_rays,
+                                );
+                                counts.push(count);
+                            }
+                        }
+                        rays.fetch_add(tile_rays, Ordering::Relaxed);
+                        {
+                            let (tile_films, sample_counts) = &mut *finished.lock().unwrap();
+                            let index = tile.y / TILE_SIZE * columns + tile.x / TILE_SIZE;
+                            tile_films[index] = Some((tile_film, film_x, film_y));
+                            for (row, line) in counts.chunks(tile.width).enumerate() {
+                                let start = (tile.y + row) * settings.width + tile.x;
+                                sample_counts[start..(start + tile.width)].copy_from_slice(line);
+                            }
+                        }
+                        let done = tiles_done.fetch_add(1, Ordering::Relaxed) + 1;
+                        if let Some(progress) = &self.progress {
+                            progress(done, tile_count);
+                        }
+                    }
+                });
+            }
+        });
+
+        let (tile_films, sample_counts) = finished.into_inner().unwrap();
+        let mut film = Film::new(settings.width, settings.height);
+        for (tile_film, x, y) in tile_films.into_iter().flatten() {
+            film.merge(&tile_film, x, y);
+        }
+        let stats = RenderStats {
+            rays: rays.into_inner(),
+            elapsed: start.elapsed(),
+            sample_counts,
+        };
+        (film, stats)
+    }
+
+    /// Takes the samples of the pixel `x` columns from the left and `y` rows
+    /// from the top, splatting them onto `film` (whose top left corner is at
+    /// pixel `film_x`, `film_y`), and returns the number of samples taken.
+    fn render_pixel(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        sampler: &mut dyn Sampler,
+        (x, y): (usize, usize),
+        (film, film_x, film_y): (&mut Film, usize, usize),
+        rays: &mut u64,
+    ) -> usize {
+        let settings = &self.settings;
+        // the camera's v grows upwards
+        let h = settings.height - 1 - y;
+        let mut trace_sample = |index: usize| {
+            sampler.start_sample(x, y, index);
+            let (dx, dy) = sampler.next_2d();
+            let u = ((x as Num) + dx) / (settings.width - 1).max(1) as Num;
+            let v = ((h as Num) + dy) / (settings.height - 1).max(1) as Num;
+            let ray = camera.get_ray(u, v, sampler);
+            let radiance = trace_path(scene, ray, settings.max_depth, sampler, rays);
+            // where the sample is on the film, y grows downwards
+            let (film_dx, film_dy) = ((x - film_x) as Num + dx, (y - film_y) as Num + 1.0 - dy);
+            film.splat(film_dx, film_dy, radiance, &settings.filter);
+            radiance
+        };
+        let mut luminance = RunningVariance::default();
+        let mut count = 0;
+        let mut target = settings.samples_per_pixel;
+        while count < target {
+            let radiance = trace_sample(count);
+            luminance.push(radiance.luminance());
+            count += 1;
+            if count == target {
+                if let Some(adaptive) = &settings.adaptive {
+                    if count < adaptive.max_samples && !luminance.converged(adaptive.threshold) {
+                        target = usize::min(count + ADAPTIVE_BATCH, adaptive.max_samples);
+                    }
+                }
+            }
+        }
+        count
+    }
+}
+
+/// The number of CPUs, or 1 if that can't be found out.
+pub fn available_threads() -> usize {
+    thread::available_parallelism().map_or(1, |count| count.get())
+}
+
+/// Pixels per side of the tiles handed out to the worker threads.
+const TILE_SIZE: usize = 16;
+
+/// Welford's online mean and variance.
+#[derive(Debug, Default)]
+struct RunningVariance {
+    count: usize,
+    mean: Num,
+    squared_deviations: Num,
+}
+
+impl RunningVariance {
+    fn push(&mut self, value: Num) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as Num;
+        self.squared_deviations += delta * (value - self.mean);
+    }
+
+    /// Whether the standard error of the mean is within `threshold` of it.
+    fn converged(&self, threshold: Num) -> bool {
+        if self.count < 2 {
+            return false;
+        }
+        let variance = self.squared_deviations / (self.count - 1) as Num;
+        let standard_error = Num::sqrt(variance / self.count as Num);
+        standard_error <= threshold * Num::max(self.mean, 0.01)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderStats {
+    /// camera, bounce and shadow rays traced
+    pub rays: u64,
+    pub elapsed: Duration,
+    /// samples taken per pixel, rows from the top
+    pub sample_counts: Vec<usize>,
+}
+
+impl RenderStats {
+    pub fn rays_per_second(&self) -> f64 {
+        self.rays as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// Traces a path from `ray`, estimating the radiance arriving along it.
+///
+/// At every non-specular bounce the scene's lights are sampled directly, and
+/// those samples are combined with the ones found by following the material
+/// (which may also stumble onto a light) using multiple importance sampling.
+pub fn send_ray(scene: &Scene, ray: Ray, depth: usize, sampler: &mut dyn Sampler) -> Color {
+    trace_path(scene, ray, depth, sampler, &mut 0)
+}
+
+/// `send_ray`, adding the number of rays traced to `rays`.
+fn trace_path(
+    scene: &Scene,
+    ray: Ray,
+    depth: usize,
+    sampler: &mut dyn Sampler,
+    rays: &mut u64,
+) -> Color {
+    let mut radiance = Color::zero();
+    let mut throughput = Color::one();
+    let mut ray = ray;
+    // density of the material sample that produced `ray`, `None` for camera
+    // rays and specular bounces which lights can't be sampled against
+    let mut scatter_pdf: Option<Num> = None;
+
+    for _ in 0..depth {
+        // TODO: check out shaodw acne
+        *rays += 1;
+        let record = match scene.world.sample_hit(&ray, 0.001, INFINITY, sampler) {
+            Some(record) => record,
+            None => {
+                radiance = radiance + throughput * scene.background.radiance(&ray);
+                break;
+            }
+        };
+        let material = record.material.clone();
+
+        let emitted = material.emitted(&ray, &record);
+        if emitted != Color::zero() {
+            // only what light sampling could also have picked is shared with it
+            let weight = match scatter_pdf {
+                Some(pdf) if is_sampled_light(scene, &ray, &record, rays) => {
+                    power_heuristic(pdf, scene.lights.pdf_value(ray.origin, ray.direction))
+                }
+                _ => 1.0,
+            };
+            radiance = radiance + throughput * emitted * weight;
+        }
+
+        if !material.is_specular() && !scene.lights.is_empty() {
+            radiance = radiance + throughput * sample_lights(scene, &ray, &record, sampler, rays);
+        }
+
+        match material.scatter(ray, record.clone(), sampler) {
+            Some((deflected_ray, attenuation)) => {
+                throughput = throughput * attenuation;
+                scatter_pdf = if material.is_specular() {
+                    None
+                } else {
+                    Some(material.pdf(&ray, &record, deflected_ray.direction))
+                };
+                ray = deflected_ray;
+            }
+            None => break,
+        }
+    }
+    radiance
+}
+
+/// Whether `record` lies on one of the scene's lights rather than on an
+/// emitter they don't include, like glowing fog or an unlisted lamp.
+fn is_sampled_light(scene: &Scene, ray: &Ray, record: &HitRecord, rays: &mut u64) -> bool {
+    *rays += 1;
+    match scene.lights.hit(ray, 0.001, INFINITY) {
+        Some(light_record) => (light_record.t - record.t).abs() <= 1e-6 * record.t.max(1.0),
+        None => false,
+    }
+}
+
+/// Next event estimation: light reaching the hit directly from a point
+/// picked on one of the scene's lights, MIS weighted against the material.
+fn sample_lights(
+    scene: &Scene,
+    ray_in: &Ray,
+    record: &HitRecord,
+    sampler: &mut dyn Sampler,
+    rays: &mut u64,
+) -> Color {
+    let direction = scene.lights.random_direction(record.position, sampler);
+    let light_pdf = scene.lights.pdf_value(record.position, direction);
+    if light_pdf <= 0.0 {
+        return Color::zero();
+    }
+    let material = &record.material;
+    let bsdf = material.eval(ray_in, record, direction);
+    if bsdf == Color::zero() {
+        return Color::zero();
+    }
+    let shadow_ray = Ray {
+        origin: record.position,
+        direction,
+        time: ray_in.time,
+    };
+    *rays += 1;
+    let light_record = match scene.lights.hit(&shadow_ray, 0.001, INFINITY) {
+        Some(light_record) => light_record,
+        None => return Color::zero(),
+    };
+    // surfaces in between block the light, media dim it
+    let transmittance =
+        scene
+            .world
+            .transmittance(&shadow_ray, 0.001, light_record.t - 0.001, sampler);
+    if transmittance == 0.0 {
+        return Color::zero();
+    }
+    let emitted = light_record.material.emitted(&shadow_ray, &light_record);
+    let weight = power_heuristic(light_pdf, material.pdf(ray_in, record, direction));
+    bsdf * emitted * (transmittance * weight / light_pdf)
+}
+
+#[cfg(test)]
+mod test_render {
+    use super::*;
+    use crate::materials::*;
+    use crate::math::transform::*;
+    use crate::math::vec3::*;
+    use crate::media::*;
+    use crate::voxels::*;
+
+    fn scene(world: HittablesList, lights: HittablesList, background: Background) -> Scene {
+        Scene {
+            world: Arc::new(world),
+            lights,
+            background,
+        }
+    }
+
+    fn light_panel() -> HittablePtr {
+        Arc::new(Quad::new(
+            Point::new(-1, -1, -1),
+            Vec3::unit_x() * 2.0,
+            Vec3::unit_y() * 2.0,
+            Arc::new(DiffuseLight::new(Color::new(4, 2, 1))),
+        ))
+    }
+
+    #[test]
+    fn test_renderer() {
+        let settings = RenderSettings {
+            width: 40,
+            height: 20,
+            samples_per_pixel: 2,
+            max_depth: 4,
+            threads: 3,
+            seed: 0,
+            sampler: SamplerKind::Sobol,
+            adaptive: None,
+            // negative lobes still average a flat image to itself
+            filter: Filter::new(FilterKind::Lanczos),
+        };
+        let background = Background::Constant(Color::new(0.1, 0.2, 0.3));
+        let camera = Camera::new(
+            Point::zero(),
+            -Vec3::unit_z(),
+            Vec3::unit_y(),
+            settings.aspect_ratio(),
+            90.0,
+            0.0,
+            1.0,
+        );
+        let tiles = Arc::new(Mutex::new(Vec::new()));
+        let reported = tiles.clone();
+        let renderer = Renderer::new(settings).with_progress(move |done, total| {
+            reported.lock().unwrap().push((done, total));
+        });
+        let scene = scene(HittablesList::new(), HittablesList::new(), background);
+        let (image, stats) = renderer.render_with_stats(Arc::new(scene), camera);
+        assert_eq!((image.width, image.height), (40, 20));
+        // every sample misses, one ray each
+        assert_eq!(stats.rays, 40 * 20 * 2);
+        assert_eq!(stats.sample_counts, vec![2; 40 * 20]);
+        for pixel in &image.pixels {
+            assert!((*pixel - Color::new(0.1, 0.2, 0.3)).magnitude() < 1e-9);
+        }
+        // 16 pixel tiles, three across and two down, possibly reported out of order
+        let mut reported = tiles.lock().unwrap().clone();
+        reported.sort();
+        let expected: Vec<_> = (1..=6).map(|done| (done, 6)).collect();
+        assert_eq!(reported, expected);
+    }
+
+    #[test]
+    fn test_seed_determines_image() {
+        let world: HittablesList = vec![
+            Arc::new(Sphere {
+                center: Point::new(0, 0, -2),
+                radius: 1.0,
+                material: Arc::new(Lambertian::new(Color::new(0.5, 0.6, 0.7))),
+            }),
+            Arc::new(Sphere {
+                center: Point::new(1.5, 0, -2),
+                radius: 0.5,
+                material: Arc::new(Dielectric {
+                    refraction_index: 1.5,
+                }),
+            }),
+        ];
+        let scene = Arc::new(scene(world, HittablesList::new(), Background::sky()));
+        let render = |threads, seed, sampler| {
+            let settings = RenderSettings {
+                width: 37,
+                height: 21,
+                samples_per_pixel: 3,
+                max_depth: 8,
+                threads,
+                seed,
+                sampler,
+                adaptive: None,
+                filter: Filter::default(),
+            };
+            let camera = Camera::new(
+                Point::zero(),
+                -Vec3::unit_z(),
+                Vec3::unit_y(),
+                settings.aspect_ratio(),
+                60.0,
+                0.2,
+                2.0,
+            );
+            Renderer::new(settings).render(scene.clone(), camera)
+        };
+        for &sampler in &SamplerKind::ALL {
+            let image = render(1, 5, sampler);
+            assert_eq!(image, render(1, 5, sampler));
+            assert_eq!(image, render(4, 5, sampler));
+            assert_ne!(image, render(4, 6, sampler));
+        }
+    }
+
+    #[test]
+    fn test_motion_blur() {
+        // a white sphere crossing the frame against black
+        let world: HittablesList = vec![Arc::new(MovingSphere {
+            start_center: Point::new(-3, 0, -3),
+            end_center: Point::new(3, 0, -3),
+            start_time: 0.0,
+            end_time: 1.0,
+            radius: 0.5,
+            material: Arc::new(DiffuseLight::new(Color::one())),
+        })];
+        let scene = Arc::new(scene(world, HittablesList::new(), Background::black()));
+        let settings = RenderSettings {
+            width: 32,
+            height: 8,
+            samples_per_pixel: 64,
+            max_depth: 2,
+            threads: 1,
+            seed: 0,
+            sampler: SamplerKind::Sobol,
+            adaptive: None,
+            filter: Filter::new(FilterKind::Box),
+        };
+        let aspect_ratio = settings.aspect_ratio();
+        let camera = || {
+            Camera::new(
+                Point::zero(),
+                -Vec3::unit_z(),
+                Vec3::unit_y(),
+                aspect_ratio,
+                20.0,
+                0.0,
+                3.0,
+            )
+        };
+        let renderer = Renderer::new(settings);
+        let still = renderer.render(scene.clone(), camera());
+        let blurred = renderer.render(scene, camera().with_shutter(0.0, 1.0));
+        let row = |image: &Image| -> Vec<Num> { (0..32).map(|x| image.pixel(x, 4).x).collect() };
+        // at the start it's off the left of the frame, moving it streaks across
+        assert!(row(&still).iter().all(|&value| value == 0.0));
+        let streak = row(&blurred);
+        assert!(streak.iter().filter(|&&value| value > 0.0).count() > 24);
+        assert!(streak.iter().all(|&value| value < 0.5));
+    }
+
+    #[test]
+    fn test_adaptive_sampling() {
+        // a glossy sphere on the right half of the frame, plain background on the left
+        let world: HittablesList = vec![Arc::new(Sphere {
+            center: Point::new(1.2, 0, -2),
+            radius: 1.0,
+            material: Arc::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.8)),
+        })];
+        let background = Background::Constant(Color::new(0.1, 0.2, 0.3));
+        let scene = Arc::new(scene(world, vec![light_panel()], background));
+        let settings = RenderSettings {
+            width: 32,
+            height: 16,
+            samples_per_pixel: 4,
+            max_depth: 4,
+            threads: 2,
+            seed: 0,
+            sampler: SamplerKind::Sobol,
+            adaptive: Some(AdaptiveSampling {
+                threshold: 0.01,
+                max_samples: 60,
+            }),
+            filter: Filter::new(FilterKind::Box),
+        };
+        let camera = Camera::new(
+            Point::zero(),
+            -Vec3::unit_z(),
+            Vec3::unit_y(),
+            settings.aspect_ratio(),
+            90.0,
+            0.0,
+            1.0,
+        );
+        let (film, stats) = Renderer::new(settings).render_film(scene, camera);
+        let counts = &stats.sample_counts;
+        assert!(counts.iter().all(|&count| (4..=60).contains(&count)));
+        // with a one pixel box every sample weighs the same
+        for (index, &count) in counts.iter().enumerate() {
+            assert_eq!(film.weight(index % 32, index / 32), count as Num);
+        }
+        // background pixels have no variance, so they stop at the base samples
+        assert_eq!(counts[8 * 32], 4);
+        assert!(counts.contains(&60));
+        let total: usize = counts.iter().sum();
+        assert!(total < 32 * 16 * 60);
+    }
+
+    #[test]
+    fn test_running_variance() {
+        let mut variance = RunningVariance::default();
+        assert!(!variance.converged(0.1));
+        variance.push(1.0);
+        // one sample says nothing about the error
+        assert!(!variance.converged(0.1));
+        for &value in &[2.0, 3.0, 4.0] {
+            variance.push(value);
+        }
+        assert!((variance.mean - 2.5).abs() < 1e-12);
+        // sample variance 5/3, standard error sqrt(5/12) ~ 0.645 of a 2.5 mean
+        assert!(variance.converged(0.26));
+        assert!(!variance.converged(0.25));
+    }
+
+    #[test]
+    fn test_miss_returns_background() {
+        let mut sampler = IndependentSampler::new(0);
+        let ray = Ray {
+            origin: Point::zero(),
+            direction: Vec3::unit_y(),
+            time: 0.0,
+        };
+        let background = Background::Constant(Color::new(0.1, 0.2, 0.3));
+        let constant = scene(HittablesList::new(), HittablesList::new(), background);
+        assert_eq!(
+            send_ray(&constant, ray, 50, &mut sampler),
+            Color::new(0.1, 0.2, 0.3)
+        );
+        let sky = scene(
+            HittablesList::new(),
+            HittablesList::new(),
+            Background::sky(),
+        );
+        assert_eq!(
+            send_ray(&sky, ray, 50, &mut sampler),
+            Color::new(0.5, 0.7, 1.0)
+        );
+    }
+
+    #[test]
+    fn test_emission() {
+        let mut sampler = IndependentSampler::new(0);
+        let panel = scene(
+            vec![light_panel()],
+            vec![light_panel()],
+            Background::black(),
+        );
+        let facing = Ray {
+            origin: Point::zero(),
+            direction: -Vec3::unit_z(),
+            time: 0.0,
+        };
+        assert_eq!(
+            send_ray(&panel, facing, 50, &mut sampler),
+            Color::new(4, 2, 1)
+        );
+        let behind = Ray {
+            origin: Point::new(0, 0, -2),
+            direction: Vec3::unit_z(),
+            time: 0.0,
+        };
+        assert_eq!(send_ray(&panel, behind, 50, &mut sampler), Color::zero());
+    }
+
+    /// A diffuse floor under a large square light: the reflected radiance is
+    /// `albedo * emitted * form_factor` whether or not the light is sampled.
+    #[test]
+    fn test_direct_lighting_converges() {
+        let mut sampler = IndependentSampler::new(0);
+        let half_size = 20.0;
+        let albedo = 0.5;
+        let light: HittablePtr = Arc::new(Quad::new(
+            Point::new(-half_size, 1.0, -half_size),
+            Vec3::unit_x() * (2.0 * half_size),
+            Vec3::unit_z() * (2.0 * half_size),
+            Arc::new(DiffuseLight::new(Color::one())),
+        ));
+        let floor: HittablePtr = Arc::new(Plane {
+            p1: Point::zero(),
+            normal: Vec3::unit_y(),
+            material: Arc::new(Lambertian::new(Color::one() * albedo)),
+        });
+        // point to parallel rectangle form factor, one quarter of the light at a time
+        let a = half_size;
+        let b = Num::sqrt(1.0 + a * a);
+        let form_factor = 4.0 / (2.0 * PI) * (2.0 * a / b * Num::atan(a / b));
+        let expected = albedo * form_factor;
+
+        let ray = Ray {
+            origin: Point::new(0, 0.5, 0),
+            direction: -Vec3::unit_y(),
+            time: 0.0,
+        };
+        let samples = 20000;
+        for &sample_lights in &[true, false] {
+            let lights = if sample_lights {
+                vec![light.clone()]
+            } else {
+                HittablesList::new()
+            };
+            let scene = scene(
+                vec![light.clone(), floor.clone()],
+                lights,
+                Background::black(),
+            );
+            let mut sum = Color::zero();
+            for index in 0..samples {
+                sampler.start_sample(0, 0, index);
+                sum = sum + send_ray(&scene, ray, 50, &mut sampler);
+            }
+            let estimate = sum.x / samples as Num;
+            assert!(
+                (estimate - expected).abs() < 0.02 * expected,
+                "sample lights: {}, expected {} got {}",
+                sample_lights,
+                expected,
+                estimate
+            );
+        }
+    }
+
+    /// A light squashed out of shape by an instance is sampled as often as
+    /// it's found.
+    #[test]
+    fn test_scaled_light() {
+        let mut sampler = IndependentSampler::new(2);
+        let quad: HittablePtr = Arc::new(Quad::new(
+            Point::new(-0.5, 0.0, -0.5),
+            Vec3::unit_x(),
+            Vec3::unit_z(),
+            Arc::new(DiffuseLight::new(Color::one() * 4.0)),
+        ));
+        let transform = Transform::scale(Vec3::new(4, 1, 2))
+            .then(&Transform::rotate(Vec3::unit_z(), 20.0))
+            .then(&Transform::translate(Vec3::new(0.5, 1.5, 0)));
+        let light: HittablePtr = Arc::new(Instance::new(quad, transform));
+        let floor: HittablePtr = Arc::new(Plane {
+            p1: Point::zero(),
+            normal: Vec3::unit_y(),
+            material: Arc::new(Lambertian::new(Color::one() * 0.5)),
+        });
+        let ray = Ray {
+            origin: Point::new(0, 0.5, 0),
+            direction: -Vec3::unit_y(),
+            time: 0.0,
+        };
+        let samples = 40000;
+        let mut estimates = Vec::new();
+        for &sample_lights in &[true, false] {
+            let lights = if sample_lights {
+                vec![light.clone()]
+            } else {
+                HittablesList::new()
+            };
+            let scene = scene(
+                vec![light.clone(), floor.clone()],
+                lights,
+                Background::black(),
+            );
+            let mut sum = Color::zero();
+            for index in 0..samples {
+                sampler.start_sample(0, 0, index);
+                sum = sum + send_ray(&scene, ray, 50, &mut sampler);
+            }
+            estimates.push(sum.x / samples as Num);
+        }
+        assert!(estimates[0] > 0.1, "{:?}", estimates);
+        assert!(
+            (estimates[0] - estimates[1]).abs() < 0.03 * estimates[0],
+            "{:?}",
+            estimates
+        );
+    }
+
+    /// Emitters in front of a light but missing from the scene's lights are
+    /// never weighted against light sampling, only the light itself is.
+    #[test]
+    fn test_unlisted_emitters() {
+        let mut sampler = IndependentSampler::new(5);
+        let light: HittablePtr = Arc::new(Quad::new(
+            Point::new(-1, 2, -1),
+            Vec3::unit_x() * 2.0,
+            Vec3::unit_z() * 2.0,
+            Arc::new(DiffuseLight::new(Color::one() * 4.0)),
+        ));
+        let bounds = Aabb::new(Point::new(-1, 0.8, -1), Point::new(1, 1.2, 1));
+        let grid = VoxelGrid::new([1, 1, 1], bounds, vec![1.0], Some(vec![3000.0])).unwrap();
+        let glowing_fog: HittablePtr = Arc::new(
+            GridMedium::new(
+                Arc::new(grid),
+                2.0,
+                Color::one() * 0.5,
+                PhaseFunction::Isotropic,
+            )
+            .with_emission(1.0),
+        );
+        let lamp: HittablePtr = Arc::new(Quad::new(
+            Point::new(-0.5, 1, -0.5),
+            Vec3::unit_x(),
+            Vec3::unit_z(),
+            Arc::new(DiffuseLight::new(Color::one() * 2.0)),
+        ));
+        let floor: HittablePtr = Arc::new(Plane {
+            p1: Point::zero(),
+            normal: Vec3::unit_y(),
+            material: Arc::new(Lambertian::new(Color::one() * 0.5)),
+        });
+        let ray = Ray {
+            origin: Point::new(0, 0.5, 0),
+            direction: -Vec3::unit_y(),
+            time: 0.0,
+        };
+        let samples = 40000;
+        for emitter in &[glowing_fog, lamp] {
+            let mut estimates = Vec::new();
+            for &sample_lights in &[true, false] {
+                let lights = if sample_lights {
+                    vec![light.clone()]
+                } else {
+                    HittablesList::new()
+                };
+                let scene = scene(
+                    vec![light.clone(), emitter.clone(), floor.clone()],
+                    lights,
+                    Background::black(),
+                );
+                let mut sum = Color::zero();
+                for index in 0..samples {
+                    sampler.start_sample(0, 0, index);
+                    sum = sum + send_ray(&scene, ray, 50, &mut sampler);
+                }
+                estimates.push(sum.x / samples as Num);
+            }
+            assert!(estimates[0] > 0.1, "{:?}", estimates);
+            assert!(
+                (estimates[0] - estimates[1]).abs() < 0.03 * estimates[0],
+                "{:?}",
+                estimates
+            );
+        }
+    }
+
+    #[test]
+    fn test_media() {
+        let fog = |albedo: Num, phase: PhaseFunction| -> HittablePtr {
+            let material = Arc::new(VolumeScatter::new(Color::one() * albedo, phase));
+            let boundary = Arc::new(Sphere {
+                center: Point::zero(),
+                radius: 1.0,
+                material,
+            });
+            Arc::new(ConstantMedium::new(boundary, 1.0))
+        };
+        // the same ray straight through the middle every time
+        let estimate = |scene: &Scene| {
+            let mut sampler = IndependentSampler::new(4);
+            let samples = 20000;
+            let mut sum = Color::zero();
+            let ray = Ray {
+                origin: Point::new(0, 0, 3),
+                direction: -Vec3::unit_z(),
+                time: 0.0,
+            };
+            for index in 0..samples {
+                sampler.start_sample(0, 0, index);
+                sum = sum + send_ray(scene, ray, 100, &mut sampler);
+            }
+            sum.x / samples as Num
+        };
+
+        // a pure absorber lets through the transmittance along the ray
+        let glow = || Background::Constant(Color::one());
+        let absorber = scene(vec![fog(0.0, PhaseFunction::Isotropic)], vec![], glow());
+        let found = estimate(&absorber);
+        assert!((found - Num::exp(-2.0)).abs() < 0.01, "{}", found);
+        // and one that only scatters hides nothing of a uniform background
+        for &phase in &[
+            PhaseFunction::Isotropic,
+            PhaseFunction::HenyeyGreenstein(0.6),
+        ] {
+            let furnace = scene(vec![fog(1.0, phase)], vec![], glow());
+            let found = estimate(&furnace);
+            assert!((found - 1.0).abs() < 1e-3, "{:?}: {}", phase, found);
+        }
+
+        // lit from the side, sampling the light agrees with finding it
+        let light: HittablePtr = Arc::new(Quad::new(
+            Point::new(-1, 2, -1),
+            Vec3::unit_x() * 2.0,
+            Vec3::unit_z() * 2.0,
+            Arc::new(DiffuseLight::new(Color::one() * 4.0)),
+        ));
+        let mut estimates = Vec::new();
+        for &sample_lights in &[true, false] {
+            let lights = if sample_lights {
+                vec![light.clone()]
+            } else {
+                vec![]
+            };
+            let world = vec![
+                light.clone(),
+                fog(0.8, PhaseFunction::HenyeyGreenstein(-0.3)),
+            ];
+            estimates.push(estimate(&scene(world, lights, Background::black())));
+        }
+        assert!(estimates[0] > 0.05, "{:?}", estimates);
+        assert!(
+            (estimates[0] - estimates[1]).abs() < 0.05 * estimates[0],
+            "{:?}",
+            estimates
+        );
+    }
+}
@@ -0,0 +1,534 @@
+//! Sources of the random numbers a path is built from.
+//!
+//! A sampler is set to one sample of one pixel with `start_sample`, and then
+//! hands out numbers in [0, 1) one or two dimensions at a time: the pixel
+//! position first, then the lens, then whatever the materials and lights
+//! ask for at every bounce. Low discrepancy samplers spread each of those
+//! dimensions evenly over a pixel's samples, which converges faster than
+//! independent random numbers.
+
+use crate::math::vec3::*;
+use crate::math::*;
+use std::fmt;
+use std::str::FromStr;
+
+pub trait Sampler {
+    /// Moves to sample `index` of the pixel `x` columns from the left and
+    /// `y` rows from the top. What's drawn afterwards only depends on these,
+    /// the seed and the order of the draws.
+    fn start_sample(&mut self, x: usize, y: usize, index: usize);
+    fn next_1d(&mut self) -> Num;
+    fn next_2d(&mut self) -> (Num, Num);
+}
+
+pub type SamplerPtr = Box<dyn Sampler + Send>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SamplerKind {
+    Independent,
+    /// jittered strata, shuffled per pixel and dimension
+    Stratified,
+    /// Halton sequence with a random shift per pixel and dimension
+    Halton,
+    /// Owen scrambled Sobol points, padded in pairs of dimensions
+    Sobol,
+}
+
+impl SamplerKind {
+    pub const ALL: [SamplerKind; 4] = [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+    ];
+
+    /// A sampler for renders of `samples_per_pixel` samples.
+    pub fn create(self, seed: u64, samples_per_pixel: usize) -> SamplerPtr {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(seed, samples_per_pixel)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SamplerKind::Independent => "independent",
+            SamplerKind::Stratified => "stratified",
+            SamplerKind::Halton => "halton",
+            SamplerKind::Sobol => "sobol",
+        }
+    }
+}
+
+impl fmt::Display for SamplerKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for SamplerKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<SamplerKind, String> {
+        SamplerKind::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.name() == name)
+            .ok_or_else(|| {
+                format!(
+                    "unknown sampler `{}`, expected independent, stratified, halton or sobol",
+                    name
+                )
+            })
+    }
+}
+
+/// Hashes a pixel sample's coordinates and dimension into a seed.
+fn hash(values: &[u64]) -> u64 {
+    values
+        .iter()
+        .fold(0, |hash, &value| mix_bits(hash ^ mix_bits(value)))
+}
+
+/// Plain random numbers, each sample with its own generator.
+pub struct IndependentSampler {
+    seed: u64,
+    rng: Rng,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> IndependentSampler {
+        IndependentSampler {
+            seed,
+            rng: Rng::new(seed),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.rng = Rng::for_sample(self.seed, x, y, index);
+    }
+
+    fn next_1d(&mut self) -> Num {
+        self.rng.num()
+    }
+
+    fn next_2d(&mut self) -> (Num, Num) {
+        (self.rng.num(), self.rng.num())
+    }
+}
+
+/// Splits every dimension into as many strata as there are samples (a grid
+/// of `n * n` for two dimensions) and jitters within them. Which sample
+/// lands in which stratum is shuffled independently per dimension, so the
+/// dimensions don't correlate.
+pub struct StratifiedSampler {
+    seed: u64,
+    samples_per_pixel: usize,
+    pixel: (usize, usize),
+    index: usize,
+    dimension: u64,
+    rng: Rng,
+}
+
+impl StratifiedSampler {
+    pub fn new(seed: u64, samples_per_pixel: usize) -> StratifiedSampler {
+        StratifiedSampler {
+            seed,
+            samples_per_pixel: samples_per_pixel.max(1),
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0,
+            rng: Rng::new(seed),
+        }
+    }
+
+    /// The shuffled stratum of the current sample among `count`.
+    fn stratum(&mut self, count: usize) -> usize {
+        let (x, y) = self.pixel;
+        let permutation = hash(&[self.seed, x as u64, y as u64, self.dimension]);
+        self.dimension += 1;
+        permutation_element(self.index % count, count, permutation)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.pixel = (x, y);
+        self.index = index;
+        self.dimension = 0;
+        self.rng = Rng::for_sample(self.seed, x, y, index);
+    }
+
+    fn next_1d(&mut self) -> Num {
+        let count = self.samples_per_pixel;
+        let stratum = self.stratum(count);
+        (stratum as Num + self.rng.num()) / count as Num
+    }
+
+    fn next_2d(&mut self) -> (Num, Num) {
+        let side = Num::sqrt(self.samples_per_pixel as Num) as usize;
+        let stratum = self.stratum(side * side);
+        let (sx, sy) = (stratum % side, stratum / side);
+        (
+            (sx as Num + self.rng.num()) / side as Num,
+            (sy as Num + self.rng.num()) / side as Num,
+        )
+    }
+}
+
+/// Element `index` of a random permutation of `0..count` picked by `seed`
+/// (Kensler, "Correlated Multi-Jittered Sampling").
+pub fn permutation_element(index: usize, count: usize, seed: u64) -> usize {
+    let (l, p) = (count as u32, seed as u32);
+    let mut w = l - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    let mut i = index as u32;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            break;
+        }
+    }
+    ((i + p % l) % l) as usize
+}
+
+const PRIMES: [u64; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+/// The Halton sequence, dimension `d` being the radical inverse in the
+/// `d`th prime. Every pixel sees the same points, shifted by a random
+/// offset per dimension (a Cranley-Patterson rotation). Dimensions past the
+/// last prime fall back to random numbers.
+pub struct HaltonSampler {
+    seed: u64,
+    pixel: (usize, usize),
+    index: u64,
+    dimension: usize,
+    rng: Rng,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> HaltonSampler {
+        HaltonSampler {
+            seed,
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0,
+            rng: Rng::new(seed),
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.pixel = (x, y);
+        self.index = index as u64;
+        self.dimension = 0;
+        self.rng = Rng::for_sample(self.seed, x, y, index);
+    }
+
+    fn next_1d(&mut self) -> Num {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        match PRIMES.get(dimension) {
+            Some(&base) => {
+                let (x, y) = self.pixel;
+                let shift = hash(&[self.seed, x as u64, y as u64, dimension as u64]);
+                let shift = (shift >> 11) as Num * (1.0 / (1u64 << 53) as Num);
+                let value = radical_inverse(base, self.index) + shift;
+                if value >= 1.0 {
+                    value - 1.0
+                } else {
+                    value
+                }
+            }
+            None => self.rng.num(),
+        }
+    }
+
+    fn next_2d(&mut self) -> (Num, Num) {
+        (self.next_1d(), self.next_1d())
+    }
+}
+
+/// `index`'s digits in `base` mirrored around the radix point.
+pub fn radical_inverse(base: u64, mut index: u64) -> Num {
+    let inverse_base = 1.0 / base as Num;
+    let mut reversed = 0;
+    let mut scale = 1.0;
+    while index > 0 {
+        reversed = reversed * base + index % base;
+        scale *= inverse_base;
+        index /= base;
+    }
+    Num::min(reversed as Num * scale, 1.0 - Num::EPSILON)
+}
+
+/// The first two dimensions of the Sobol sequence, Owen scrambled with
+/// hashing (Burley, "Practical Hash-based Owen Scrambling"). Every draw
+/// uses a fresh pair of scrambles and its own shuffle of the sample order,
+/// so any number of dimensions keep the stratification of the 2D points.
+pub struct SobolSampler {
+    seed: u64,
+    pixel: (usize, usize),
+    index: u32,
+    dimension: u64,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> SobolSampler {
+        SobolSampler {
+            seed,
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    /// The scrambled index and the scramble seeds for the next dimension.
+    fn next_dimension(&mut self) -> (u32, u64) {
+        let (x, y) = self.pixel;
+        let seed = hash(&[self.seed, x as u64, y as u64, self.dimension]);
+        self.dimension += 1;
+        (nested_uniform_scramble(self.index, seed as u32), seed)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.pixel = (x, y);
+        self.index = index as u32;
+        self.dimension = 0;
+    }
+
+    fn next_1d(&mut self) -> Num {
+        let (index, seed) = self.next_dimension();
+        let scramble = (seed >> 32) as u32;
+        to_unit(nested_uniform_scramble(index.reverse_bits(), scramble))
+    }
+
+    fn next_2d(&mut self) -> (Num, Num) {
+        let (index, seed) = self.next_dimension();
+        let scramble = mix_bits(seed);
+        (
+            to_unit(nested_uniform_scramble(
+                index.reverse_bits(),
+                scramble as u32,
+            )),
+            to_unit(nested_uniform_scramble(
+                sobol_second_dimension(index),
+                (scramble >> 32) as u32,
+            )),
+        )
+    }
+}
+
+/// The Sobol sequence's second dimension, from the polynomial `x + 1`.
+fn sobol_second_dimension(mut index: u32) -> u32 {
+    let mut direction = 1u32 << 31;
+    let mut result = 0;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+    result
+}
+
+/// Owen scrambling: flips every bit depending on the bits above it.
+fn nested_uniform_scramble(value: u32, seed: u32) -> u32 {
+    laine_karras_permutation(value.reverse_bits(), seed).reverse_bits()
+}
+
+fn laine_karras_permutation(mut value: u32, seed: u32) -> u32 {
+    value = value.wrapping_add(seed);
+    value ^= value.wrapping_mul(0x6c50b47c);
+    value ^= value.wrapping_mul(0xb82f1e52);
+    value ^= value.wrapping_mul(0xc7afe638);
+    value ^= value.wrapping_mul(0x8d22f6e6);
+    value
+}
+
+fn to_unit(value: u32) -> Num {
+    value as Num * (1.0 / 4_294_967_296.0)
+}
+
+/// Uniform on the unit sphere.
+pub fn sample_unit_sphere((u1, u2): (Num, Num)) -> Vec3 {
+    let z = 1.0 - 2.0 * u1;
+    let r = Num::sqrt(Num::max(0.0, 1.0 - z * z));
+    let phi = 2.0 * PI * u2;
+    Vec3::new(r * Num::cos(phi), r * Num::sin(phi), z)
+}
+
+/// Uniform in the unit ball.
+pub fn sample_unit_ball(direction: (Num, Num), radius: Num) -> Vec3 {
+    sample_unit_sphere(direction) * Num::cbrt(radius)
+}
+
+/// Uniform on the unit disk in the xy plane, keeping strata compact
+/// (Shirley and Chiu's concentric mapping).
+pub fn sample_unit_disk((u1, u2): (Num, Num)) -> Vec3 {
+    let (a, b) = (2.0 * u1 - 1.0, 2.0 * u2 - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return Vec3::zero();
+    }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, PI / 4.0 * (b / a))
+    } else {
+        (b, PI / 2.0 - PI / 4.0 * (a / b))
+    };
+    Vec3::new(r * Num::cos(theta), r * Num::sin(theta), 0)
+}
+
+#[cfg(test)]
+mod test_sampler {
+    use super::*;
+
+    /// The `dimension`th 2D draw of each of a pixel's first `count` samples.
+    fn samples_2d(kind: SamplerKind, count: usize, dimension: usize) -> Vec<(Num, Num)> {
+        let mut sampler = kind.create(11, count);
+        (0..count)
+            .map(|index| {
+                sampler.start_sample(3, 5, index);
+                for _ in 0..dimension {
+                    sampler.next_2d();
+                }
+                sampler.next_2d()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_deterministic_and_in_range() {
+        for &kind in &SamplerKind::ALL {
+            let draws = |seed| {
+                let mut sampler = kind.create(seed, 16);
+                let mut draws = Vec::new();
+                for index in 0..20 {
+                    sampler.start_sample(2, 9, index);
+                    for _ in 0..40 {
+                        let (u, v) = sampler.next_2d();
+                        draws.extend_from_slice(&[u, v, sampler.next_1d()]);
+                    }
+                }
+                draws
+            };
+            let first = draws(7);
+            assert!(first.iter().all(|value| (0.0..1.0).contains(value)));
+            assert_eq!(first, draws(7), "{}", kind);
+            assert_ne!(first, draws(8), "{}", kind);
+        }
+    }
+
+    #[test]
+    fn test_stratification() {
+        for &kind in &[SamplerKind::Stratified, SamplerKind::Sobol] {
+            for dimension in 0..6 {
+                let mut cells = vec![0; 16];
+                for (u, v) in samples_2d(kind, 16, dimension) {
+                    cells[(u * 4.0) as usize + 4 * (v * 4.0) as usize] += 1;
+                }
+                assert_eq!(cells, vec![1; 16], "{} dimension {}", kind, dimension);
+            }
+        }
+    }
+
+    #[test]
+    fn test_radical_inverse() {
+        assert_eq!(radical_inverse(2, 0), 0.0);
+        assert_eq!(radical_inverse(2, 1), 0.5);
+        assert_eq!(radical_inverse(2, 6), 0.375);
+        assert!((radical_inverse(3, 5) - 7.0 / 9.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_permutation_element() {
+        for &count in &[1, 5, 16, 100] {
+            let mut seen: Vec<usize> = (0..count)
+                .map(|index| permutation_element(index, count, 1234))
+                .collect();
+            seen.sort_unstable();
+            assert_eq!(seen, (0..count).collect::<Vec<_>>());
+        }
+    }
+
+    /// Low discrepancy points integrate a smooth function better than random ones.
+    #[test]
+    fn test_integration_error() {
+        let error = |kind: SamplerKind| {
+            let mut sampler = kind.create(3, 64);
+            let mut total = 0.0;
+            for pixel in 0..50 {
+                let mut sum = 0.0;
+                for index in 0..64 {
+                    sampler.start_sample(pixel, 0, index);
+                    sampler.next_2d();
+                    let (u, v) = sampler.next_2d();
+                    sum += Num::sin(PI * u) * v * v;
+                }
+                // the integral is 2/pi * 1/3
+                total += (sum / 64.0 - 2.0 / (3.0 * PI)).abs();
+            }
+            total / 50.0
+        };
+        let independent = error(SamplerKind::Independent);
+        for &kind in &[
+            SamplerKind::Stratified,
+            SamplerKind::Halton,
+            SamplerKind::Sobol,
+        ] {
+            assert!(error(kind) < independent / 2.0, "{}", kind);
+        }
+    }
+
+    #[test]
+    fn test_warps() {
+        let mut sampler = SamplerKind::Sobol.create(0, 256);
+        for index in 0..256 {
+            sampler.start_sample(0, 0, index);
+            let sphere = sample_unit_sphere(sampler.next_2d());
+            assert!((sphere.magnitude() - 1.0).abs() < 1e-9);
+            assert!(sample_unit_disk(sampler.next_2d()).magnitude() <= 1.0 + 1e-9);
+            let ball = sample_unit_ball(sampler.next_2d(), sampler.next_1d());
+            assert!(ball.magnitude() <= 1.0);
+        }
+    }
+
+    #[test]
+    fn test_from_str() {
+        for &kind in &SamplerKind::ALL {
+            assert_eq!(kind.name().parse::<SamplerKind>(), Ok(kind));
+        }
+        assert!("owen".parse::<SamplerKind>().is_err());
+    }
+}
@@ -0,0 +1,1758 @@
+//! Scene description files.
+//!
+//! Scenes are written in TOML. Every section is optional:
+//!
+//! ```toml
+//! [render]                    # defaults for the command line settings
+//! width = 800
+//! height = 450
+//! samples = 64
+//! max_depth = 20
+//! seed = 0
+//! sampler = "sobol"           # independent, stratified, halton or sobol
+//! max_samples = 512           # keep sampling noisy pixels, adaptively
+//! noise_threshold = 0.02      # relative error adaptive sampling stops at
+//! filter = "gaussian"         # box, tent, gaussian, mitchell or lanczos
+//! filter_radius = 1.5         # in pixels, defaults to the filter's usual one
+//!
+//! [camera]
+//! look_from = [13, 2, 3]
+//! look_at = [0, 0, 0]
+//! vup = [0, 1, 0]
+//! fov = 20                    # vertical, in degrees
+//! aperture = 0.1
+//! focus_dist = 10
+//! shutter_open = 0            # moving objects blur between these times
+//! shutter_close = 1
+//!
+//! [display]                   # how .png and .ppm output is tone mapped
+//! exposure = 0                # in stops
+//! tonemap = "aces"            # clamp, reinhard, reinhard-extended, hable or aces
+//! white_point = 4             # luminance reinhard-extended maps to white
+//!
+//! [background]                # sky, black, constant or gradient
+//! type = "constant"
+//! color = [0.1, 0.1, 0.1]
+//!
+//! [atmosphere]                # haze filling the whole scene, hiding the background
+//! density = 0.01
+//! albedo = [1, 1, 1]
+//! anisotropy = 0.5            # -1 scatters light back, 1 forwards, 0 evenly
+//!
+//! [materials.ground]          # lambertian, metal, conductor, dielectric, principled, volume or diffuse_light
+//! type = "lambertian"
+//! albedo = { type = "checker", scale = 0.5, even = [0.2, 0.3, 0.1], odd = [0.9, 0.9, 0.9] }
+//!
+//! [materials.gold]
+//! type = "conductor"
+//! metal = "gold"              # gold, copper, aluminium or silver, or give eta and k
+//! roughness = 0.3             # 0 is a mirror, 1 is very rough
+//! distribution = "ggx"        # ggx or beckmann
+//!
+//! [materials.bottle]
+//! type = "dielectric"
+//! refraction_index = 1.5
+//! roughness = 0.1             # frosted, with the distribution as for conductors
+//! absorption = [0.8, 0.1, 0.6] # lost inside per unit of distance, colors by thickness
+//!
+//! [materials.paint]           # any of the settings, defaulting to Blender's
+//! type = "principled"
+//! base_color = [0.8, 0.1, 0.1]
+//! metallic = 0
+//! roughness = 0.5
+//! specular = 0.5
+//! specular_tint = 0
+//! sheen = 0
+//! sheen_tint = 0.5
+//! clearcoat = 1
+//! clearcoat_roughness = 0.03
+//! transmission = 0
+//! anisotropic = 0
+//! refraction_index = 1.45
+//!
+//! [materials.smoke]           # for objects with a density
+//! type = "volume"
+//! albedo = [0.2, 0.2, 0.2]
+//! anisotropy = 0
+//!
+//! [[objects]]                 # sphere, triangle, quad, box, plane, mesh or grid
+//! type = "sphere"
+//! center = [0, 1, 0]
+//! end_center = [0, 1.5, 0]    # spheres can move, over the motion_time
+//! radius = 1
+//! material = { type = "dielectric", refraction_index = 1.5 }
+//!
+//! [[objects]]
+//! type = "mesh"
+//! path = "bunny.obj"
+//! material = "ground"
+//! transform = { scale = 2, rotate = [0, 45, 0], translate = [1, 0, -2] }
+//! end_transform = { scale = 2, rotate = [0, 60, 0], translate = [1, 0, -2] }
+//!
+//! [[objects]]
+//! type = "box"
+//! min = [-1, 0, -1]
+//! max = [1, 2, 1]
+//! material = "smoke"
+//! density = 0.5               # fills the shape with smoke instead
+//!
+//! [[objects]]
+//! type = "grid"               # smoke or fire from a voxel grid file
+//! path = "fire.yvox"
+//! density_scale = 2
+//! albedo = [0.9, 0.9, 0.9]
+//! anisotropy = 0
+//! emission = 1                # how brightly the grid's temperatures glow
+//! ```
+//!
+//! Colors (`albedo`, `emit`) are either an `[r, g, b]` triple or a texture
+//! table: `solid`, `checker`, `noise` or `image`. An object's `material` is
+//! either the name of an entry in `[materials]` or an inline table. Relative
+//! paths of meshes and images are resolved against the scene file's
+//! directory. Spheres and quads with a `diffuse_light` material are sampled
+//! as lights.
+//!
+//! Any object can be given a `transform`: a `scale` (one factor or one per
+//! axis), a `rotate` (degrees about x, then y, then z) and a `translate`,
+//! applied in that order. Meshes loaded from the same file with the same
+//! named material are only loaded once and shared by every object using them.
+//!
+//! Objects with an `end_transform` (and spheres with an `end_center`) move
+//! from where they start to where they end over their `motion_time`, `[0, 1]`
+//! unless given, and blur if that overlaps the camera's shutter. Moving
+//! lights still shine but aren't sampled directly. An `end_transform` can
+//! only mirror the object if its `transform` does too.
+//!
+//! Objects with a `density` are volumes of fog or smoke filling their shape,
+//! which must be convex, and scatter light with their material throughout;
+//! a `volume` material gives them an albedo and phase function. The
+//! `density` is the chance of scattering per unit of distance, after the
+//! `transform`.
+//!
+//! Grids are volumes whose density varies, read from the voxel files
+//! described in [`crate::voxels`] and scaled by `density_scale`. Their
+//! `albedo` is the part of each collision that's scattered; with an
+//! `emission`, the rest glows with the grid's temperature as a black body,
+//! at unit brightness for 1500 K times `emission`. The same file is only
+//! loaded once.
+
+use crate::bvh::*;
+use crate::filter::*;
+use crate::materials::*;
+use crate::math::transform::*;
+use crate::math::vec3::*;
+use crate::math::*;
+use crate::media::*;
+use crate::mesh::*;
+use crate::microfacet::*;
+use crate::sampler::*;
+use crate::textures::*;
+use crate::tonemap::*;
+use crate::types::*;
+use crate::voxels::*;
+use serde::de::{DeserializeOwned, Deserializer, Error as _};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The render settings a scene file asks for, `None` where it doesn't say.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SceneSettings {
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    pub samples_per_pixel: Option<usize>,
+    pub max_depth: Option<usize>,
+    pub seed: Option<u64>,
+    pub sampler: Option<SamplerKind>,
+    pub max_samples: Option<usize>,
+    pub noise_threshold: Option<Num>,
+    pub filter: Option<FilterKind>,
+    pub filter_radius: Option<Num>,
+    pub look_from: Option<Point>,
+    pub look_at: Option<Point>,
+    pub vup: Option<Vec3>,
+    pub vertical_fov: Option<Num>,
+    pub aperture: Option<Num>,
+    pub focus_dist: Option<Num>,
+    pub shutter_open: Option<Num>,
+    pub shutter_close: Option<Num>,
+    pub exposure: Option<Num>,
+    pub tone_map: Option<ToneMap>,
+    pub white_point: Option<Num>,
+}
+
+pub struct SceneDescription {
+    pub scene: Scene,
+    pub settings: SceneSettings,
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(std::io::Error),
+    /// malformed TOML, or a section of the wrong shape
+    Syntax(toml::de::Error),
+    /// an entry that parsed but can't be built, like `objects[3]`
+    Invalid {
+        entry: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(err) => write!(f, "couldn't read scene file: {}", err),
+            SceneError::Syntax(err) => write!(f, "{}", err),
+            SceneError::Invalid { entry, message } => write!(f, "{}: {}", entry, message),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<std::io::Error> for SceneError {
+    fn from(err: std::io::Error) -> SceneError {
+        SceneError::Io(err)
+    }
+}
+
+/// Loads a scene file, see the module documentation for the format.
+pub fn load_scene<P: AsRef<Path>>(path: P) -> Result<SceneDescription, SceneError> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path)?;
+    parse_scene(&source, path.parent().unwrap_or_else(|| Path::new("")))
+}
+
+/// Parses a scene, resolving relative file paths against `base_dir`.
+pub fn parse_scene(source: &str, base_dir: &Path) -> Result<SceneDescription, SceneError> {
+    let file: SceneFile = toml::from_str(source).map_err(SceneError::Syntax)?;
+    let mut builder = Builder {
+        base_dir,
+        meshes: BTreeMap::new(),
+        grids: BTreeMap::new(),
+    };
+
+    let settings = file.settings()?;
+
+    let mut materials = BTreeMap::new();
+    for (name, entry) in file.materials {
+        let material = builder
+            .material(entry)
+            .map_err(|message| invalid(format!("materials.{}", name), message))?;
+        materials.insert(name, material);
+    }
+
+    let mut world = HittablesList::new();
+    let mut lights = HittablesList::new();
+    for (index, object) in file.objects.into_iter().enumerate() {
+        let (object, is_light) = builder
+            .placed_object(object, &materials)
+            .map_err(|message| invalid(format!("objects[{}]", index), message))?;
+        if is_light {
+            lights.push(object.clone());
+        }
+        world.push(object);
+    }
+
+    if let Some(atmosphere) = file.atmosphere {
+        let medium = builder
+            .atmosphere(atmosphere)
+            .map_err(|message| invalid("atmosphere".to_string(), message))?;
+        world.push(Arc::new(medium));
+    }
+
+    let background = match file.background {
+        BackgroundEntry::Sky => Background::sky(),
+        BackgroundEntry::Black => Background::black(),
+        BackgroundEntry::Constant { color } => Background::Constant(vec3(color)),
+        BackgroundEntry::Gradient { bottom, top } => Background::Gradient {
+            bottom: vec3(bottom),
+            top: vec3(top),
+        },
+    };
+
+    Ok(SceneDescription {
+        scene: Scene {
+            world: Arc::new(Bvh::new(world)),
+            lights,
+            background,
+        },
+        settings,
+    })
+}
+
+fn invalid(entry: String, message: String) -> SceneError {
+    SceneError::Invalid { entry, message }
+}
+
+fn phase_function(anisotropy: Num) -> Result<PhaseFunction, String> {
+    if !(-1.0 < anisotropy && anisotropy < 1.0) {
+        return Err("anisotropy must be between -1 and 1".to_string());
+    }
+    Ok(PhaseFunction::with_anisotropy(anisotropy))
+}
+
+fn microfacet(roughness: Num, distribution: Option<String>) -> Result<Microfacet, String> {
+    if !(0.0..=1.0).contains(&roughness) {
+        return Err("roughness must be between 0 and 1".to_string());
+    }
+    let distribution = match distribution {
+        Some(name) => name.parse()?,
+        None => Distribution::Ggx,
+    };
+    Ok(Microfacet::isotropic(distribution, roughness))
+}
+
+fn vec3([x, y, z]: [Num; 3]) -> Vec3 {
+    Vec3::new(x, y, z)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    #[serde(default)]
+    render: RenderEntry,
+    #[serde(default)]
+    camera: CameraEntry,
+    #[serde(default)]
+    display: DisplayEntry,
+    #[serde(default)]
+    background: BackgroundEntry,
+    #[serde(default)]
+    materials: BTreeMap<String, MaterialEntry>,
+    #[serde(default)]
+    objects: Vec<PlacedObject>,
+    atmosphere: Option<AtmosphereEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AtmosphereEntry {
+    density: Num,
+    albedo: Option<ColorEntry>,
+    #[serde(default)]
+    anisotropy: Num,
+}
+
+impl SceneFile {
+    fn settings(&self) -> Result<SceneSettings, SceneError> {
+        let count = |name: &str, value: Option<usize>| match value {
+            Some(0) => Err(invalid(
+                format!("render.{}", name),
+                "must be positive".to_string(),
+            )),
+            _ => Ok(value),
+        };
+        let render = &self.render;
+        let camera = &self.camera;
+        let display = &self.display;
+        if render.filter_radius.is_some_and(|radius| radius <= 0.0) {
+            let message = "must be positive".to_string();
+            return Err(invalid("render.filter_radius".to_string(), message));
+        }
+        if render
+            .noise_threshold
+            .is_some_and(|threshold| threshold <= 0.0)
+        {
+            let message = "must be positive".to_string();
+            return Err(invalid("render.noise_threshold".to_string(), message));
+        }
+        if let Some(fov) = camera.fov {
+            if !(fov > 0.0 && fov < 180.0) {
+                let message = "must be between 0 and 180 degrees".to_string();
+                return Err(invalid("camera.fov".to_string(), message));
+            }
+        }
+        if camera.aperture.is_some_and(|aperture| aperture < 0.0) {
+            let message = "can't be negative".to_string();
+            return Err(invalid("camera.aperture".to_string(), message));
+        }
+        if camera.focus_dist.is_some_and(|dist| dist <= 0.0) {
+            let message = "must be positive".to_string();
+            return Err(invalid("camera.focus_dist".to_string(), message));
+        }
+        if let (Some(open), Some(close)) = (camera.shutter_open, camera.shutter_close) {
+            if open > close {
+                let message = "can't be before shutter_open".to_string();
+                return Err(invalid("camera.shutter_close".to_string(), message));
+            }
+        }
+        if display.white_point.is_some_and(|white| white <= 0.0) {
+            let message = "must be positive".to_string();
+            return Err(invalid("display.white_point".to_string(), message));
+        }
+        Ok(SceneSettings {
+            width: count("width", render.width)?,
+            height: count("height", render.height)?,
+            samples_per_pixel: count("samples", render.samples)?,
+            max_depth: count("max_depth", render.max_depth)?,
+            seed: render.seed,
+            sampler: match &render.sampler {
+                Some(name) => Some(
+                    name.parse()
+                        .map_err(|message| invalid("render.sampler".to_string(), message))?,
+                ),
+                None => None,
+            },
+            max_samples: count("max_samples", render.max_samples)?,
+            noise_threshold: render.noise_threshold,
+            filter: match &render.filter {
+                Some(name) => Some(
+                    name.parse()
+                        .map_err(|message| invalid("render.filter".to_string(), message))?,
+                ),
+                None => None,
+            },
+            filter_radius: render.filter_radius,
+            look_from: camera.look_from.map(vec3),
+            look_at: camera.look_at.map(vec3),
+            vup: camera.vup.map(vec3),
+            vertical_fov: camera.fov,
+            aperture: camera.aperture,
+            focus_dist: camera.focus_dist,
+            shutter_open: camera.shutter_open,
+            shutter_close: camera.shutter_close,
+            exposure: display.exposure,
+            tone_map: match &display.tonemap {
+                Some(name) => Some(
+                    name.parse()
+                        .map_err(|message| invalid("display.tonemap".to_string(), message))?,
+                ),
+                None => None,
+            },
+            white_point: display.white_point,
+        })
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct RenderEntry {
+    width: Option<usize>,
+    height: Option<usize>,
+    samples: Option<usize>,
+    max_depth: Option<usize>,
+    seed: Option<u64>,
+    sampler: Option<String>,
+    max_samples: Option<usize>,
+    noise_threshold: Option<Num>,
+    filter: Option<String>,
+    filter_radius: Option<Num>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct CameraEntry {
+    look_from: Option<[Num; 3]>,
+    look_at: Option<[Num; 3]>,
+    vup: Option<[Num; 3]>,
+    fov: Option<Num>,
+    aperture: Option<Num>,
+    focus_dist: Option<Num>,
+    shutter_open: Option<Num>,
+    shutter_close: Option<Num>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct DisplayEntry {
+    exposure: Option<Num>,
+    tonemap: Option<String>,
+    white_point: Option<Num>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum BackgroundEntry {
+    #[default]
+    Sky,
+    Black,
+    Constant {
+        color: [Num; 3],
+    },
+    Gradient {
+        bottom: [Num; 3],
+        top: [Num; 3],
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialEntry {
+    Lambertian {
+        albedo: ColorEntry,
+    },
+    Metal {
+        albedo: ColorEntry,
+        #[serde(default)]
+        fuzz: Num,
+    },
+    Conductor {
+        metal: Option<String>,
+        /// the complex index of refraction, when there's no `metal`
+        eta: Option<[Num; 3]>,
+        k: Option<[Num; 3]>,
+        #[serde(default)]
+        roughness: Num,
+        distribution: Option<String>,
+    },
+    Dielectric {
+        refraction_index: Num,
+        #[serde(default)]
+        roughness: Num,
+        distribution: Option<String>,
+        absorption: Option<[Num; 3]>,
+    },
+    Principled {
+        base_color: Option<ColorEntry>,
+        metallic: Option<Num>,
+        roughness: Option<Num>,
+        specular: Option<Num>,
+        specular_tint: Option<Num>,
+        sheen: Option<Num>,
+        sheen_tint: Option<Num>,
+        clearcoat: Option<Num>,
+        clearcoat_roughness: Option<Num>,
+        transmission: Option<Num>,
+        anisotropic: Option<Num>,
+        refraction_index: Option<Num>,
+    },
+    Volume {
+        albedo: ColorEntry,
+        #[serde(default)]
+        anisotropy: Num,
+    },
+    DiffuseLight {
+        emit: ColorEntry,
+    },
+}
+
+/// A material name or an inline material table.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MaterialRef {
+    Named(String),
+    Inline(toml::Table),
+}
+
+/// An `[r, g, b]` color or a texture table.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ColorEntry {
+    Color([Num; 3]),
+    Texture(toml::Table),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureEntry {
+    Solid {
+        color: [Num; 3],
+    },
+    Checker {
+        scale: Num,
+        even: ColorEntry,
+        odd: ColorEntry,
+    },
+    Noise {
+        scale: Num,
+        #[serde(default)]
+        style: NoiseStyleEntry,
+        #[serde(default = "white")]
+        color: [Num; 3],
+    },
+    Image {
+        path: PathBuf,
+    },
+}
+
+fn white() -> [Num; 3] {
+    [1.0; 3]
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+enum NoiseStyleEntry {
+    #[default]
+    Smooth,
+    Turbulence,
+    Marble,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectEntry {
+    Sphere {
+        center: [Num; 3],
+        /// where a moving sphere is at the end of `motion_time`
+        end_center: Option<[Num; 3]>,
+        radius: Num,
+        material: MaterialRef,
+    },
+    Triangle {
+        vertices: [[Num; 3]; 3],
+        normals: Option<[[Num; 3]; 3]>,
+        material: MaterialRef,
+    },
+    Quad {
+        corner: [Num; 3],
+        u: [Num; 3],
+        v: [Num; 3],
+        material: MaterialRef,
+    },
+    Box {
+        min: [Num; 3],
+        max: [Num; 3],
+        material: MaterialRef,
+    },
+    Plane {
+        point: [Num; 3],
+        normal: [Num; 3],
+        material: MaterialRef,
+    },
+    Mesh {
+        path: PathBuf,
+        material: MaterialRef,
+    },
+    Grid {
+        path: PathBuf,
+        #[serde(default = "one")]
+        density_scale: Num,
+        #[serde(default = "white")]
+        albedo: [Num; 3],
+        #[serde(default)]
+        anisotropy: Num,
+        #[serde(default)]
+        emission: Num,
+    },
+}
+
+fn one() -> Num {
+    1.0
+}
+
+/// An object with the transform placing it in the world and how it moves,
+/// if at all.
+struct PlacedObject {
+    entry: ObjectEntry,
+    transform: Option<TransformEntry>,
+    end_transform: Option<TransformEntry>,
+    /// when the motion starts and ends
+    motion_time: Option<[Num; 2]>,
+    /// fills the object with a medium this dense
+    density: Option<Num>,
+}
+
+impl<'de> Deserialize<'de> for PlacedObject {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<PlacedObject, D::Error> {
+        // every object type takes these, so they're split off before the
+        // rest of the table is read by its type
+        let mut table = toml::Table::deserialize(deserializer)?;
+        let mut transform = |key: &str| match table.remove(key) {
+            Some(value) => TransformEntry::deserialize(value)
+                .map(Some)
+                .map_err(D::Error::custom),
+            None => Ok(None),
+        };
+        let (transform, end_transform) = (transform("transform")?, transform("end_transform")?);
+        let motion_time = match table.remove("motion_time") {
+            Some(value) => Some(<[Num; 2]>::deserialize(value).map_err(D::Error::custom)?),
+            None => None,
+        };
+        let density = match table.remove("density") {
+            Some(value) => Some(Num::deserialize(value).map_err(D::Error::custom)?),
+            None => None,
+        };
+        let entry =
+            ObjectEntry::deserialize(toml::Value::Table(table)).map_err(D::Error::custom)?;
+        Ok(PlacedObject {
+            entry,
+            transform,
+            end_transform,
+            motion_time,
+            density,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TransformEntry {
+    scale: Option<ScaleEntry>,
+    /// degrees about x, then y, then z
+    rotate: Option<[Num; 3]>,
+    translate: Option<[Num; 3]>,
+}
+
+/// One factor for every axis, or one per axis.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScaleEntry {
+    Uniform(Num),
+    PerAxis([Num; 3]),
+}
+
+/// A material, and whether it gives off light.
+type BuiltMaterial = (MaterialPtr, bool);
+
+struct Builder<'a> {
+    base_dir: &'a Path,
+    /// loaded meshes by path and material name, to share between objects
+    meshes: BTreeMap<(PathBuf, String), HittablePtr>,
+    /// loaded voxel grids by path
+    grids: BTreeMap<PathBuf, Arc<VoxelGrid>>,
+}
+
+impl<'a> Builder<'a> {
+    fn path(&self, path: &Path) -> PathBuf {
+        self.base_dir.join(path)
+    }
+
+    fn material(&self, entry: MaterialEntry) -> Result<BuiltMaterial, String> {
+        Ok(match entry {
+            MaterialEntry::Lambertian { albedo } => {
+                (Arc::new(Lambertian::textured(self.texture(albedo)?)), false)
+            }
+            MaterialEntry::Metal { albedo, fuzz } => {
+                if fuzz < 0.0 {
+                    return Err("fuzz can't be negative".to_string());
+                }
+                (
+                    Arc::new(Metal::textured(self.texture(albedo)?, fuzz)),
+                    false,
+                )
+            }
+            MaterialEntry::Conductor {
+                metal,
+                eta,
+                k,
+                roughness,
+                distribution,
+            } => {
+                let (eta, k) = match (metal, eta, k) {
+                    (Some(metal), None, None) => metal.parse::<ConductorPreset>()?.eta_k(),
+                    (None, Some(eta), Some(k)) => (vec3(eta), vec3(k)),
+                    _ => return Err("give either a metal or both eta and k".to_string()),
+                };
+                let microfacet = microfacet(roughness, distribution)?;
+                (Arc::new(RoughConductor::new(eta, k, microfacet)), false)
+            }
+            MaterialEntry::Dielectric {
+                refraction_index,
+                roughness,
+                distribution,
+                absorption,
+            } => {
+                if refraction_index <= 0.0 {
+                    return Err("refraction_index must be positive".to_string());
+                }
+                let microfacet = microfacet(roughness, distribution)?;
+                let absorption = absorption.unwrap_or([0.0; 3]);
+                if absorption.iter().any(|&a| a < 0.0) {
+                    return Err("absorption can't be negative".to_string());
+                }
+                let absorption = vec3(absorption);
+                let microfacet = Some(microfacet).filter(|_| roughness > 0.0);
+                let glass = RoughDielectric::new(refraction_index, microfacet);
+                (Arc::new(glass.with_absorption(absorption)), false)
+            }
+            MaterialEntry::Principled {
+                base_color,
+                metallic,
+                roughness,
+                specular,
+                specular_tint,
+                sheen,
+                sheen_tint,
+                clearcoat,
+                clearcoat_roughness,
+                transmission,
+                anisotropic,
+                refraction_index,
+            } => {
+                let defaults = Principled::default();
+                let unit = |value: Option<Num>, default: Num, name: &str| match value {
+                    Some(value) if !(0.0..=1.0).contains(&value) => {
+                        Err(format!("{} must be between 0 and 1", name))
+                    }
+                    value => Ok(value.unwrap_or(default)),
+                };
+                let refraction_index = refraction_index.unwrap_or(defaults.refraction_index);
+                if refraction_index <= 0.0 {
+                    return Err("refraction_index must be positive".to_string());
+                }
+                let principled = Principled {
+                    base_color: match base_color {
+                        Some(entry) => self.texture(entry)?,
+                        None => defaults.base_color,
+                    },
+                    metallic: unit(metallic, defaults.metallic, "metallic")?,
+                    roughness: unit(roughness, defaults.roughness, "roughness")?,
+                    specular: unit(specular, defaults.specular, "specular")?,
+                    specular_tint: unit(specular_tint, defaults.specular_tint, "specular_tint")?,
+                    sheen: unit(sheen, defaults.sheen, "sheen")?,
+                    sheen_tint: unit(sheen_tint, defaults.sheen_tint, "sheen_tint")?,
+                    clearcoat: unit(clearcoat, defaults.clearcoat, "clearcoat")?,
+                    clearcoat_roughness: unit(
+                        clearcoat_roughness,
+                        defaults.clearcoat_roughness,
+                        "clearcoat_roughness",
+                    )?,
+                    transmission: unit(transmission, defaults.transmission, "transmission")?,
+                    anisotropic: unit(anisotropic, defaults.anisotropic, "anisotropic")?,
+                    refraction_index,
+                };
+                (Arc::new(principled), false)
+            }
+            MaterialEntry::Volume { albedo, anisotropy } => {
+                let phase = phase_function(anisotropy)?;
+                let albedo = self.texture(albedo)?;
+                (Arc::new(VolumeScatter::textured(albedo, phase)), false)
+            }
+            MaterialEntry::DiffuseLight { emit } => {
+                let emit = self.texture(emit)?;
+                (Arc::new(DiffuseLight { emit }), true)
+            }
+        })
+    }
+
+    fn atmosphere(&self, entry: AtmosphereEntry) -> Result<ConstantMedium, String> {
+        if entry.density <= 0.0 {
+            return Err("density must be positive".to_string());
+        }
+        let albedo = match entry.albedo {
+            Some(albedo) => self.texture(albedo)?,
+            None => SolidColor::shared(Color::one()),
+        };
+        let phase = phase_function(entry.anisotropy)?;
+        let material = Arc::new(VolumeScatter::textured(albedo, phase));
+        Ok(ConstantMedium::everywhere(entry.density, material))
+    }
+
+    fn material_ref(
+        &self,
+        material: MaterialRef,
+        materials: &BTreeMap<String, BuiltMaterial>,
+    ) -> Result<BuiltMaterial, String> {
+        match material {
+            MaterialRef::Named(name) => materials
+                .get(&name)
+                .cloned()
+                .ok_or_else(|| format!("no material named `{}`", name)),
+            MaterialRef::Inline(table) => self.material(from_table(table)?),
+        }
+    }
+
+    fn texture(&self, entry: ColorEntry) -> Result<TexturePtr, String> {
+        let table = match entry {
+            ColorEntry::Color(color) => return Ok(SolidColor::shared(vec3(color))),
+            ColorEntry::Texture(table) => table,
+        };
+        Ok(match from_table(table)? {
+            TextureEntry::Solid { color } => SolidColor::shared(vec3(color)),
+            TextureEntry::Checker { scale, even, odd } => {
+                if scale <= 0.0 {
+                    return Err("checker scale must be positive".to_string());
+                }
+                Arc::new(Checker {
+                    scale,
+                    even: self.texture(even)?,
+                    odd: self.texture(odd)?,
+                })
+            }
+            TextureEntry::Noise {
+                scale,
+                style,
+                color,
+            } => {
+                if scale <= 0.0 {
+                    return Err("noise scale must be positive".to_string());
+                }
+                let style = match style {
+                    NoiseStyleEntry::Smooth => NoiseStyle::Smooth,
+                    NoiseStyleEntry::Turbulence => NoiseStyle::Turbulence,
+                    NoiseStyleEntry::Marble => NoiseStyle::Marble,
+                };
+                Arc::new(Noise::new(scale, style, vec3(color)))
+            }
+            TextureEntry::Image { path } => {
+                let path = self.path(&path);
+                let image = ImageTexture::load(&path)
+                    .map_err(|err| format!("{}: {}", path.display(), err))?;
+                Arc::new(image)
+            }
+        })
+    }
+
+    fn placed_object(
+        &mut self,
+        object: PlacedObject,
+        materials: &BTreeMap<String, BuiltMaterial>,
+    ) -> Result<(HittablePtr, bool), String> {
+        let [start_time, end_time] = object.motion_time.unwrap_or([0.0, 1.0]);
+        if start_time > end_time {
+            return Err("motion_time can't end before it starts".to_string());
+        }
+        if let (ObjectEntry::Grid { .. }, Some(_)) = (&object.entry, object.density) {
+            return Err("grids take a density_scale instead of a density".to_string());
+        }
+        let (built, is_light) = self.object(object.entry, (start_time, end_time), materials)?;
+        let transform = match object.transform {
+            Some(entry) => Some(self.transform(entry)?),
+            None => None,
+        };
+        let placed: (HittablePtr, bool) = match (transform, object.end_transform) {
+            (transform, Some(end)) => {
+                let motion = MotionTransform::new(
+                    transform.unwrap_or_default(),
+                    self.transform(end)?,
+                    start_time,
+                    end_time,
+                )
+                .ok_or_else(|| {
+                    "transform and end_transform must both mirror or neither".to_string()
+                })?;
+                // lights are only sampled where they stand still
+                let is_light = is_light && !motion.is_moving();
+                (Arc::new(Instance::moving(built, motion)), is_light)
+            }
+            (Some(transform), None) => (Arc::new(Instance::new(built, transform)), is_light),
+            (None, None) => (built, is_light),
+        };
+        // the medium goes around the placed shape, so its density is per
+        // unit of world space
+        Ok(match object.density {
+            Some(density) if density <= 0.0 => {
+                return Err("density must be positive".to_string());
+            }
+            Some(density) => (Arc::new(ConstantMedium::new(placed.0, density)), false),
+            None => placed,
+        })
+    }
+
+    fn transform(&self, entry: TransformEntry) -> Result<Transform, String> {
+        let mut transform = Transform::identity();
+        if let Some(scale) = entry.scale {
+            let factors = match scale {
+                ScaleEntry::Uniform(factor) => Vec3::new(factor, factor, factor),
+                ScaleEntry::PerAxis(factors) => vec3(factors),
+            };
+            if factors.x == 0.0 || factors.y == 0.0 || factors.z == 0.0 {
+                return Err("scale can't be zero".to_string());
+            }
+            transform = Transform::scale(factors);
+        }
+        if let Some([x, y, z]) = entry.rotate {
+            transform = transform
+                .then(&Transform::rotate(Vec3::unit_x(), x))
+                .then(&Transform::rotate(Vec3::unit_y(), y))
+                .then(&Transform::rotate(Vec3::unit_z(), z));
+        }
+        if let Some(offset) = entry.translate {
+            transform = transform.then(&Transform::translate(vec3(offset)));
+        }
+        Ok(transform)
+    }
+
+    /// Builds an object, moving between the times in `motion_time` if it
+    /// moves by itself.
+    fn object(
+        &mut self,
+        entry: ObjectEntry,
+        (start_time, end_time): (Num, Num),
+        materials: &BTreeMap<String, BuiltMaterial>,
+    ) -> Result<(HittablePtr, bool), String> {
+        Ok(match entry {
+            ObjectEntry::Sphere {
+                center,
+                end_center,
+                radius,
+                material,
+            } => {
+                if radius <= 0.0 {
+                    return Err("radius must be positive".to_string());
+                }
+                let (material, is_light) = self.material_ref(material, materials)?;
+                match end_center {
+                    Some(end_center) => {
+                        let sphere = MovingSphere {
+                            start_center: vec3(center),
+                            end_center: vec3(end_center),
+                            start_time,
+                            end_time,
+                            radius,
+                            material,
+                        };
+                        // moving lights aren't sampled
+                        (Arc::new(sphere), false)
+                    }
+                    None => {
+                        let sphere = Sphere {
+                            center: vec3(center),
+                            radius,
+                            material,
+                        };
+                        (Arc::new(sphere), is_light)
+                    }
+                }
+            }
+            ObjectEntry::Triangle {
+                vertices: [p1, p2, p3],
+                normals,
+                material,
+            } => {
+                let (material, _) = self.material_ref(material, materials)?;
+                let mut triangle = Triangle::new(vec3(p1), vec3(p2), vec3(p3), material);
+                if let Some([n1, n2, n3]) = normals {
+                    triangle = triangle.with_normals(vec3(n1), vec3(n2), vec3(n3));
+                }
+                (Arc::new(triangle), false)
+            }
+            ObjectEntry::Quad {
+                corner,
+                u,
+                v,
+                material,
+            } => {
+                let (u, v) = (vec3(u), vec3(v));
+                if u.cross(v).magnitude_squared() == 0.0 {
+                    return Err("u and v can't be parallel".to_string());
+                }
+                let (material, is_light) = self.material_ref(material, materials)?;
+                (Arc::new(Quad::new(vec3(corner), u, v, material)), is_light)
+            }
+            ObjectEntry::Box { min, max, material } => {
+                let (min, max) = (vec3(min), vec3(max));
+                if !(min.x < max.x && min.y < max.y && min.z < max.z) {
+                    return Err("min must be below max on every axis".to_string());
+                }
+                let (material, _) = self.material_ref(material, materials)?;
+                (Arc::new(Cube { min, max, material }), false)
+            }
+            ObjectEntry::Plane {
+                point,
+                normal,
+                material,
+            } => {
+                let normal = vec3(normal);
+                if normal.magnitude_squared() == 0.0 {
+                    return Err("normal can't be zero".to_string());
+                }
+                let (material, _) = self.material_ref(material, materials)?;
+                let plane = Plane {
+                    p1: vec3(point),
+                    normal: normal.unit_vector(),
+                    material,
+                };
+                (Arc::new(plane), false)
+            }
+            ObjectEntry::Mesh { path, material } => {
+                let path = self.path(&path);
+                // only meshes with named materials are known to be identical
+                let key = match &material {
+                    MaterialRef::Named(name) => Some((path.clone(), name.clone())),
+                    MaterialRef::Inline(_) => None,
+                };
+                if let Some(mesh) = key.as_ref().and_then(|key| self.meshes.get(key)) {
+                    return Ok((mesh.clone(), false));
+                }
+                let (material, _) = self.material_ref(material, materials)?;
+                let mesh: HittablePtr = Arc::new(
+                    load_obj(&path, material)
+                        .map_err(|err| format!("{}: {}", path.display(), err))?,
+                );
+                if let Some(key) = key {
+                    self.meshes.insert(key, mesh.clone());
+                }
+                (mesh, false)
+            }
+            ObjectEntry::Grid {
+                path,
+                density_scale,
+                albedo,
+                anisotropy,
+                emission,
+            } => {
+                if density_scale <= 0.0 {
+                    return Err("density_scale must be positive".to_string());
+                }
+                if albedo.iter().any(|a| !(0.0..=1.0).contains(a)) {
+                    return Err("albedo must be between 0 and 1".to_string());
+                }
+                if emission < 0.0 {
+                    return Err("emission can't be negative".to_string());
+                }
+                let phase = phase_function(anisotropy)?;
+                let grid = self.grid(path)?;
+                let medium = GridMedium::new(grid, density_scale, vec3(albedo), phase);
+                (Arc::new(medium.with_emission(emission)), false)
+            }
+        })
+    }
+
+    fn grid(&mut self, path: PathBuf) -> Result<Arc<VoxelGrid>, String> {
+        let path = self.path(&path);
+        if let Some(grid) = self.grids.get(&path) {
+            return Ok(grid.clone());
+        }
+        let grid =
+            Arc::new(load_grid(&path).map_err(|err| format!("{}: {}", path.display(), err))?);
+        self.grids.insert(path, grid.clone());
+        Ok(grid)
+    }
+}
+
+/// Reads an inline table as a material or texture entry.
+fn from_table<T: DeserializeOwned>(table: toml::Table) -> Result<T, String> {
+    T::deserialize(toml::Value::Table(table)).map_err(|err| err.message().to_string())
+}
+
+#[cfg(test)]
+mod test_scene_file {
+    use super::*;
+
+    fn parse(source: &str) -> Result<SceneDescription, SceneError> {
+        parse_scene(source, Path::new(""))
+    }
+
+    fn invalid_entry(source: &str) -> (String, String) {
+        match parse(source) {
+            Err(SceneError::Invalid { entry, message }) => (entry, message),
+            Err(err) => panic!("unexpected error {}", err),
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn test_empty() {
+        let description = parse("").unwrap();
+        assert_eq!(description.settings, SceneSettings::default());
+        assert!(description.scene.lights.is_empty());
+        let ray = Ray {
+            origin: Point::zero(),
+            direction: Vec3::unit_y(),
+            time: 0.0,
+        };
+        assert!(description.scene.world.hit(&ray, 0.0, INFINITY).is_none());
+        assert_eq!(
+            description.scene.background.radiance(&ray),
+            Color::new(0.5, 0.7, 1.0)
+        );
+    }
+
+    #[test]
+    fn test_scene() {
+        let description = parse(
+            r#"
+            [render]
+            width = 320
+            samples = 8
+            seed = 3
+            sampler = "stratified"
+            max_samples = 64
+            filter = "tent"
+
+            [camera]
+            look_from = [0, 0, 5]
+            fov = 40
+
+            [display]
+            exposure = -1.5
+            tonemap = "hable"
+
+            [background]
+            type = "constant"
+            color = [0.25, 0.5, 1]
+
+            [materials.light]
+            type = "diffuse_light"
+            emit = [4, 4, 4]
+
+            [materials.copper]
+            type = "conductor"
+            eta = [0.2, 0.92, 1.1]
+            k = [3.9, 2.45, 2.14]
+            roughness = 0.4
+            distribution = "beckmann"
+
+            [materials.frosted]
+            type = "dielectric"
+            refraction_index = 1.5
+            roughness = 0.2
+            absorption = [0, 0.5, 1]
+
+            [materials.paint]
+            type = "principled"
+            base_color = { type = "checker", scale = 1, even = [1, 0, 0], odd = [0, 0, 1] }
+            clearcoat = 1
+            anisotropic = 0.5
+
+            [materials.checks]
+            type = "lambertian"
+            albedo = { type = "checker", scale = 1, even = [1, 1, 1], odd = { type = "solid", color = [0, 0, 0] } }
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = "checks"
+
+            [[objects]]
+            type = "quad"
+            corner = [-1, 3, -1]
+            u = [2, 0, 0]
+            v = [0, 0, 2]
+            material = "light"
+
+            [[objects]]
+            type = "box"
+            min = [2, -1, -1]
+            max = [3, 1, 1]
+            material = { type = "metal", albedo = [0.8, 0.8, 0.8], fuzz = 0.1 }
+            "#,
+        )
+        .unwrap();
+        let settings = description.settings;
+        assert_eq!(settings.width, Some(320));
+        assert_eq!(settings.height, None);
+        assert_eq!(settings.samples_per_pixel, Some(8));
+        assert_eq!(settings.seed, Some(3));
+        assert_eq!(settings.sampler, Some(SamplerKind::Stratified));
+        assert_eq!(settings.max_samples, Some(64));
+        assert_eq!(settings.noise_threshold, None);
+        assert_eq!(settings.filter, Some(FilterKind::Tent));
+        assert_eq!(settings.filter_radius, None);
+        assert_eq!(settings.look_from, Some(Point::new(0, 0, 5)));
+        assert_eq!(settings.vertical_fov, Some(40.0));
+        assert_eq!(settings.exposure, Some(-1.5));
+        assert_eq!(settings.tone_map, Some(ToneMap::Hable));
+        assert_eq!(settings.white_point, None);
+
+        let scene = description.scene;
+        assert_eq!(scene.lights.len(), 1);
+        let ray = Ray {
+            origin: Point::new(0, 0, 5),
+            direction: -Vec3::unit_z(),
+            time: 0.0,
+        };
+        let record = scene.world.hit(&ray, 0.001, INFINITY).unwrap();
+        assert!((record.t - 4.0).abs() < 1e-9);
+        let up = Ray {
+            origin: Point::zero(),
+            direction: Vec3::unit_y(),
+            time: 0.0,
+        };
+        let record = scene.world.hit(&up, 1.5, INFINITY).unwrap();
+        assert_eq!(record.material.emitted(&up, &record), Color::new(4, 4, 4));
+        let away = Ray {
+            origin: Point::new(0, 0, 5),
+            direction: Vec3::unit_z(),
+            time: 0.0,
+        };
+        assert_eq!(scene.background.radiance(&away), Color::new(0.25, 0.5, 1.0));
+    }
+
+    #[test]
+    fn test_smooth_glass() {
+        // reflects by the exact Fresnel term, whether or not it absorbs
+        let builder = Builder {
+            base_dir: Path::new(""),
+            meshes: BTreeMap::new(),
+            grids: BTreeMap::new(),
+        };
+        let cos_theta: Num = 0.5;
+        let expected = fresnel_dielectric(cos_theta, 1.5);
+        for source in &[
+            "type = \"dielectric\"\nrefraction_index = 1.5",
+            "type = \"dielectric\"\nrefraction_index = 1.5\nabsorption = [0, 0, 0]",
+        ] {
+            let entry = from_table(toml::from_str(source).unwrap()).unwrap();
+            let (material, _) = builder.material(entry).unwrap();
+            let ray = Ray {
+                origin: Point::new(Num::sqrt(1.0 - cos_theta * cos_theta), 0.0, cos_theta),
+                direction: Vec3::new(-Num::sqrt(1.0 - cos_theta * cos_theta), 0.0, -cos_theta),
+                time: 0.0,
+            };
+            let record = HitRecord::new(Point::zero(), 1.0, &ray, Vec3::unit_z(), material.clone());
+            let mut sampler = IndependentSampler::new(5);
+            let count = 20_000;
+            let reflected = (0..count)
+                .filter(|&index| {
+                    sampler.start_sample(0, 0, index);
+                    let (scattered, _) =
+                        material.scatter(ray, record.clone(), &mut sampler).unwrap();
+                    scattered.direction.z > 0.0
+                })
+                .count();
+            let reflectance = reflected as Num / count as Num;
+            assert!(
+                (reflectance - expected).abs() < 0.01,
+                "{}: {}",
+                source,
+                reflectance
+            );
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let (entry, message) = invalid_entry(
+            r#"
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = "missing"
+            "#,
+        );
+        assert_eq!(entry, "objects[0]");
+        assert!(message.contains("missing"));
+
+        let (entry, _) = invalid_entry(
+            r#"
+            [materials.glass]
+            type = "dielectric"
+            refraction_index = -1
+            "#,
+        );
+        assert_eq!(entry, "materials.glass");
+
+        let (entry, message) = invalid_entry(
+            r#"
+            [[objects]]
+            type = "plane"
+            point = [0, 0, 0]
+            normal = [0, 1, 0]
+            material = { type = "lambertian", albedo = { type = "plaid" } }
+            "#,
+        );
+        assert_eq!(entry, "objects[0]");
+        assert!(message.contains("plaid"), "{}", message);
+
+        let (entry, message) =
+            invalid_entry("[materials.brass]\ntype = \"conductor\"\nmetal = \"brass\"");
+        assert_eq!(entry, "materials.brass");
+        assert!(message.contains("unknown metal"), "{}", message);
+        let (_, message) = invalid_entry(
+            "[materials.odd]\ntype = \"conductor\"\nmetal = \"gold\"\neta = [1, 1, 1]",
+        );
+        assert!(message.contains("either"), "{}", message);
+        let (_, message) = invalid_entry(
+            "[materials.odd]\ntype = \"conductor\"\nmetal = \"gold\"\ndistribution = \"phong\"",
+        );
+        assert!(message.contains("unknown distribution"), "{}", message);
+        let (_, message) = invalid_entry(
+            "[materials.glass]\ntype = \"dielectric\"\nrefraction_index = 1.5\nroughness = 2",
+        );
+        assert!(message.contains("roughness"), "{}", message);
+        let (_, message) = invalid_entry(
+            "[materials.glass]\ntype = \"dielectric\"\nrefraction_index = 1.5\nabsorption = [0, -1, 0]",
+        );
+        assert!(message.contains("absorption"), "{}", message);
+        let (_, message) = invalid_entry("[materials.paint]\ntype = \"principled\"\nsheen = 1.5");
+        assert!(
+            message.contains("sheen must be between 0 and 1"),
+            "{}",
+            message
+        );
+
+        let (entry, _) = invalid_entry("[camera]\nfov = 200");
+        assert_eq!(entry, "camera.fov");
+
+        let syntax = |source: &str| match parse(source) {
+            Err(SceneError::Syntax(_)) => (),
+            Err(err) => panic!("unexpected error {}", err),
+            Ok(_) => panic!("expected a syntax error"),
+        };
+        syntax("[render\nwidth = 3");
+        syntax("[render]\nwidht = 3");
+        syntax("[[objects]]\ntype = \"torus\"");
+        syntax("[[objects]]\ntype = \"box\"\nmin = [0, 0, 0]\nmax = [1, 1, 1]\nmaterial = \"m\"\ntransform = { spin = 3 }");
+    }
+
+    #[test]
+    fn test_transforms() {
+        let description = parse(
+            r#"
+            [materials.white]
+            type = "lambertian"
+            albedo = [1, 1, 1]
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = "white"
+            transform = { scale = 2, translate = [0, 0, -10] }
+
+            [[objects]]
+            type = "box"
+            min = [-0.5, -0.5, -0.5]
+            max = [0.5, 0.5, 0.5]
+            material = "white"
+            transform = { scale = [1, 4, 1], rotate = [0, 45, 0], translate = [5, 0, 0] }
+            "#,
+        )
+        .unwrap();
+        let world = description.scene.world;
+        let ray = |origin: Point, direction: Vec3| Ray {
+            origin,
+            direction,
+            time: 0.0,
+        };
+        let record = world
+            .hit(&ray(Point::zero(), -Vec3::unit_z()), 0.001, INFINITY)
+            .unwrap();
+        assert!((record.t - 8.0).abs() < 1e-9);
+        assert!((record.normal - Vec3::unit_z()).magnitude() < 1e-9);
+        // the box is turned on its corner and stretched to 4 units tall
+        let record = world
+            .hit(
+                &ray(Point::new(5, 1.9, 3), -Vec3::unit_z()),
+                0.001,
+                INFINITY,
+            )
+            .unwrap();
+        assert!((record.t - (3.0 - Num::sqrt(0.5))).abs() < 1e-9);
+        assert!(world
+            .hit(
+                &ray(Point::new(5, 2.1, 3), -Vec3::unit_z()),
+                0.001,
+                INFINITY
+            )
+            .is_none());
+
+        let (entry, message) = invalid_entry(
+            r#"
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = { type = "lambertian", albedo = [1, 1, 1] }
+            transform = { scale = [1, 0, 1] }
+            "#,
+        );
+        assert_eq!(entry, "objects[0]");
+        assert!(message.contains("scale"), "{}", message);
+    }
+
+    #[test]
+    fn test_motion() {
+        let description = parse(
+            r#"
+            [camera]
+            shutter_open = 0.5
+            shutter_close = 1.5
+
+            [materials.light]
+            type = "diffuse_light"
+            emit = [1, 1, 1]
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, -5]
+            end_center = [4, 0, -5]
+            radius = 1
+            material = "light"
+
+            [[objects]]
+            type = "box"
+            min = [-0.5, -0.5, -0.5]
+            max = [0.5, 0.5, 0.5]
+            material = "light"
+            end_transform = { translate = [0, 10, 0] }
+            motion_time = [1, 2]
+
+            [[objects]]
+            type = "quad"
+            corner = [-1, 5, -1]
+            u = [2, 0, 0]
+            v = [0, 0, 2]
+            material = "light"
+            transform = { translate = [0, 1, 0] }
+            end_transform = { translate = [0, 1, 0] }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(description.settings.shutter_open, Some(0.5));
+        assert_eq!(description.settings.shutter_close, Some(1.5));
+        // only the light that doesn't move is sampled
+        assert_eq!(description.scene.lights.len(), 1);
+        let world = description.scene.world;
+        let ray = |x: Num, y: Num, time: Num| Ray {
+            origin: Point::new(x, y, 0),
+            direction: -Vec3::unit_z(),
+            time,
+        };
+        assert!(world.hit(&ray(0.0, 0.0, 0.0), 0.001, INFINITY).unwrap().t < 4.5);
+        assert!(world.hit(&ray(4.0, 0.0, 1.0), 0.001, INFINITY).is_some());
+        assert!(world.hit(&ray(4.0, 0.0, 0.5), 0.001, INFINITY).is_none());
+        // the box waits until its motion starts
+        let record = world.hit(&ray(0.0, 0.0, 1.0), 0.001, INFINITY).unwrap();
+        assert!((record.t - 0.5).abs() < 1e-9);
+        assert!(world.hit(&ray(0.0, 5.0, 1.5), 0.001, INFINITY).is_some());
+
+        let (entry, _) = invalid_entry("[camera]\nshutter_open = 1\nshutter_close = 0");
+        assert_eq!(entry, "camera.shutter_close");
+        let (entry, message) = invalid_entry(
+            r#"
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = { type = "lambertian", albedo = [1, 1, 1] }
+            motion_time = [2, 1]
+            "#,
+        );
+        assert_eq!(entry, "objects[0]");
+        assert!(message.contains("motion_time"), "{}", message);
+        let (entry, message) = invalid_entry(
+            r#"
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = { type = "lambertian", albedo = [1, 1, 1] }
+            end_transform = { scale = [-1, 1, 1] }
+            "#,
+        );
+        assert_eq!(entry, "objects[0]");
+        assert!(message.contains("mirror"), "{}", message);
+    }
+
+    #[test]
+    fn test_media() {
+        let description = parse(
+            r#"
+            [materials.smoke]
+            type = "volume"
+            albedo = [0.5, 0.5, 0.5]
+            anisotropy = 0.3
+
+            [[objects]]
+            type = "box"
+            min = [-1, -1, -1]
+            max = [1, 1, 1]
+            material = "smoke"
+            transform = { scale = 2 }
+            density = 0.25
+
+            [[objects]]
+            type = "sphere"
+            center = [0, 10, 0]
+            radius = 1
+            material = { type = "diffuse_light", emit = [1, 1, 1] }
+            density = 1
+            "#,
+        )
+        .unwrap();
+        // glowing gas isn't sampled as a light
+        assert!(description.scene.lights.is_empty());
+        // the density is per unit of world space, so a quarter over the
+        // scaled box's four units
+        let world = description.scene.world;
+        let mut sampler = IndependentSampler::new(1);
+        let count = 10_000;
+        let through = (0..count)
+            .filter(|_| {
+                let (x, y) = sampler.next_2d();
+                let ray = Ray {
+                    origin: Point::new(x * 2.0 - 1.0, y * 2.0 - 1.0, 5.0),
+                    direction: -Vec3::unit_z(),
+                    time: 0.0,
+                };
+                world
+                    .sample_hit(&ray, 0.001, INFINITY, &mut sampler)
+                    .is_none()
+            })
+            .count();
+        let transmittance = through as Num / count as Num;
+        assert!(
+            (transmittance - Num::exp(-1.0)).abs() < 2e-2,
+            "{}",
+            transmittance
+        );
+
+        // an atmosphere is everywhere
+        let description = parse("[atmosphere]\ndensity = 0.5\nanisotropy = -0.2").unwrap();
+        let ray = Ray {
+            origin: Point::zero(),
+            direction: Vec3::unit_x(),
+            time: 0.0,
+        };
+        let world = description.scene.world;
+        assert!(world
+            .sample_hit(&ray, 0.001, INFINITY, &mut sampler)
+            .is_some());
+
+        let (entry, message) = invalid_entry(
+            r#"
+            [[objects]]
+            type = "sphere"
+            center = [0, 0, 0]
+            radius = 1
+            material = { type = "volume", albedo = [1, 1, 1] }
+            density = 0
+            "#,
+        );
+        assert_eq!(entry, "objects[0]");
+        assert!(message.contains("density"), "{}", message);
+        let (entry, message) = invalid_entry(
+            "[materials.smoke]\ntype = \"volume\"\nalbedo = [1, 1, 1]\nanisotropy = 1",
+        );
+        assert_eq!(entry, "materials.smoke");
+        assert!(message.contains("anisotropy"), "{}", message);
+        let (entry, _) = invalid_entry("[atmosphere]\ndensity = -1");
+        assert_eq!(entry, "atmosphere");
+    }
+
+    #[test]
+    fn test_mesh_paths() {
+        let dir = std::env::temp_dir().join("yart_test_scene_file");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("tri.obj"), "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+        let scene_path = dir.join("scene.toml");
+        std::fs::write(
+            &scene_path,
+            r#"
+            [[objects]]
+            type = "mesh"
+            path = "tri.obj"
+            material = { type = "lambertian", albedo = [0.5, 0.5, 0.5] }
+            "#,
+        )
+        .unwrap();
+        let description = load_scene(&scene_path).unwrap();
+        let ray = Ray {
+            origin: Point::new(0.25, 0.25, 1),
+            direction: -Vec3::unit_z(),
+            time: 0.0,
+        };
+        assert!(description.scene.world.hit(&ray, 0.001, INFINITY).is_some());
+
+        // the same mesh with the same named material is only loaded once
+        let mut builder = Builder {
+            base_dir: &dir,
+            meshes: BTreeMap::new(),
+            grids: BTreeMap::new(),
+        };
+        let mut materials = BTreeMap::new();
+        materials.insert(
+            "grey".to_string(),
+            builder
+                .material(
+                    from_table(
+                        toml::from_str("type = \"lambertian\"\nalbedo = [0.5, 0.5, 0.5]").unwrap(),
+                    )
+                    .unwrap(),
+                )
+                .unwrap(),
+        );
+        let mut mesh = |source: &str| {
+            let entry = from_table(toml::from_str(source).unwrap()).unwrap();
+            builder.object(entry, (0.0, 1.0), &materials).unwrap().0
+        };
+        let named = "type = \"mesh\"\npath = \"tri.obj\"\nmaterial = \"grey\"";
+        let inline = "type = \"mesh\"\npath = \"tri.obj\"\nmaterial = { type = \"lambertian\", albedo = [1, 1, 1] }";
+        let first = mesh(named);
+        assert!(Arc::ptr_eq(&first, &mesh(named)));
+        assert!(!Arc::ptr_eq(&first, &mesh(inline)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_grids() {
+        let dir = std::env::temp_dir().join("yart_test_grids");
+        std::fs::create_dir_all(&dir).unwrap();
+        let bounds = Aabb::new(Point::new(-1, -1, -1), Point::new(1, 1, 1));
+        let grid = VoxelGrid::new([1, 1, 1], bounds, vec![0.5], Some(vec![2000.0])).unwrap();
+        std::fs::write(dir.join("fire.yvox"), grid.to_bytes()).unwrap();
+        std::fs::write(dir.join("broken.yvox"), b"YVOX").unwrap();
+        let scene_path = dir.join("scene.toml");
+        std::fs::write(
+            &scene_path,
+            r#"
+            [[objects]]
+            type = "grid"
+            path = "fire.yvox"
+            density_scale = 2
+            albedo = [0.5, 0.5, 0.5]
+            emission = 1
+            transform = { translate = [0, 0, -3] }
+            "#,
+        )
+        .unwrap();
+        let description = load_scene(&scene_path).unwrap();
+        // glowing grids aren't sampled as lights
+        assert!(description.scene.lights.is_empty());
+        let world = description.scene.world;
+        assert_eq!(
+            world.bounding_box(),
+            Some(Aabb::new(Point::new(-1, -1, -4), Point::new(1, 1, -2)))
+        );
+        // twice as dense across two units
+        let mut sampler = IndependentSampler::new(1);
+        let count = 10_000;
+        let mut through = 0;
+        for _ in 0..count {
+            let (x, y) = sampler.next_2d();
+            let ray = Ray {
+                origin: Point::new(x * 2.0 - 1.0, y * 2.0 - 1.0, 0.0),
+                direction: -Vec3::unit_z(),
+                time: 0.0,
+            };
+            match world.sample_hit(&ray, 0.001, INFINITY, &mut sampler) {
+                Some(record) => {
+                    let emitted = record.material.emitted(&ray, &record);
+                    assert!((emitted - blackbody(2000.0) * 0.5).magnitude() < 1e-9);
+                }
+                None => through += 1,
+            }
+        }
+        let transmittance = through as Num / count as Num;
+        assert!(
+            (transmittance - Num::exp(-2.0)).abs() < 1e-2,
+            "{}",
+            transmittance
+        );
+
+        // the same file is only loaded once
+        let mut builder = Builder {
+            base_dir: &dir,
+            meshes: BTreeMap::new(),
+            grids: BTreeMap::new(),
+        };
+        let first = builder.grid(PathBuf::from("fire.yvox")).unwrap();
+        assert!(Arc::ptr_eq(
+            &first,
+            &builder.grid(PathBuf::from("fire.yvox")).unwrap()
+        ));
+
+        let invalid = |object: &str| {
+            let source = format!("[[objects]]\ntype = \"grid\"\n{}", object);
+            match parse_scene(&source, &dir) {
+                Err(SceneError::Invalid { entry, message }) => {
+                    assert_eq!(entry, "objects[0]");
+                    message
+                }
+                Err(err) => panic!("unexpected error {}", err),
+                Ok(_) => panic!("expected an error"),
+            }
+        };
+        let message = invalid("path = \"missing.yvox\"");
+        assert!(message.contains("missing.yvox"), "{}", message);
+        let message = invalid("path = \"broken.yvox\"");
+        assert!(message.contains("bad voxel grid"), "{}", message);
+        let message = invalid("path = \"fire.yvox\"\ndensity_scale = 0");
+        assert!(message.contains("density_scale"), "{}", message);
+        let message = invalid("path = \"fire.yvox\"\nalbedo = [2, 1, 1]");
+        assert!(message.contains("albedo"), "{}", message);
+        let message = invalid("path = \"fire.yvox\"\nemission = -1");
+        assert!(message.contains("emission"), "{}", message);
+        let message = invalid("path = \"fire.yvox\"\ndensity = 1");
+        assert!(message.contains("density_scale"), "{}", message);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,485 @@
+use crate::math::vec3::*;
+use crate::math::*;
+use crate::sampler::*;
+use crate::types::*;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+pub type TexturePtr = Arc<dyn Texture + Send + Sync>;
+
+pub trait Texture {
+    /// Color at surface coordinates `(u, v)` and world `position`.
+    fn value(&self, u: Num, v: Num, position: Point) -> Color;
+}
+
+pub struct SolidColor {
+    pub color: Color,
+}
+
+impl SolidColor {
+    /// A solid color texture ready to be shared between materials.
+    pub fn shared(color: Color) -> TexturePtr {
+        Arc::new(SolidColor { color })
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _: Num, _: Num, _: Point) -> Color {
+        self.color
+    }
+}
+
+/// A 3D checkerboard of cubes `scale` wide, so it doesn't depend on how the
+/// surface is parameterized.
+pub struct Checker {
+    pub scale: Num,
+    pub even: TexturePtr,
+    pub odd: TexturePtr,
+}
+
+impl Texture for Checker {
+    fn value(&self, u: Num, v: Num, position: Point) -> Color {
+        let cell = |coord: Num| Num::floor(coord / self.scale) as i64;
+        if (cell(position.x) + cell(position.y) + cell(position.z)) % 2 == 0 {
+            self.even.value(u, v, position)
+        } else {
+            self.odd.value(u, v, position)
+        }
+    }
+}
+
+const PERLIN_POINTS: usize = 256;
+
+/// Gradient noise over a lattice of random unit vectors.
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    permutations: [Vec<usize>; 3],
+}
+
+impl Perlin {
+    pub fn new(rng: &mut Rng) -> Perlin {
+        let gradients = (0..PERLIN_POINTS)
+            .map(|_| sample_unit_sphere((rng.num(), rng.num())))
+            .collect();
+        let mut permutation = || {
+            let mut points: Vec<usize> = (0..PERLIN_POINTS).collect();
+            for i in (1..PERLIN_POINTS).rev() {
+                points.swap(i, rng.index(i + 1));
+            }
+            points
+        };
+        Perlin {
+            permutations: [permutation(), permutation(), permutation()],
+            gradients,
+        }
+    }
+
+    /// Smooth noise in roughly `[-1, 1]`.
+    pub fn noise(&self, position: Point) -> Num {
+        let floor = |coord: Num| Num::floor(coord);
+        let (i, j, k) = (floor(position.x), floor(position.y), floor(position.z));
+        let (u, v, w) = (position.x - i, position.y - j, position.z - k);
+        let (i, j, k) = (i as i64, j as i64, k as i64);
+        let wrap = |coord: i64| (coord & (PERLIN_POINTS as i64 - 1)) as usize;
+
+        // hermite smoothing of the interpolation weights
+        let smooth = |t: Num| t * t * (3.0 - 2.0 * t);
+        let (uu, vv, ww) = (smooth(u), smooth(v), smooth(w));
+
+        let mut sum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let gradient = self.gradients[self.permutations[0][wrap(i + di)]
+                        ^ self.permutations[1][wrap(j + dj)]
+                        ^ self.permutations[2][wrap(k + dk)]];
+                    let (fi, fj, fk) = (di as Num, dj as Num, dk as Num);
+                    let weight = Vec3::new(u - fi, v - fj, w - fk);
+                    sum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                        * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                        * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                        * gradient.dot(weight);
+                }
+            }
+        }
+        sum
+    }
+
+    /// Absolute value of the sum of `depth` octaves of noise, in `[0, 1)`-ish.
+    pub fn turbulence(&self, position: Point, depth: usize) -> Num {
+        let mut sum = 0.0;
+        let mut position = position;
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            sum += weight * self.noise(position);
+            weight *= 0.5;
+            position = position * 2.0;
+        }
+        sum.abs()
+    }
+}
+
+/// The same lattice every time, so noise textures don't change between renders.
+impl Default for Perlin {
+    fn default() -> Perlin {
+        Perlin::new(&mut Rng::new(0))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NoiseStyle {
+    /// plain perlin noise
+    Smooth,
+    /// layered octaves of noise
+    Turbulence,
+    /// sine stripes along z perturbed by turbulence
+    Marble,
+}
+
+pub struct Noise {
+    pub perlin: Perlin,
+    /// frequency of the noise in world space
+    pub scale: Num,
+    pub style: NoiseStyle,
+    pub color: Color,
+}
+
+impl Noise {
+    pub fn new(scale: Num, style: NoiseStyle, color: Color) -> Noise {
+        Noise {
+            perlin: Perlin::default(),
+            scale,
+            style,
+            color,
+        }
+    }
+}
+
+impl Texture for Noise {
+    fn value(&self, _: Num, _: Num, position: Point) -> Color {
+        let position = position * self.scale;
+        let intensity = match self.style {
+            NoiseStyle::Smooth => 0.5 * (1.0 + self.perlin.noise(position)),
+            NoiseStyle::Turbulence => self.perlin.turbulence(position, 7),
+            NoiseStyle::Marble => {
+                0.5 * (1.0 + Num::sin(position.z + 10.0 * self.perlin.turbulence(position, 7)))
+            }
+        };
+        self.color * intensity
+    }
+}
+
+/// An image mapped over `[0, 1]` surface coordinates, repeating outside it.
+/// Pixels are stored as linear colors.
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    /// rows from the top of the image down
+    pixels: Vec<Color>,
+}
+
+impl ImageTexture {
+    /// # Panics
+    ///
+    /// Panics if there isn't exactly `width * height` pixels.
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> ImageTexture {
+        assert_eq!(pixels.len(), width * height);
+        ImageTexture {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Loads a PNG, or a binary (P6) or plain (P3) PPM, decoding sRGB values
+    /// to linear ones.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ImageTexture, ImageError> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)?;
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("png") => decode_png(&bytes),
+            Some("ppm") => decode_ppm(&bytes),
+            _ => Err(ImageError::Unsupported(path.display().to_string())),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: Num, v: Num, _: Point) -> Color {
+        if self.pixels.is_empty() {
+            // cyan, so missing images are obvious
+            return Color::new(0, 1, 1);
+        }
+        let wrap = |coord: Num| coord - Num::floor(coord);
+        // v goes up, rows go down
+        let (u, v) = (wrap(u), 1.0 - wrap(v));
+        let column = usize::min((u * self.width as Num) as usize, self.width - 1);
+        let row = usize::min((v * self.height as Num) as usize, self.height - 1);
+        self.pixels[row * self.width + column]
+    }
+}
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io(std::io::Error),
+    /// the file contents are malformed
+    Decode(String),
+    /// the file type isn't one we can read
+    Unsupported(String),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Io(err) => write!(f, "couldn't read image: {}", err),
+            ImageError::Decode(message) => write!(f, "invalid image: {}", message),
+            ImageError::Unsupported(path) => write!(f, "unsupported image format: {}", path),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+impl From<std::io::Error> for ImageError {
+    fn from(err: std::io::Error) -> ImageError {
+        ImageError::Io(err)
+    }
+}
+
+/// sRGB encoded value in `[0, 1]` to linear.
+pub fn srgb_to_linear(value: Num) -> Num {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        Num::powf((value + 0.055) / 1.055, 2.4)
+    }
+}
+
+fn decode_png(bytes: &[u8]) -> Result<ImageTexture, ImageError> {
+    let decode_error = |err: png::DecodingError| ImageError::Decode(err.to_string());
+    let mut decoder = png::Decoder::new(bytes);
+    // expand palettes and low bit depths to plain 8 bit channels
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(decode_error)?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(decode_error)?;
+    let channels = info.color_type.samples();
+    let pixels = buffer[..info.buffer_size()]
+        .chunks(channels)
+        .map(|pixel| {
+            let channel = |i: usize| srgb_to_linear(pixel[i] as Num / 255.0);
+            match channels {
+                // grayscale, with or without alpha
+                1 | 2 => Color::one() * channel(0),
+                _ => Color::new(channel(0), channel(1), channel(2)),
+            }
+        })
+        .collect();
+    Ok(ImageTexture::new(
+        info.width as usize,
+        info.height as usize,
+        pixels,
+    ))
+}
+
+/// Reads the whitespace separated header fields of a PPM file.
+struct PpmHeader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> PpmHeader<'a> {
+    fn next_field(&mut self) -> Option<&'a [u8]> {
+        let bytes = self.bytes;
+        loop {
+            while self.position < bytes.len() && bytes[self.position].is_ascii_whitespace() {
+                self.position += 1;
+            }
+            // skip `#` comments
+            if self.position < bytes.len() && bytes[self.position] == b'#' {
+                while self.position < bytes.len() && bytes[self.position] != b'\n' {
+                    self.position += 1;
+                }
+            } else {
+                break;
+            }
+        }
+        let start = self.position;
+        while self.position < bytes.len() && !bytes[self.position].is_ascii_whitespace() {
+            self.position += 1;
+        }
+        if start == self.position {
+            None
+        } else {
+            Some(&bytes[start..self.position])
+        }
+    }
+
+    fn next_number(&mut self) -> Result<usize, ImageError> {
+        self.next_field()
+            .and_then(|field| std::str::from_utf8(field).ok())
+            .and_then(|field| field.parse().ok())
+            .ok_or_else(|| ImageError::Decode("expected a number".to_string()))
+    }
+}
+
+fn decode_ppm(bytes: &[u8]) -> Result<ImageTexture, ImageError> {
+    let error = |message: &str| ImageError::Decode(message.to_string());
+    let magic = &bytes[..usize::min(2, bytes.len())];
+    if magic != b"P3" && magic != b"P6" {
+        return Err(error("not a P3 or P6 ppm file"));
+    }
+    let mut header = PpmHeader { bytes, position: 2 };
+    let width = header.next_number()?;
+    let height = header.next_number()?;
+    let max_value = header.next_number()?;
+    if max_value == 0 || max_value > 65535 {
+        return Err(error("max value must be in 1..=65535"));
+    }
+    let count = width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(3))
+        .ok_or_else(|| error("image too large"))?;
+    let samples: Vec<usize> = if magic == b"P3" {
+        (0..count)
+            .map(|_| header.next_number())
+            .collect::<Result<_, _>>()?
+    } else {
+        // a single whitespace byte separates the header from the samples
+        let data = &bytes[usize::min(header.position + 1, bytes.len())..];
+        let sample_size = if max_value < 256 { 1 } else { 2 };
+        let size = count
+            .checked_mul(sample_size)
+            .ok_or_else(|| error("image too large"))?;
+        if data.len() < size {
+            return Err(error("not enough pixel data"));
+        }
+        data.chunks(sample_size)
+            .take(count)
+            .map(|sample| match sample_size {
+                1 => sample[0] as usize,
+                _ => ((sample[0] as usize) << 8) | sample[1] as usize,
+            })
+            .collect()
+    };
+    let pixels = samples
+        .chunks(3)
+        .map(|rgb| {
+            let channel = |i: usize| srgb_to_linear(rgb[i] as Num / max_value as Num);
+            Color::new(channel(0), channel(1), channel(2))
+        })
+        .collect();
+    Ok(ImageTexture::new(width, height, pixels))
+}
+
+#[cfg(test)]
+mod test_textures {
+    use super::*;
+
+    #[test]
+    fn test_checker() {
+        let checker = Checker {
+            scale: 1.0,
+            even: SolidColor::shared(Color::one()),
+            odd: SolidColor::shared(Color::zero()),
+        };
+        assert_eq!(
+            checker.value(0.0, 0.0, Point::new(0.5, 0.5, 0.5)),
+            Color::one()
+        );
+        assert_eq!(
+            checker.value(0.0, 0.0, Point::new(1.5, 0.5, 0.5)),
+            Color::zero()
+        );
+        assert_eq!(
+            checker.value(0.0, 0.0, Point::new(-0.5, 0.5, 0.5)),
+            Color::zero()
+        );
+        assert_eq!(
+            checker.value(0.0, 0.0, Point::new(1.5, 1.5, 0.5)),
+            Color::one()
+        );
+    }
+
+    #[test]
+    fn test_perlin() {
+        let perlin = Perlin::default();
+        // noise vanishes on lattice points and is continuous between them
+        assert_eq!(perlin.noise(Point::new(3, -2, 7)), 0.0);
+        let p = Point::new(1.3, 2.7, -0.4);
+        let nearby = p + Vec3::one() * 1e-6;
+        assert!((perlin.noise(p) - perlin.noise(nearby)).abs() < 1e-4);
+        let mut rng = Rng::new(1);
+        for _ in 0..1000 {
+            let noise = perlin.noise(random_vec3_rng(&mut rng, -50.0, 50.0));
+            assert!(noise.abs() <= 1.5);
+        }
+        let noise = Noise::new(4.0, NoiseStyle::Marble, Color::one());
+        let value = noise.value(0.0, 0.0, p);
+        assert!(value.x >= 0.0 && value.x <= 1.0);
+    }
+
+    #[test]
+    fn test_ppm_image() {
+        let plain = b"P3\n# two by one\n2 1\n255\n255 0 0  0 0 255\n";
+        let image = decode_ppm(plain).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 1));
+        assert_eq!(image.value(0.25, 0.5, Point::zero()), Color::new(1, 0, 0));
+        assert_eq!(image.value(0.75, 0.5, Point::zero()), Color::new(0, 0, 1));
+        // wraps around
+        assert_eq!(image.value(1.25, 0.5, Point::zero()), Color::new(1, 0, 0));
+
+        let mut binary = b"P6 1 2 255\n".to_vec();
+        binary.extend_from_slice(&[255, 255, 255, 0, 0, 0]);
+        let image = decode_ppm(&binary).unwrap();
+        // the first row is the top of the image
+        assert_eq!(image.value(0.5, 0.75, Point::zero()), Color::one());
+        assert_eq!(image.value(0.5, 0.25, Point::zero()), Color::zero());
+
+        assert!(decode_ppm(b"P6 2 2 255\n\x00").is_err());
+        assert!(decode_ppm(b"P5 1 1 255\n\x00").is_err());
+        // sizes that overflow are errors too
+        for huge in &[
+            format!("P6 {} 2 255\n", usize::MAX / 2),
+            format!("P6 {} 1 65535\n", usize::MAX / 4),
+        ] {
+            match decode_ppm(huge.as_bytes()) {
+                Err(ImageError::Decode(message)) => assert!(message.contains("too large")),
+                _ => panic!("expected a decode error"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_png_image() {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut bytes, 2, 1);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().unwrap();
+            writer
+                .write_image_data(&[255, 255, 255, 0, 128, 0])
+                .unwrap();
+        }
+        let image = decode_png(&bytes).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 1));
+        assert_eq!(image.value(0.25, 0.5, Point::zero()), Color::one());
+        let green = image.value(0.75, 0.5, Point::zero());
+        // 128 is about a fifth in linear terms
+        assert!((green.y - 0.2158605).abs() < 1e-6);
+    }
+}
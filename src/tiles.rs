@@ -0,0 +1,112 @@
+//! Splitting an image into tiles and handing them out to worker threads.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// A rectangle of pixels, `y` counting rows from the top.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tile {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Covers a `width` by `height` image with tiles of at most `size` pixels
+/// square, in rows from the top left.
+pub fn split_tiles(width: usize, height: usize, size: usize) -> Vec<Tile> {
+    let mut tiles = Vec::new();
+    for y in (0..height).step_by(size) {
+        for x in (0..width).step_by(size) {
+            tiles.push(Tile {
+                x,
+                y,
+                width: size.min(width - x),
+                height: size.min(height - y),
+            });
+        }
+    }
+    tiles
+}
+
+/// A work-stealing scheduler: every worker starts out with its own run of
+/// neighbouring tiles, and once those are done takes tiles from the back of
+/// the other workers' queues so nobody sits idle near the end.
+pub struct TileQueues {
+    queues: Vec<Mutex<VecDeque<Tile>>>,
+}
+
+impl TileQueues {
+    pub fn new(tiles: Vec<Tile>, workers: usize) -> TileQueues {
+        assert!(workers > 0);
+        let per_worker = tiles.len().div_ceil(workers);
+        let mut tiles = tiles.into_iter();
+        let queues = (0..workers)
+            .map(|_| Mutex::new(tiles.by_ref().take(per_worker).collect()))
+            .collect();
+        TileQueues { queues }
+    }
+
+    /// The next tile for `worker`, `None` once every tile was handed out.
+    pub fn next(&self, worker: usize) -> Option<Tile> {
+        if let Some(tile) = self.queues[worker].lock().unwrap().pop_front() {
+            return Some(tile);
+        }
+        let count = self.queues.len();
+        (1..count)
+            .map(|offset| (worker + offset) % count)
+            .find_map(|victim| self.queues[victim].lock().unwrap().pop_back())
+    }
+}
+
+#[cfg(test)]
+mod test_tiles {
+    use super::*;
+
+    #[test]
+    fn test_split_tiles() {
+        let tiles = split_tiles(40, 20, 16);
+        assert_eq!(tiles.len(), 6);
+        assert_eq!(
+            tiles[2],
+            Tile {
+                x: 32,
+                y: 0,
+                width: 8,
+                height: 16
+            }
+        );
+        let covered: usize = tiles.iter().map(|tile| tile.width * tile.height).sum();
+        assert_eq!(covered, 40 * 20);
+    }
+
+    #[test]
+    fn test_every_tile_once() {
+        let tiles = split_tiles(100, 70, 8);
+        let queues = TileQueues::new(tiles.clone(), 3);
+        // one worker takes everything, its own tiles first
+        let mut taken = Vec::new();
+        while let Some(tile) = queues.next(1) {
+            taken.push(tile);
+        }
+        assert_eq!(taken.len(), tiles.len());
+        assert_eq!(taken[0], tiles[tiles.len().div_ceil(3)]);
+        for tile in &tiles {
+            assert_eq!(taken.iter().filter(|&taken| taken == tile).count(), 1);
+        }
+
+        let queues = TileQueues::new(tiles.clone(), 4);
+        let taken = Mutex::new(Vec::new());
+        std::thread::scope(|scope| {
+            for worker in 0..4 {
+                let (queues, taken) = (&queues, &taken);
+                scope.spawn(move || {
+                    while let Some(tile) = queues.next(worker) {
+                        taken.lock().unwrap().push(tile);
+                    }
+                });
+            }
+        });
+        assert_eq!(taken.into_inner().unwrap().len(), tiles.len());
+    }
+}
@@ -0,0 +1,258 @@
+//! Display transforms turning linear radiance into 8 bit sRGB.
+
+use crate::math::*;
+use crate::types::*;
+use std::fmt;
+use std::str::FromStr;
+
+/// The curve squeezing radiance into the displayable [0, 1] range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToneMap {
+    /// no curve, everything above 1 is clipped
+    Clamp,
+    /// `L / (1 + L)` on the luminance
+    Reinhard,
+    /// Reinhard with luminance `white_point` mapped to 1
+    ExtendedReinhard,
+    /// John Hable's filmic curve from Uncharted 2
+    Hable,
+    /// Stephen Hill's fit of the ACES reference and sRGB output transforms
+    Aces,
+}
+
+impl ToneMap {
+    pub const ALL: [ToneMap; 5] = [
+        ToneMap::Clamp,
+        ToneMap::Reinhard,
+        ToneMap::ExtendedReinhard,
+        ToneMap::Hable,
+        ToneMap::Aces,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ToneMap::Clamp => "clamp",
+            ToneMap::Reinhard => "reinhard",
+            ToneMap::ExtendedReinhard => "reinhard-extended",
+            ToneMap::Hable => "hable",
+            ToneMap::Aces => "aces",
+        }
+    }
+}
+
+impl fmt::Display for ToneMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for ToneMap {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<ToneMap, String> {
+        ToneMap::ALL
+            .iter()
+            .copied()
+            .find(|tone_map| tone_map.name() == name)
+            .ok_or_else(|| {
+                format!(
+                    "unknown tone map `{}`, expected clamp, reinhard, reinhard-extended, hable or aces",
+                    name
+                )
+            })
+    }
+}
+
+/// How linear radiance is shown: scaled by the exposure, tone mapped and
+/// encoded with the sRGB transfer function.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisplayTransform {
+    /// in stops, each one doubles the brightness
+    pub exposure: Num,
+    pub tone_map: ToneMap,
+    /// the luminance extended Reinhard maps to white
+    pub white_point: Num,
+}
+
+impl Default for DisplayTransform {
+    fn default() -> DisplayTransform {
+        DisplayTransform {
+            exposure: 0.0,
+            tone_map: ToneMap::Clamp,
+            white_point: 4.0,
+        }
+    }
+}
+
+impl DisplayTransform {
+    /// The display referred color in [0, 1], before the sRGB encoding.
+    pub fn tone_map(&self, color: Color) -> Color {
+        let color = color * Num::powf(2.0, self.exposure);
+        let mapped = match self.tone_map {
+            ToneMap::Clamp => color,
+            ToneMap::Reinhard => scale_luminance(color, |l| l / (1.0 + l)),
+            ToneMap::ExtendedReinhard => {
+                let white_squared = self.white_point * self.white_point;
+                scale_luminance(color, |l| l * (1.0 + l / white_squared) / (1.0 + l))
+            }
+            ToneMap::Hable => {
+                let white_scale = 1.0 / hable_partial(HABLE_WHITE);
+                // the curve is meant to be fed twice the radiance
+                map_channels(color, |c| hable_partial(2.0 * c) * white_scale)
+            }
+            ToneMap::Aces => aces_fitted(color),
+        };
+        map_channels(mapped, |c| clamp_num(c, 0.0, 1.0))
+    }
+
+    /// Tone maps and encodes a linear color to 8 bit sRGB.
+    pub fn to_rgb8(&self, color: Color) -> [u8; 3] {
+        let color = self.tone_map(color);
+        let channel = |value: Num| (256.0 * clamp_num(srgb_oetf(value), 0.0, 0.999)) as u8;
+        [channel(color.x), channel(color.y), channel(color.z)]
+    }
+}
+
+/// The sRGB opto-electronic transfer function, for values in [0, 1].
+pub fn srgb_oetf(linear: Num) -> Num {
+    if linear <= 0.0031308 {
+        12.92 * linear
+    } else {
+        1.055 * Num::powf(linear, 1.0 / 2.4) - 0.055
+    }
+}
+
+fn map_channels<F: Fn(Num) -> Num>(color: Color, f: F) -> Color {
+    Color::new(f(color.x), f(color.y), f(color.z))
+}
+
+/// Scales the color so its luminance is `curve(luminance)`, keeping its hue.
+fn scale_luminance<F: Fn(Num) -> Num>(color: Color, curve: F) -> Color {
+    let luminance = color.luminance();
+    if luminance <= 0.0 {
+        return Color::zero();
+    }
+    color * (curve(luminance) / luminance)
+}
+
+/// linear radiance Hable's curve maps to white
+const HABLE_WHITE: Num = 11.2;
+
+fn hable_partial(x: Num) -> Num {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+}
+
+/// sRGB to ACES AP1 with the RRT's saturation adjustment, by rows
+const ACES_INPUT: [[Num; 3]; 3] = [
+    [0.59719, 0.35458, 0.04823],
+    [0.07600, 0.90834, 0.01566],
+    [0.02840, 0.13383, 0.83777],
+];
+/// back from the ODT's space to linear sRGB, by rows
+const ACES_OUTPUT: [[Num; 3]; 3] = [
+    [1.60475, -0.53108, -0.07367],
+    [-0.10208, 1.10813, -0.00605],
+    [-0.00327, -0.07276, 1.07602],
+];
+
+fn aces_fitted(color: Color) -> Color {
+    let multiply = |matrix: &[[Num; 3]; 3], v: Color| {
+        let row = |r: &[Num; 3]| r[0] * v.x + r[1] * v.y + r[2] * v.z;
+        Color::new(row(&matrix[0]), row(&matrix[1]), row(&matrix[2]))
+    };
+    let rrt_and_odt = |v: Num| {
+        let a = v * (v + 0.0245786) - 0.000090537;
+        let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+        a / b
+    };
+    multiply(
+        &ACES_OUTPUT,
+        map_channels(multiply(&ACES_INPUT, color), rrt_and_odt),
+    )
+}
+
+#[cfg(test)]
+mod test_tonemap {
+    use super::*;
+
+    fn transform(tone_map: ToneMap) -> DisplayTransform {
+        DisplayTransform {
+            tone_map,
+            ..DisplayTransform::default()
+        }
+    }
+
+    #[test]
+    fn test_srgb_oetf() {
+        assert_eq!(srgb_oetf(0.0), 0.0);
+        assert!((srgb_oetf(1.0) - 1.0).abs() < 1e-12);
+        assert!((srgb_oetf(0.002) - 0.02584).abs() < 1e-12);
+        assert!((srgb_oetf(0.18) - 0.46135).abs() < 1e-4);
+        // continuous where the linear segment meets the power curve
+        assert!((srgb_oetf(0.0031308) - srgb_oetf(0.0031309)).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_exposure() {
+        let display = DisplayTransform {
+            exposure: 2.0,
+            ..DisplayTransform::default()
+        };
+        assert_eq!(
+            display.tone_map(Color::new(0.1, 0.2, 0.3)),
+            Color::new(0.4, 0.8, 1)
+        );
+        let display = DisplayTransform {
+            exposure: -1.0,
+            ..DisplayTransform::default()
+        };
+        assert_eq!(display.tone_map(Color::new(1, 2, 0)), Color::new(0.5, 1, 0));
+    }
+
+    #[test]
+    fn test_reinhard() {
+        let grey =
+            |display: &DisplayTransform, value: Num| display.tone_map(Color::one() * value).x;
+        let reinhard = transform(ToneMap::Reinhard);
+        assert!((grey(&reinhard, 1.0) - 0.5).abs() < 1e-12);
+        assert!((grey(&reinhard, 3.0) - 0.75).abs() < 1e-12);
+        let extended = transform(ToneMap::ExtendedReinhard);
+        assert!((grey(&extended, 4.0) - 1.0).abs() < 1e-12);
+        assert!(grey(&extended, 2.0) > grey(&reinhard, 2.0));
+        // the hue is kept
+        let orange = reinhard.tone_map(Color::new(2, 1, 0));
+        assert!((orange.x - 2.0 * orange.y).abs() < 1e-12);
+        assert_eq!(reinhard.tone_map(Color::zero()), Color::zero());
+    }
+
+    #[test]
+    fn test_curves_are_monotonic() {
+        for &tone_map in &ToneMap::ALL {
+            let display = transform(tone_map);
+            let mut previous = -1.0;
+            for step in 0..200 {
+                let value = display.tone_map(Color::one() * (step as Num * 0.1)).x;
+                assert!(value >= previous, "{} decreases at {}", tone_map, step);
+                assert!((0.0..=1.0).contains(&value));
+                previous = value;
+            }
+            assert!(display.tone_map(Color::zero()).x < 0.01, "{}", tone_map);
+        }
+        // filmic curves roll off rather than clip
+        assert!(transform(ToneMap::Hable).tone_map(Color::one() * 4.0).x < 1.0);
+        assert!((transform(ToneMap::Hable).tone_map(Color::one() * 5.6).x - 1.0).abs() < 1e-12);
+        assert!(transform(ToneMap::Aces).tone_map(Color::one() * 4.0).x < 1.0);
+    }
+
+    #[test]
+    fn test_names() {
+        for &tone_map in &ToneMap::ALL {
+            assert_eq!(tone_map.to_string().parse(), Ok(tone_map));
+        }
+        assert!("filmic"
+            .parse::<ToneMap>()
+            .unwrap_err()
+            .contains("unknown tone map"));
+    }
+}
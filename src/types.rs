@@ -1,6 +1,8 @@
 use crate::materials::*;
+use crate::math::transform::*;
 use crate::math::vec3::*;
 use crate::math::*;
+use crate::sampler::*;
 // #[derive(Clone, Copy)]
 pub struct Camera {
     pub origin: Point,
@@ -9,8 +11,11 @@ pub struct Camera {
     pub vertical: Vec3,
     u: Vec3,
     v: Vec3,
+    #[allow(dead_code)]
     w: Vec3,
     lens_radius: Num,
+    /// times the shutter opens and closes
+    shutter: (Num, Num),
     // use_ctor_please: (),
 }
 
@@ -50,93 +55,321 @@ impl Camera {
             v,
             w,
             lens_radius,
+            shutter: (0.0, 0.0),
         }
     }
-    pub fn get_ray(&self, u: Num, v: Num) -> Ray {
-        let rd = random_in_unit_disk() * self.lens_radius;
+
+    /// Keeps the shutter open from `open` to `close`, blurring anything that
+    /// moves in between.
+    pub fn with_shutter(mut self, open: Num, close: Num) -> Camera {
+        assert!(open <= close, "the shutter can't close before it opens");
+        self.shutter = (open, close);
+        self
+    }
+
+    pub fn shutter(&self) -> (Num, Num) {
+        self.shutter
+    }
+
+    /// The ray through the viewport at (`u`, `v`), from a point on the lens
+    /// picked with the sampler's next 2D sample. With the shutter open for a
+    /// while, the ray's time is picked with the next 1D sample.
+    pub fn get_ray(&self, u: Num, v: Num, sampler: &mut dyn Sampler) -> Ray {
+        let rd = sample_unit_disk(sampler.next_2d()) * self.lens_radius;
         let offset = self.u * rd.x + self.v * rd.y;
+        let (open, close) = self.shutter;
+        let time = if open < close {
+            open + sampler.next_1d() * (close - open)
+        } else {
+            open
+        };
         Ray {
             origin: self.origin + offset,
             direction: (self.lower_left_corner + (self.horizontal * u) + (self.vertical * v))
                 - self.origin
                 - offset,
-        }
-    }
-}
-
-fn random_in_unit_disk() -> Vec3 {
-    let mut rng = random_num_generator_rng();
-    loop {
-        let p = Vec3::new(rng(-1.0, 1.0), rng(-1.0, 1.0), 0);
-        if p.magnitude_squared() < 1.0 {
-            return p;
+            time,
         }
     }
 }
 
 pub struct Triangle {
     pub p1: Point,
-    pub p3: Point,
     pub p2: Point,
+    pub p3: Point,
+    /// per-vertex normals (in `p1`, `p2`, `p3` order) for smooth shading
+    pub normals: Option<[Vec3; 3]>,
+    pub material: MaterialPtr,
+}
+
+impl Triangle {
+    pub fn new(p1: Point, p2: Point, p3: Point, material: MaterialPtr) -> Triangle {
+        Triangle {
+            p1,
+            p2,
+            p3,
+            normals: None,
+            material,
+        }
+    }
+
+    pub fn with_normals(self, n1: Vec3, n2: Vec3, n3: Vec3) -> Triangle {
+        Triangle {
+            normals: Some([n1.unit_vector(), n2.unit_vector(), n3.unit_vector()]),
+            ..self
+        }
+    }
 }
-/* impl Hit for Cube {
+
+impl Hit for Triangle {
     fn hit(&self, ray: &Ray, t_min: Num, t_max: Num) -> Option<HitRecord> {
+        let (t, b1, b2) = intersect_triangle(ray, self.p1, self.p2, self.p3, t_min, t_max)?;
+        let geometric_normal = (self.p2 - self.p1).cross(self.p3 - self.p1).unit_vector();
+        let record = HitRecord::new(ray.at(t), t, ray, geometric_normal, self.material.clone());
+        Some(record.with_barycentric(b1, b2, self.normals))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::from_points(&[self.p1, self.p2, self.p3]))
+    }
+}
+
+/// Möller–Trumbore ray/triangle intersection.
+///
+/// Returns `(t, b1, b2)` where `b1` and `b2` are the barycentric weights of
+/// `p2` and `p3` (the weight of `p1` being `1 - b1 - b2`). Both faces are hit;
+/// points exactly on an edge or a vertex count as hits.
+pub fn intersect_triangle(
+    ray: &Ray,
+    p1: Point,
+    p2: Point,
+    p3: Point,
+    t_min: Num,
+    t_max: Num,
+) -> Option<(Num, Num, Num)> {
+    let edge1 = p2 - p1;
+    let edge2 = p3 - p1;
+    let p_vec = ray.direction.cross(edge2);
+    let det = edge1.dot(p_vec);
+    // ray is parallel to the triangle's plane
+    if det.abs() < EPSILON {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+
+    let t_vec = ray.origin - p1;
+    let b1 = t_vec.dot(p_vec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+
+    let q_vec = t_vec.cross(edge1);
+    let b2 = ray.direction.dot(q_vec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
 
+    let t = edge2.dot(q_vec) * inv_det;
+    if t < t_max && t > t_min {
+        Some((t, b1, b2))
+    } else {
+        None
     }
 }
- */
 
+/// An infinite plane.
 pub struct Plane {
     pub p1: Point,
     pub normal: Vec3,
+    pub material: MaterialPtr,
 }
 
 impl Hit for Plane {
     fn hit(&self, ray: &Ray, t_min: Num, t_max: Num) -> Option<HitRecord> {
+        let denominator = self.normal.dot(ray.direction);
+        // ray is parallel to the plane
+        if denominator.abs() < EPSILON {
+            return None;
+        }
+        let t = self.normal.dot(self.p1 - ray.origin) / denominator;
+        if t < t_max && t > t_min {
+            let position = ray.at(t);
+            let normal = self.normal.unit_vector();
+            // world space distances along two directions in the plane
+            let (u, v) = orthonormal_basis(normal);
+            let planar = position - self.p1;
+            let record = HitRecord::new(position, t, ray, normal, self.material.clone());
+            Some(record.with_uv(planar.dot(u), planar.dot(v)))
+        } else {
+            None
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
         None
     }
 }
+
+/// A parallelogram spanned by the `u` and `v` edges from `corner`.
+pub struct Quad {
+    pub corner: Point,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub material: MaterialPtr,
+    normal: Vec3,
+    // n / (n . n), used to find the planar coordinates of a hit
+    w: Vec3,
+    area: Num,
+}
+
+impl Quad {
+    pub fn new(corner: Point, u: Vec3, v: Vec3, material: MaterialPtr) -> Quad {
+        let n = u.cross(v);
+        Quad {
+            corner,
+            u,
+            v,
+            material,
+            normal: n.unit_vector(),
+            w: n / n.magnitude_squared(),
+            area: n.magnitude(),
+        }
+    }
+
+    pub fn normal(&self) -> Vec3 {
+        self.normal
+    }
+}
+
+impl Hit for Quad {
+    fn hit(&self, ray: &Ray, t_min: Num, t_max: Num) -> Option<HitRecord> {
+        let denominator = self.normal.dot(ray.direction);
+        if denominator.abs() < EPSILON {
+            return None;
+        }
+        let t = self.normal.dot(self.corner - ray.origin) / denominator;
+        if !(t < t_max && t > t_min) {
+            return None;
+        }
+        let position = ray.at(t);
+        let planar = position - self.corner;
+        let alpha = self.w.dot(planar.cross(self.v));
+        let beta = self.w.dot(self.u.cross(planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+        let record = HitRecord::new(position, t, ray, self.normal, self.material.clone());
+        Some(record.with_uv(alpha, beta))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::from_points(&[
+            self.corner,
+            self.corner + self.u,
+            self.corner + self.v,
+            self.corner + self.u + self.v,
+        ]))
+    }
+
+    fn pdf_value(&self, origin: Point, direction: Vec3) -> Num {
+        match self.hit(
+            &Ray {
+                origin,
+                direction,
+                time: 0.0,
+            },
+            0.001,
+            INFINITY,
+        ) {
+            Some(record) => {
+                let distance_squared = record.t * record.t * direction.magnitude_squared();
+                let cosine = direction.dot(self.normal).abs() / direction.magnitude();
+                distance_squared / (cosine * self.area)
+            }
+            None => 0.0,
+        }
+    }
+
+    fn random_direction(&self, origin: Point, sampler: &mut dyn Sampler) -> Vec3 {
+        let (s, t) = sampler.next_2d();
+        let point = self.corner + (self.u * s) + (self.v * t);
+        point - origin
+    }
+}
+
+/// An axis-aligned box.
 pub struct Cube {
-    pub center: Point,
-    pub width: Num,
+    pub min: Point,
+    pub max: Point,
     pub material: MaterialPtr,
 }
 
-/* impl Hit for Cube {
+impl Cube {
+    pub fn new(center: Point, width: Num, material: MaterialPtr) -> Cube {
+        let half = Vec3::new(width, width, width) / 2.0;
+        Cube {
+            min: center - half,
+            max: center + half,
+            material,
+        }
+    }
+}
+
+impl Hit for Cube {
     fn hit(&self, ray: &Ray, t_min: Num, t_max: Num) -> Option<HitRecord> {
-        // let p be a point on the cube
-        // (Cx +- (w /2), Cy +- (h/2), Cx +- (l / 2) = P
-        // Vec3(Px-Cx, Py-Cy, Pz-Cz) = Vec3(w, h, l) /2
-        // P +-- C = (w, h, l) /2
-        // let d = Vec3(w, h, l)
-        // P +- C = d / 2
-        // P(t) = A + tB (Ray formula): A = origin, B = direction
-        // (A + tB) +- C = d
-        // t = ((A + d) + C) / B
-        // or
-        // t = ((A + d) - C) / B
-        let d = Vec3::new(self.width, self.width, self.width) / 2.0;
-        let t = d + self.center - ray.origin;
-        let t = Vec3 {
-            x: t.x / ray.direction.x,
-            y: t.y / ray.direction.y,
-            z: t.z / ray.direction.z,
-        };
-        if t < t_max && t > t_min {
-            let position = ray.origin + (ray.direction * t);
-
-            Some(HitRecord::new(
-                position,
-                t,
-                ray,
-                outward_normal,
-                self.material.clone,
-            ))
-        } else {
-            None
+        // slab test, remembering which faces the ray enters and leaves by
+        let mut t_near = NEG_INFINITY;
+        let mut t_far = INFINITY;
+        let mut near_face = (0, Vec3::zero());
+        let mut far_face = (0, Vec3::zero());
+        for axis in 0..3 {
+            let unit = match axis {
+                0 => Vec3::unit_x(),
+                1 => Vec3::unit_y(),
+                _ => Vec3::unit_z(),
+            };
+            let inverse_direction = 1.0 / ray.direction[axis];
+            let mut t0 = (self.min[axis] - ray.origin[axis]) * inverse_direction;
+            let mut t1 = (self.max[axis] - ray.origin[axis]) * inverse_direction;
+            // outward normals of the faces crossed at t0 and t1
+            let mut n0 = -unit;
+            let mut n1 = unit;
+            if inverse_direction < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+                std::mem::swap(&mut n0, &mut n1);
+            }
+            if t0 > t_near {
+                t_near = t0;
+                near_face = (axis, n0);
+            }
+            if t1 < t_far {
+                t_far = t1;
+                far_face = (axis, n1);
+            }
+            if t_far < t_near {
+                return None;
+            }
         }
+        // the entry point, or the exit point if the ray starts inside
+        let (t, (axis, outward_normal)) = if t_near < t_max && t_near > t_min {
+            (t_near, near_face)
+        } else if t_far < t_max && t_far > t_min {
+            (t_far, far_face)
+        } else {
+            return None;
+        };
+        let position = ray.at(t);
+        // surface coordinates span each face along the two other axes
+        let face_coord =
+            |axis: usize| (position[axis] - self.min[axis]) / (self.max[axis] - self.min[axis]);
+        let record = HitRecord::new(position, t, ray, outward_normal, self.material.clone());
+        Some(record.with_uv(face_coord((axis + 1) % 3), face_coord((axis + 2) % 3)))
     }
-} */
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(self.min, self.max))
+    }
+}
 
 pub struct Sphere {
     pub center: Point,
@@ -144,56 +377,308 @@ pub struct Sphere {
     pub material: MaterialPtr,
 }
 
+/// Where `ray` first enters or leaves a sphere within (`t_min`, `t_max`).
+fn hit_sphere(
+    center: Point,
+    radius: Num,
+    material: &MaterialPtr,
+    ray: &Ray,
+    t_min: Num,
+    t_max: Num,
+) -> Option<HitRecord> {
+    // (t^2 * b^2) + (2tb * (A−C)) + ((A−C) * (A−C)) − r^2 = 0
+    // A = origin
+    // b = direction
+    // t = step
+    // C = sphere center
+
+    // use quadratic equation to solve
+    // +- b * sqrt(b^2 * 4*a*c) / 2 * a
+
+    // a = b^2  --  b dot b = |b|^2
+    let a = ray.direction.magnitude_squared();
+
+    let o_to_c = ray.origin - center; // (A - C)
+
+    // b = 2b * (A - C) -- remove the 2
+    let half_b = ray.direction.dot(o_to_c);
+
+    // c = (A-C)^2 - r^2 -- again v dot b = |v|^2
+    let c = o_to_c.magnitude_squared() - radius * radius;
+
+    // b^2 * 4*a*c = (2*half_b)^2 - 4ac = 4halfb^2 - 4ac
+    // = halfb^2 -ac (take common 4 out of root)
+    let discriminant = (half_b * half_b) - (a * c);
+    if discriminant > 0.0 {
+        // hit sphere
+        let root = Num::sqrt(discriminant);
+        let mut solution = (-half_b - root) / a;
+        let mut valid: bool = solution < t_max && solution > t_min;
+        if !valid {
+            solution = (-half_b + root) / a;
+            valid = solution < t_max && solution > t_min;
+        }
+        if valid {
+            let position = ray.at(solution);
+            let outward_normal = (position - center) / radius;
+            let (u, v) = sphere_uv(outward_normal);
+
+            let record = HitRecord::new(position, solution, ray, outward_normal, material.clone());
+            return Some(record.with_uv(u, v));
+        }
+    }
+    // didn't hit sphere
+    None
+}
+
 impl Hit for Sphere {
     fn hit(&self, ray: &Ray, t_min: Num, t_max: Num) -> Option<HitRecord> {
-        // (t^2 * b^2) + (2tb * (A−C)) + ((A−C) * (A−C)) − r^2 = 0
-        // A = origin
-        // b = direction
-        // t = step
-        // C = sphere center
-
-        // use quadratic equation to solve
-        // +- b * sqrt(b^2 * 4*a*c) / 2 * a
-
-        // a = b^2  --  b dot b = |b|^2
-        let a = ray.direction.magnitude_squared();
-
-        let o_to_c = ray.origin - self.center; // (A - C)
-
-        // b = 2b * (A - C) -- remove the 2
-        let half_b = ray.direction.dot(o_to_c);
-
-        // c = (A-C)^2 - r^2 -- again v dot b = |v|^2
-        let c = o_to_c.magnitude_squared() - self.radius * self.radius;
-
-        // b^2 * 4*a*c = (2*half_b)^2 - 4ac = 4halfb^2 - 4ac
-        // = halfb^2 -ac (take common 4 out of root)
-        let discriminant = (half_b * half_b) - (a * c);
-        if discriminant > 0.0 {
-            // hit sphere
-            let root = Num::sqrt(discriminant);
-            let mut solution = (-half_b - root) / a;
-            let mut valid: bool = solution < t_max && solution > t_min;
-            if !valid {
-                solution = (-half_b - root) / a;
-                valid = solution < t_max && solution > t_min;
-            }
-            if valid {
-                let position = ray.at(solution);
-                let outward_normal = (position - self.center) / self.radius;
-
-                let record = HitRecord::new(
-                    position,
-                    solution,
-                    ray,
-                    outward_normal,
-                    self.material.clone(),
-                );
-                return Some(record);
+        hit_sphere(self.center, self.radius, &self.material, ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let radius = Vec3::new(self.radius, self.radius, self.radius);
+        Some(Aabb::new(self.center - radius, self.center + radius))
+    }
+
+    fn pdf_value(&self, origin: Point, direction: Vec3) -> Num {
+        let record = match self.hit(
+            &Ray {
+                origin,
+                direction,
+                time: 0.0,
+            },
+            0.001,
+            INFINITY,
+        ) {
+            Some(record) => record,
+            None => return 0.0,
+        };
+        let distance_squared = (self.center - origin).magnitude_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            // inside: points were sampled uniformly over the surface
+            let to_hit = record.position - origin;
+            let cosine = to_hit.unit_vector().dot(record.normal).abs();
+            let area = 4.0 * PI * radius_squared;
+            return to_hit.magnitude_squared() / (cosine * area);
+        }
+        // outside: directions were sampled uniformly in the visible cone
+        let cos_theta_max = Num::sqrt(1.0 - radius_squared / distance_squared);
+        1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+    }
+
+    fn random_direction(&self, origin: Point, sampler: &mut dyn Sampler) -> Vec3 {
+        let to_center = self.center - origin;
+        let distance_squared = to_center.magnitude_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return self.center + random_unit_vector(sampler) * self.radius - origin;
+        }
+        let cos_theta_max = Num::sqrt(1.0 - radius_squared / distance_squared);
+        let (s, t) = sampler.next_2d();
+        let cos_theta = 1.0 + s * (cos_theta_max - 1.0);
+        let sin_theta = Num::sqrt(1.0 - cos_theta * cos_theta);
+        let phi = 2.0 * PI * t;
+        let w = to_center.unit_vector();
+        let (u, v) = orthonormal_basis(w);
+        u * (Num::cos(phi) * sin_theta) + v * (Num::sin(phi) * sin_theta) + w * cos_theta
+    }
+}
+
+/// Longitude/latitude of a point on the unit sphere, both in `[0, 1]`, with
+/// `v` going from the bottom (-y) pole to the top one and `u` starting at -x.
+pub fn sphere_uv(point: Point) -> (Num, Num) {
+    let theta = Num::acos(clamp_num(-point.y, -1.0, 1.0));
+    let phi = Num::atan2(-point.z, point.x) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
+
+/// A sphere moving in a straight line from `start_center` at `start_time` to
+/// `end_center` at `end_time`, holding still before and after.
+pub struct MovingSphere {
+    pub start_center: Point,
+    pub end_center: Point,
+    pub start_time: Num,
+    pub end_time: Num,
+    pub radius: Num,
+    pub material: MaterialPtr,
+}
+
+impl MovingSphere {
+    pub fn center(&self, time: Num) -> Point {
+        if self.end_time <= self.start_time {
+            return self.start_center;
+        }
+        let t = clamp_num(
+            (time - self.start_time) / (self.end_time - self.start_time),
+            0.0,
+            1.0,
+        );
+        self.start_center + (self.end_center - self.start_center) * t
+    }
+}
+
+impl Hit for MovingSphere {
+    fn hit(&self, ray: &Ray, t_min: Num, t_max: Num) -> Option<HitRecord> {
+        hit_sphere(
+            self.center(ray.time),
+            self.radius,
+            &self.material,
+            ray,
+            t_min,
+            t_max,
+        )
+    }
+
+    /// Covers the sphere all along its way.
+    fn bounding_box(&self) -> Option<Aabb> {
+        let radius = Vec3::new(self.radius, self.radius, self.radius);
+        let start = Aabb::new(self.start_center - radius, self.start_center + radius);
+        let end = Aabb::new(self.end_center - radius, self.end_center + radius);
+        Some(start.union(end))
+    }
+}
+
+/// Another object placed in the world with a transform, which may change
+/// over time.
+///
+/// The object is shared rather than copied, so one mesh (and its BVH) can be
+/// placed any number of times.
+pub struct Instance {
+    pub object: HittablePtr,
+    /// object to world space
+    motion: MotionTransform,
+}
+
+/// times a moving instance's bounds are taken at
+const MOTION_BOUNDS_STEPS: usize = 64;
+
+impl Instance {
+    pub fn new(object: HittablePtr, transform: Transform) -> Instance {
+        Instance::moving(object, MotionTransform::fixed(transform))
+    }
+
+    pub fn moving(object: HittablePtr, motion: MotionTransform) -> Instance {
+        Instance { object, motion }
+    }
+
+    /// The object to world transform at `time`.
+    pub fn transform(&self, time: Num) -> Transform {
+        self.motion.at(time)
+    }
+
+    /// The object to world transform at the ray's time, and the ray in
+    /// object space. The direction isn't renormalized, so distances along
+    /// the ray match.
+    fn local_ray(&self, ray: &Ray) -> (Transform, Ray) {
+        let transform = self.motion.at(ray.time);
+        let world_to_object = transform.inverse();
+        let local_ray = Ray {
+            origin: world_to_object.point(ray.origin),
+            direction: world_to_object.vector(ray.direction),
+            time: ray.time,
+        };
+        (transform, local_ray)
+    }
+
+    /// A hit on the object moved back into the world.
+    fn place(ray: &Ray, transform: &Transform, mut record: HitRecord) -> HitRecord {
+        record.position = ray.at(record.t);
+        // the normal stays on the same side of the ray as in object space
+        record.normal = transform.normal(record.normal).unit_vector();
+        record
+    }
+
+    fn corners(local: &Aabb) -> Vec<Point> {
+        (0..8)
+            .map(|corner| {
+                let pick = |bit: usize, axis: usize| {
+                    if corner & bit == 0 {
+                        local.min[axis]
+                    } else {
+                        local.max[axis]
+                    }
+                };
+                Vec3::new(pick(1, 0), pick(2, 1), pick(4, 2))
+            })
+            .collect()
+    }
+}
+
+impl Hit for Instance {
+    fn hit(&self, ray: &Ray, t_min: Num, t_max: Num) -> Option<HitRecord> {
+        let (transform, local_ray) = self.local_ray(ray);
+        let record = self.object.hit(&local_ray, t_min, t_max)?;
+        Some(Instance::place(ray, &transform, record))
+    }
+
+    fn sample_hit(
+        &self,
+        ray: &Ray,
+        t_min: Num,
+        t_max: Num,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
+        let (transform, local_ray) = self.local_ray(ray);
+        let record = self.object.sample_hit(&local_ray, t_min, t_max, sampler)?;
+        Some(Instance::place(ray, &transform, record))
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: Num, t_max: Num, sampler: &mut dyn Sampler) -> Num {
+        let (_, local_ray) = self.local_ray(ray);
+        self.object.transmittance(&local_ray, t_min, t_max, sampler)
+    }
+
+    /// Covers the object all along its way.
+    fn bounding_box(&self) -> Option<Aabb> {
+        let corners = Instance::corners(&self.object.bounding_box()?);
+        let placed = |transform: &Transform| {
+            let points: Vec<Point> = corners.iter().map(|&p| transform.point(p)).collect();
+            Aabb::from_points(&points)
+        };
+        let motion = &self.motion;
+        if !motion.is_moving() {
+            return Some(placed(&motion.start));
+        }
+        let mut bounds = placed(&motion.start);
+        // how far the corners are from the center of rotation
+        let mut reach: Num = 0.0;
+        for step in 0..=MOTION_BOUNDS_STEPS {
+            let t = step as Num / MOTION_BOUNDS_STEPS as Num;
+            let transform =
+                motion.at(motion.start_time + (motion.end_time - motion.start_time) * t);
+            for &corner in &corners {
+                reach = reach.max(transform.vector(corner).magnitude());
             }
+            bounds = bounds.union(placed(&transform));
         }
-        // didn't hit sphere
-        None
+        // between the steps the corners swing out along arcs, by at most
+        // the arcs' height over their chords
+        let step_angle = motion.rotation_angle() / MOTION_BOUNDS_STEPS as Num;
+        let sagitta = reach * (1.0 - Num::cos(step_angle / 2.0));
+        let padding = Vec3::new(sagitta, sagitta, sagitta);
+        Some(Aabb::new(bounds.min - padding, bounds.max + padding))
+    }
+
+    /// Moving instances are sampled where they start, whatever the time, so
+    /// they don't belong in [`Scene::lights`].
+    fn pdf_value(&self, origin: Point, direction: Vec3) -> Num {
+        let transform = &self.motion.start;
+        let world_to_object = transform.inverse();
+        let local = world_to_object.vector(direction.unit_vector());
+        let pdf = self.object.pdf_value(world_to_object.point(origin), local);
+        // directions from the origin map linearly between the spaces, which
+        // stretches solid angles by |det| * |local|^3 on the way out
+        pdf / (transform.determinant().abs() * local.magnitude().powi(3))
+    }
+
+    fn random_direction(&self, origin: Point, sampler: &mut dyn Sampler) -> Vec3 {
+        let transform = &self.motion.start;
+        let local = self
+            .object
+            .random_direction(transform.inverse().point(origin), sampler);
+        transform.vector(local)
     }
 }
 
@@ -205,7 +690,7 @@ impl Hit for HittablesList {
         let mut closest_so_far = t_max;
 
         for object in self {
-            let temp = object.hit(&ray, t_min, closest_so_far);
+            let temp = object.hit(ray, t_min, closest_so_far);
             if let Some(r) = temp {
                 closest_so_far = r.t;
                 record = Some(r);
@@ -213,20 +698,201 @@ impl Hit for HittablesList {
         }
         record
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut boxes = self.iter().map(|object| object.bounding_box());
+        let first = boxes.next()??;
+        boxes.try_fold(first, |total, next| Some(total.union(next?)))
+    }
+
+    fn sample_hit(
+        &self,
+        ray: &Ray,
+        t_min: Num,
+        t_max: Num,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
+        let mut record = None;
+        let mut closest_so_far = t_max;
+        for object in self {
+            if let Some(r) = object.sample_hit(ray, t_min, closest_so_far, sampler) {
+                closest_so_far = r.t;
+                record = Some(r);
+            }
+        }
+        record
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: Num, t_max: Num, sampler: &mut dyn Sampler) -> Num {
+        let mut transmittance = 1.0;
+        for object in self {
+            transmittance *= object.transmittance(ray, t_min, t_max, sampler);
+            if transmittance == 0.0 {
+                break;
+            }
+        }
+        transmittance
+    }
+
+    /// Objects are picked with equal probability, so this is their average.
+    fn pdf_value(&self, origin: Point, direction: Vec3) -> Num {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let sum: Num = self
+            .iter()
+            .map(|object| object.pdf_value(origin, direction))
+            .sum();
+        sum / self.len() as Num
+    }
+
+    fn random_direction(&self, origin: Point, sampler: &mut dyn Sampler) -> Vec3 {
+        let index = usize::min(
+            (sampler.next_1d() * self.len() as Num) as usize,
+            self.len() - 1,
+        );
+        self[index].random_direction(origin, sampler)
+    }
 }
 
 pub type HittablePtr = std::sync::Arc<dyn Hit + Send + Sync>;
 
 pub trait Hit {
+    /// The closest surface the ray hits between `t_min` and `t_max`. Media
+    /// have none, and only stop the rays traced with `sample_hit`.
     fn hit(&self, ray: &Ray, t_min: Num, t_max: Num) -> Option<HitRecord>;
+
+    /// Like `hit`, for the rays a path is traced along: they can also
+    /// scatter in media, after a distance drawn from `sampler`.
+    fn sample_hit(
+        &self,
+        ray: &Ray,
+        t_min: Num,
+        t_max: Num,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
+        self.hit(ray, t_min, t_max)
+    }
+
+    /// Box enclosing the whole object, `None` if it's unbounded.
+    fn bounding_box(&self) -> Option<Aabb>;
+
+    /// Solid angle density with which `random_direction` picks `direction`
+    /// from `origin`. Shapes that can't be sampled as lights return zero.
+    fn pdf_value(&self, _origin: Point, _direction: Vec3) -> Num {
+        0.0
+    }
+
+    /// A direction from `origin` towards a random point on the object.
+    fn random_direction(&self, _origin: Point, _sampler: &mut dyn Sampler) -> Vec3 {
+        Vec3::unit_x()
+    }
+
+    /// Fraction of the light along the ray between `t_min` and `t_max` that
+    /// gets through the object: none if it hits a surface, some through a
+    /// medium, estimated with `sampler` where it can't be worked out.
+    fn transmittance(&self, ray: &Ray, t_min: Num, t_max: Num, _sampler: &mut dyn Sampler) -> Num {
+        if self.hit(ray, t_min, t_max).is_some() {
+            0.0
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Everything a render needs besides the camera.
+pub struct Scene {
+    pub world: HittablePtr,
+    /// emitters sampled directly at every diffuse bounce; they must also be
+    /// part of `world` to be visible, and must hold still, as a moving one is
+    /// sampled where it starts whatever the time of the ray
+    pub lights: HittablesList,
+    pub background: Background,
 }
 
+/// Axis-aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Point,
+    pub max: Point,
+}
+
+impl Aabb {
+    /// thickness given to flat boxes so rays can still hit them
+    const MIN_EXTENT: Num = 1e-4;
+
+    pub fn new(min: Point, max: Point) -> Aabb {
+        Aabb { min, max }
+    }
+
+    /// Smallest box around the points, padded along any flat axis.
+    pub fn from_points(points: &[Point]) -> Aabb {
+        let mut min = points[0];
+        let mut max = points[0];
+        for &point in &points[1..] {
+            min = min.min(point);
+            max = max.max(point);
+        }
+        let pad = |min: Num, max: Num| {
+            if max - min < Aabb::MIN_EXTENT {
+                Aabb::MIN_EXTENT / 2.0
+            } else {
+                0.0
+            }
+        };
+        let padding = Vec3::new(pad(min.x, max.x), pad(min.y, max.y), pad(min.z, max.z));
+        Aabb::new(min - padding, max + padding)
+    }
+
+    pub fn union(self, other: Aabb) -> Aabb {
+        Aabb::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    pub fn centroid(&self) -> Point {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn surface_area(&self) -> Num {
+        let d = self.max - self.min;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    /// Slab test: does the ray pass through the box within `[t_min, t_max]`?
+    pub fn hit(&self, ray: &Ray, t_min: Num, t_max: Num) -> bool {
+        self.overlap(ray, t_min, t_max).is_some()
+    }
+
+    /// The part of `[t_min, t_max]` the ray spends inside the box.
+    pub fn overlap(&self, ray: &Ray, mut t_min: Num, mut t_max: Num) -> Option<(Num, Num)> {
+        for axis in 0..3 {
+            let inverse_direction = 1.0 / ray.direction[axis];
+            let mut t0 = (self.min[axis] - ray.origin[axis]) * inverse_direction;
+            let mut t1 = (self.max[axis] - ray.origin[axis]) * inverse_direction;
+            if inverse_direction < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = Num::max(t0, t_min);
+            t_max = Num::min(t1, t_max);
+            if t_max < t_min {
+                return None;
+            }
+        }
+        Some((t_min, t_max))
+    }
+}
+
+#[derive(Clone)]
 pub struct HitRecord {
     pub position: Point,
     pub normal: Vec3,
     pub t: Num,
     pub front_face: bool,
     pub material: MaterialPtr,
+    /// barycentric weights of the second and third vertex, for triangle hits
+    pub barycentric: Option<(Num, Num)>,
+    /// surface coordinates, for texturing
+    pub u: Num,
+    pub v: Num,
 }
 
 impl HitRecord {
@@ -249,6 +915,38 @@ impl HitRecord {
             front_face,
             normal,
             material,
+            barycentric: None,
+            u: 0.0,
+            v: 0.0,
+        }
+    }
+
+    pub fn with_uv(self, u: Num, v: Num) -> HitRecord {
+        HitRecord { u, v, ..self }
+    }
+
+    /// Records the barycentric coordinates of a triangle hit, also using them
+    /// as surface coordinates, and, if vertex normals are given, replaces the
+    /// normal with the interpolated shading normal (kept on the same side as
+    /// the geometric one).
+    pub fn with_barycentric(self, b1: Num, b2: Num, normals: Option<[Vec3; 3]>) -> HitRecord {
+        let normal = match normals {
+            Some([n1, n2, n3]) => {
+                let shading = ((n1 * (1.0 - b1 - b2)) + (n2 * b1) + (n3 * b2)).unit_vector();
+                if shading.dot(self.normal) < 0.0 {
+                    -shading
+                } else {
+                    shading
+                }
+            }
+            None => self.normal,
+        };
+        HitRecord {
+            normal,
+            barycentric: Some((b1, b2)),
+            u: b1,
+            v: b2,
+            ..self
         }
     }
 
@@ -260,19 +958,55 @@ impl HitRecord {
             -outward_normal
         };
         HitRecord {
-            position: self.position,
-            t: self.t,
             front_face,
             normal,
-            material: self.material,
+            ..self
         }
     }
 }
 
-#[derive(Debug)]
+/// Radiance arriving along rays that escape the scene.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Background {
+    Constant(Color),
+    /// blends from `bottom` to `top` with the ray direction's height
+    Gradient {
+        bottom: Color,
+        top: Color,
+    },
+}
+
+impl Background {
+    pub fn black() -> Background {
+        Background::Constant(Color::zero())
+    }
+
+    /// white to light blue
+    pub fn sky() -> Background {
+        Background::Gradient {
+            bottom: Color::one(),
+            top: Color::new(0.5, 0.7, 1.0),
+        }
+    }
+
+    pub fn radiance(&self, ray: &Ray) -> Color {
+        match *self {
+            Background::Constant(color) => color,
+            Background::Gradient { bottom, top } => {
+                let unit_direction = ray.direction.unit_vector();
+                let t = 0.5 * (unit_direction.y + 1.0);
+                (bottom * (1.0 - t)) + (top * t)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
 pub struct Ray {
     pub origin: Point,
     pub direction: Vec3,
+    /// when the ray was sent, within the camera's shutter interval
+    pub time: Num,
 }
 
 impl Ray {
@@ -286,6 +1020,11 @@ pub type Point = Vec3;
 pub type Color = Vec3;
 
 impl Color {
+    /// Relative luminance of a linear Rec. 709 color.
+    pub fn luminance(self) -> Num {
+        0.2126 * self.x + 0.7152 * self.y + 0.0722 * self.z
+    }
+
     pub fn ppm_fmt(self) -> String {
         format!(
             "{} {} {}\n",
@@ -305,7 +1044,525 @@ mod test_ray {
         let ray = Ray {
             origin: Vec3::zero(),
             direction: Vec3::one(),
+            time: 0.0,
         };
         assert_eq!(ray.at(5.0), Vec3::new(5, 5, 5));
     }
+
+    #[test]
+    fn test_shutter() {
+        let camera = |shutter: Option<(Num, Num)>| {
+            let camera = Camera::new(
+                Point::zero(),
+                -Vec3::unit_z(),
+                Vec3::unit_y(),
+                1.0,
+                90.0,
+                0.0,
+                1.0,
+            );
+            match shutter {
+                Some((open, close)) => camera.with_shutter(open, close),
+                None => camera,
+            }
+        };
+        let mut sampler = IndependentSampler::new(0);
+        let open = camera(Some((0.5, 1.5)));
+        let mut times = Vec::new();
+        for index in 0..100 {
+            sampler.start_sample(0, 0, index);
+            times.push(open.get_ray(0.5, 0.5, &mut sampler).time);
+        }
+        assert!(times.iter().all(|time| (0.5..1.5).contains(time)));
+        assert!(times.iter().any(|&time| time < 0.75) && times.iter().any(|&time| time > 1.25));
+        // a shutter that doesn't stay open takes no extra sample
+        for shutter in [None, Some((2.0, 2.0))] {
+            sampler.start_sample(0, 0, 0);
+            let ray = camera(shutter).get_ray(0.5, 0.5, &mut sampler);
+            assert_eq!(ray.time, shutter.map_or(0.0, |(open, _)| open));
+            let next = sampler.next_1d();
+            sampler.start_sample(0, 0, 0);
+            sampler.next_2d();
+            assert_eq!(sampler.next_1d(), next);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_triangle {
+    use super::*;
+    use std::sync::Arc;
+
+    fn triangle() -> Triangle {
+        Triangle::new(
+            Point::new(0, 0, 0),
+            Point::new(1, 0, 0),
+            Point::new(0, 1, 0),
+            Arc::new(Lambertian::new(Color::one())),
+        )
+    }
+
+    fn ray_towards(x: Num, y: Num, from_z: Num) -> Ray {
+        Ray {
+            origin: Point::new(x, y, from_z),
+            direction: Vec3::new(0, 0, -from_z.signum()),
+            time: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_interior_hit() {
+        let record = triangle()
+            .hit(&ray_towards(0.25, 0.25, 1.0), 0.001, INFINITY)
+            .unwrap();
+        assert_eq!(record.t, 1.0);
+        assert_eq!(record.position, Point::new(0.25, 0.25, 0));
+        assert_eq!(record.barycentric, Some((0.25, 0.25)));
+        assert!(record.front_face);
+        assert_eq!(record.normal, Vec3::unit_z());
+    }
+
+    #[test]
+    fn test_edge_and_vertex_hits() {
+        let triangle = triangle();
+        let edge = triangle
+            .hit(&ray_towards(0.5, 0.0, 1.0), 0.001, INFINITY)
+            .unwrap();
+        assert_eq!(edge.barycentric, Some((0.5, 0.0)));
+        let hypotenuse = triangle
+            .hit(&ray_towards(0.5, 0.5, 1.0), 0.001, INFINITY)
+            .unwrap();
+        assert_eq!(hypotenuse.barycentric, Some((0.5, 0.5)));
+        for &(x, y) in &[(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)] {
+            let vertex = triangle.hit(&ray_towards(x, y, 1.0), 0.001, INFINITY);
+            assert!(vertex.is_some(), "missed vertex ({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn test_back_face_hit() {
+        let record = triangle()
+            .hit(&ray_towards(0.25, 0.25, -1.0), 0.001, INFINITY)
+            .unwrap();
+        assert!(!record.front_face);
+        assert_eq!(record.normal, -Vec3::unit_z());
+    }
+
+    #[test]
+    fn test_misses() {
+        let triangle = triangle();
+        assert!(triangle
+            .hit(&ray_towards(0.75, 0.75, 1.0), 0.001, INFINITY)
+            .is_none());
+        assert!(triangle
+            .hit(&ray_towards(-0.25, 0.25, 1.0), 0.001, INFINITY)
+            .is_none());
+        // out of the [t_min, t_max] range
+        assert!(triangle
+            .hit(&ray_towards(0.25, 0.25, 1.0), 0.001, 0.5)
+            .is_none());
+        // parallel to the triangle's plane
+        let parallel = Ray {
+            origin: Point::new(-1, 0.25, 0),
+            direction: Vec3::unit_x(),
+            time: 0.0,
+        };
+        assert!(triangle.hit(&parallel, 0.001, INFINITY).is_none());
+    }
+
+    #[test]
+    fn test_smooth_normals() {
+        let n = Vec3::new(1, 0, 1);
+        let triangle = triangle().with_normals(n, n, n);
+        let front = triangle
+            .hit(&ray_towards(0.25, 0.25, 1.0), 0.001, INFINITY)
+            .unwrap();
+        assert!((front.normal - n.unit_vector()).magnitude() < 1e-12);
+        let back = triangle
+            .hit(&ray_towards(0.25, 0.25, -1.0), 0.001, INFINITY)
+            .unwrap();
+        assert!((back.normal + n.unit_vector()).magnitude() < 1e-12);
+    }
+}
+
+#[cfg(test)]
+mod test_shapes {
+    use super::*;
+    use std::sync::Arc;
+
+    fn material() -> MaterialPtr {
+        Arc::new(Lambertian::new(Color::one()))
+    }
+
+    fn ray(origin: Point, direction: Vec3) -> Ray {
+        Ray {
+            origin,
+            direction,
+            time: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_plane() {
+        let plane = Plane {
+            p1: Point::zero(),
+            normal: Vec3::unit_y(),
+            material: material(),
+        };
+        let from_above = plane
+            .hit(&ray(Point::new(3, 2, -7), -Vec3::unit_y()), 0.001, INFINITY)
+            .unwrap();
+        assert_eq!(from_above.t, 2.0);
+        assert!(from_above.front_face);
+        assert_eq!(from_above.normal, Vec3::unit_y());
+        let from_below = plane
+            .hit(&ray(Point::new(0, -1, 0), Vec3::unit_y()), 0.001, INFINITY)
+            .unwrap();
+        assert!(!from_below.front_face);
+        assert_eq!(from_below.normal, -Vec3::unit_y());
+        assert!(plane
+            .hit(&ray(Point::new(0, 1, 0), Vec3::unit_x()), 0.001, INFINITY)
+            .is_none());
+    }
+
+    #[test]
+    fn test_quad() {
+        let quad = Quad::new(
+            Point::zero(),
+            Vec3::new(2, 0, 0),
+            Vec3::new(0, 0, 1),
+            material(),
+        );
+        // u x v points down
+        assert_eq!(quad.normal(), -Vec3::unit_y());
+        let inside = quad
+            .hit(
+                &ray(Point::new(1.5, 1, 0.5), -Vec3::unit_y()),
+                0.001,
+                INFINITY,
+            )
+            .unwrap();
+        assert_eq!(inside.position, Point::new(1.5, 0, 0.5));
+        assert!(!inside.front_face);
+        let corner = quad.hit(&ray(Point::new(2, 1, 1), -Vec3::unit_y()), 0.001, INFINITY);
+        assert!(corner.is_some());
+        let outside = quad.hit(
+            &ray(Point::new(2.5, 1, 0.5), -Vec3::unit_y()),
+            0.001,
+            INFINITY,
+        );
+        assert!(outside.is_none());
+    }
+
+    #[test]
+    fn test_cube_from_outside() {
+        let cube = Cube::new(Point::zero(), 2.0, material());
+        let record = cube
+            .hit(
+                &ray(Point::new(5, 0.5, 0.5), -Vec3::unit_x()),
+                0.001,
+                INFINITY,
+            )
+            .unwrap();
+        assert_eq!(record.t, 4.0);
+        assert!(record.front_face);
+        assert_eq!(record.normal, Vec3::unit_x());
+        let record = cube
+            .hit(
+                &ray(Point::new(0.5, -3, 0.5), Vec3::unit_y()),
+                0.001,
+                INFINITY,
+            )
+            .unwrap();
+        assert_eq!(record.normal, -Vec3::unit_y());
+        assert!(cube
+            .hit(
+                &ray(Point::new(5, 1.5, 0), -Vec3::unit_x()),
+                0.001,
+                INFINITY
+            )
+            .is_none());
+        // pointing away from the box
+        assert!(cube
+            .hit(&ray(Point::new(5, 0, 0), Vec3::unit_x()), 0.001, INFINITY)
+            .is_none());
+    }
+
+    #[test]
+    fn test_cube_from_inside() {
+        let cube = Cube {
+            min: Point::new(-1, -2, -3),
+            max: Point::new(1, 2, 3),
+            material: material(),
+        };
+        let record = cube
+            .hit(&ray(Point::zero(), Vec3::unit_z()), 0.001, INFINITY)
+            .unwrap();
+        assert_eq!(record.t, 3.0);
+        assert!(!record.front_face);
+        // facing back into the box, towards the ray
+        assert_eq!(record.normal, -Vec3::unit_z());
+        assert_eq!(
+            cube.bounding_box(),
+            Some(Aabb::new(Point::new(-1, -2, -3), Point::new(1, 2, 3)))
+        );
+    }
+
+    #[test]
+    fn test_light_sampling() {
+        let sphere = Sphere {
+            center: Point::new(0, 0, -4),
+            radius: 2.0,
+            material: material(),
+        };
+        let quad = Quad::new(
+            Point::new(-1, 3, -1),
+            Vec3::new(2, 0, 0),
+            Vec3::new(0, 0, 2),
+            material(),
+        );
+        // a cone of half angle 30 degrees
+        let solid_angle = 2.0 * PI * (1.0 - Num::sqrt(0.75));
+        let mut sampler = IndependentSampler::new(0);
+        for index in 0..100 {
+            sampler.start_sample(0, 0, index);
+            let origin = Point::zero();
+            let direction = sphere.random_direction(origin, &mut sampler);
+            assert!(sphere
+                .hit(&ray(origin, direction), 0.001, INFINITY)
+                .is_some());
+            let pdf = sphere.pdf_value(origin, direction);
+            assert!((pdf - 1.0 / solid_angle).abs() < 1e-9);
+
+            let inside = sphere.random_direction(sphere.center, &mut sampler);
+            assert!(sphere.pdf_value(sphere.center, inside) > 0.0);
+
+            let direction = quad.random_direction(origin, &mut sampler);
+            assert!(quad.hit(&ray(origin, direction), 0.001, INFINITY).is_some());
+            assert!(quad.pdf_value(origin, direction) > 0.0);
+        }
+        assert_eq!(sphere.pdf_value(Point::zero(), Vec3::unit_z()), 0.0);
+    }
+
+    #[test]
+    fn test_surface_coordinates() {
+        let close = |(u, v): (Num, Num), (eu, ev): (Num, Num)| {
+            (u - eu).abs() < 1e-12 && (v - ev).abs() < 1e-12
+        };
+        assert!(close(sphere_uv(Point::new(1, 0, 0)), (0.5, 0.5)));
+        assert!(close(sphere_uv(Point::new(0, 1, 0)), (0.5, 1.0)));
+        assert!(close(sphere_uv(Point::new(0, -1, 0)), (0.5, 0.0)));
+        assert!(close(sphere_uv(Point::new(-1, 0, 0)), (0.0, 0.5)));
+        assert!(close(sphere_uv(Point::new(0, 0, 1)), (0.25, 0.5)));
+
+        let sphere = Sphere {
+            center: Point::new(0, 0, -3),
+            radius: 1.0,
+            material: material(),
+        };
+        let record = sphere
+            .hit(&ray(Point::zero(), -Vec3::unit_z()), 0.001, INFINITY)
+            .unwrap();
+        assert!(close((record.u, record.v), (0.25, 0.5)));
+
+        let cube = Cube::new(Point::zero(), 2.0, material());
+        let record = cube
+            .hit(
+                &ray(Point::new(5, 0.5, -0.5), -Vec3::unit_x()),
+                0.001,
+                INFINITY,
+            )
+            .unwrap();
+        assert!(close((record.u, record.v), (0.75, 0.25)));
+    }
+
+    #[test]
+    fn test_moving_sphere() {
+        let sphere = MovingSphere {
+            start_center: Point::new(0, 0, -5),
+            end_center: Point::new(4, 0, -5),
+            start_time: 1.0,
+            end_time: 2.0,
+            radius: 1.0,
+            material: material(),
+        };
+        let at = |x: Num, time: Num| Ray {
+            origin: Point::new(x, 0, 0),
+            direction: -Vec3::unit_z(),
+            time,
+        };
+        assert_eq!(sphere.center(1.5), Point::new(2, 0, -5));
+        // it waits at either end
+        assert_eq!(sphere.center(0.0), sphere.start_center);
+        assert_eq!(sphere.center(9.0), sphere.end_center);
+        let record = sphere.hit(&at(2.0, 1.5), 0.001, INFINITY).unwrap();
+        assert_eq!(record.t, 4.0);
+        assert_eq!(record.normal, Vec3::unit_z());
+        assert!(sphere.hit(&at(2.0, 1.0), 0.001, INFINITY).is_none());
+        assert!(sphere.hit(&at(0.0, 1.0), 0.001, INFINITY).is_some());
+        assert!(sphere.hit(&at(0.0, 2.0), 0.001, INFINITY).is_none());
+        let bounds = sphere.bounding_box().unwrap();
+        assert_eq!(bounds.min, Point::new(-1, -1, -6));
+        assert_eq!(bounds.max, Point::new(5, 1, -4));
+    }
+
+    #[test]
+    fn test_moving_instance() {
+        // a long thin box swung a quarter turn about the y axis
+        let bar: HittablePtr = Arc::new(Cube {
+            min: Point::new(0, -0.1, -0.1),
+            max: Point::new(4, 0.1, 0.1),
+            material: material(),
+        });
+        let motion = MotionTransform::new(
+            Transform::identity(),
+            Transform::rotate(Vec3::unit_y(), 90.0),
+            0.0,
+            1.0,
+        )
+        .unwrap();
+        let instance = Instance::moving(bar, motion);
+        let down = |x: Num, z: Num, time: Num| Ray {
+            origin: Point::new(x, 5, z),
+            direction: -Vec3::unit_y(),
+            time,
+        };
+        assert!(instance
+            .hit(&down(3.0, 0.0, 0.0), 0.001, INFINITY)
+            .is_some());
+        assert!(instance
+            .hit(&down(3.0, 0.0, 1.0), 0.001, INFINITY)
+            .is_none());
+        assert!(instance
+            .hit(&down(0.0, -3.0, 1.0), 0.001, INFINITY)
+            .is_some());
+        let halfway = Num::sqrt(4.5);
+        let record = instance
+            .hit(&down(halfway, -halfway, 0.5), 0.001, INFINITY)
+            .unwrap();
+        assert!((record.t - 4.9).abs() < 1e-9);
+        assert!((record.normal - Vec3::unit_y()).magnitude() < 1e-9);
+        // the bounds cover the whole sweep, including the arc between the ends
+        let bounds = instance.bounding_box().unwrap();
+        for step in 0..=100 {
+            let time = step as Num / 100.0;
+            let tip = instance.transform(time).point(Point::new(4, 0, 0));
+            for axis in 0..3 {
+                assert!(bounds.min[axis] <= tip[axis] && tip[axis] <= bounds.max[axis]);
+            }
+        }
+        assert!(bounds.max.x < 4.2 && bounds.min.z > -4.2);
+    }
+
+    #[test]
+    fn test_instance() {
+        let unit: HittablePtr = Arc::new(Sphere {
+            center: Point::zero(),
+            radius: 1.0,
+            material: material(),
+        });
+        let transform =
+            Transform::scale(Vec3::new(2, 2, 2)).then(&Transform::translate(Vec3::new(1, 0, -5)));
+        let instance = Instance::new(unit.clone(), transform);
+        let direct = Sphere {
+            center: Point::new(1, 0, -5),
+            radius: 2.0,
+            material: material(),
+        };
+        let close = |a: Vec3, b: Vec3| (a - b).magnitude() < 1e-9;
+        for &direction in &[
+            -Vec3::unit_z(),
+            Vec3::new(0.3, 0.1, -1),
+            Vec3::new(0.2, -0.3, -2),
+        ] {
+            let ray = ray(Point::new(0, 0, 1), direction);
+            let expected = direct.hit(&ray, 0.001, INFINITY).unwrap();
+            let actual = instance.hit(&ray, 0.001, INFINITY).unwrap();
+            assert!((expected.t - actual.t).abs() < 1e-9);
+            assert!(close(expected.position, actual.position));
+            assert!(close(expected.normal, actual.normal));
+            assert_eq!(expected.front_face, actual.front_face);
+        }
+        let bounds = instance.bounding_box().unwrap();
+        assert!(close(bounds.min, Point::new(-1, -2, -7)));
+        assert!(close(bounds.max, Point::new(3, 2, -3)));
+
+        // a unit cube turned 45 degrees about y has its +z face on x + z = sqrt(1/2)
+        let cube: HittablePtr = Arc::new(Cube::new(Point::zero(), 1.0, material()));
+        let turned = Instance::new(cube, Transform::rotate(Vec3::unit_y(), 45.0));
+        let record = turned
+            .hit(
+                &ray(Point::new(5, 0, 0.2), -Vec3::unit_x()),
+                0.001,
+                INFINITY,
+            )
+            .unwrap();
+        assert!((record.t - (5.2 - Num::sqrt(0.5))).abs() < 1e-9);
+        assert!(close(record.normal, Vec3::new(1, 0, 1).unit_vector()));
+        assert!(turned
+            .hit(
+                &ray(Point::new(5, 0, 0.75), -Vec3::unit_x()),
+                0.001,
+                INFINITY
+            )
+            .is_none());
+
+        // lights can be sampled through an instance
+        let mut sampler = IndependentSampler::new(0);
+        for index in 0..50 {
+            sampler.start_sample(0, 0, index);
+            let origin = Point::zero();
+            let direction = instance.random_direction(origin, &mut sampler);
+            assert!(instance
+                .hit(&ray(origin, direction), 0.001, INFINITY)
+                .is_some());
+            let pdf = instance.pdf_value(origin, direction);
+            assert!((pdf - direct.pdf_value(origin, direction)).abs() < 1e-9);
+        }
+
+        // even when squashed out of shape
+        let quad: HittablePtr = Arc::new(Quad::new(
+            Point::zero(),
+            Vec3::unit_x(),
+            Vec3::unit_z(),
+            material(),
+        ));
+        let transform = Transform::scale(Vec3::new(4, 1, 0.5))
+            .then(&Transform::rotate(Vec3::unit_z(), 30.0))
+            .then(&Transform::translate(Vec3::new(-1, 2, 0)));
+        let squashed = Instance::new(quad, transform);
+        let direct = Quad::new(
+            transform.point(Point::zero()),
+            transform.vector(Vec3::unit_x()),
+            transform.vector(Vec3::unit_z()),
+            material(),
+        );
+        for index in 0..50 {
+            sampler.start_sample(0, 0, index);
+            let origin = Point::new(0, 0, 1);
+            let direction = squashed.random_direction(origin, &mut sampler);
+            let pdf = squashed.pdf_value(origin, direction);
+            let expected = direct.pdf_value(origin, direction);
+            assert!(
+                (pdf - expected).abs() < 1e-9 * expected,
+                "{} {}",
+                pdf,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_sphere_from_inside() {
+        let sphere = Sphere {
+            center: Point::zero(),
+            radius: 1.0,
+            material: material(),
+        };
+        let record = sphere
+            .hit(&ray(Point::zero(), Vec3::unit_x()), 0.001, INFINITY)
+            .unwrap();
+        assert_eq!(record.t, 1.0);
+        assert!(!record.front_face);
+    }
 }
@@ -0,0 +1,361 @@
+//! Dense voxel grids of density, and temperature for fire, as exported by
+//! simulations.
+//!
+//! Grids are read from a raw little-endian format:
+//!
+//! ```text
+//! magic       4 bytes   "YVOX"
+//! version     u32       1
+//! resolution  3 x u32   voxels along x, y and z
+//! channels    u32       1 for density, 2 for density and temperature
+//! bounds      6 x f32   the minimum x, y and z corner, then the maximum
+//! values      f32 ...   every voxel of each channel in turn, x varying
+//!                       fastest, then y, then z
+//! ```
+//!
+//! Densities are per unit of distance and temperatures in kelvin. Values
+//! sit at the centers of the voxels filling the bounds and are blended
+//! trilinearly in between; outside the bounds everything is zero.
+
+use crate::math::*;
+use crate::types::*;
+use std::convert::TryInto;
+use std::fmt;
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"YVOX";
+const VERSION: u32 = 1;
+
+pub struct VoxelGrid {
+    resolution: [usize; 3],
+    bounds: Aabb,
+    density: Vec<f32>,
+    temperature: Option<Vec<f32>>,
+    max_density: Num,
+}
+
+impl VoxelGrid {
+    /// A grid of `resolution` voxels filling `bounds`, with `density` and
+    /// optionally `temperature` laid out as in the file format.
+    pub fn new(
+        resolution: [usize; 3],
+        bounds: Aabb,
+        density: Vec<f32>,
+        temperature: Option<Vec<f32>>,
+    ) -> Result<VoxelGrid, GridError> {
+        let count = resolution.iter().product::<usize>();
+        if count == 0 {
+            return Err(GridError::Format("the grid has no voxels".to_string()));
+        }
+        if (0..3).any(|axis| bounds.max[axis] <= bounds.min[axis]) {
+            return Err(GridError::Format("the bounds are empty".to_string()));
+        }
+        let lengths_match = density.len() == count
+            && temperature
+                .as_ref()
+                .is_none_or(|temperature| temperature.len() == count);
+        if !lengths_match {
+            return Err(GridError::Format(format!(
+                "expected {} values per channel",
+                count
+            )));
+        }
+        if density
+            .iter()
+            .any(|&value| !(value >= 0.0 && value.is_finite()))
+        {
+            return Err(GridError::Format(
+                "densities must be finite and not negative".to_string(),
+            ));
+        }
+        if temperature.iter().flatten().any(|value| !value.is_finite()) {
+            return Err(GridError::Format("temperatures must be finite".to_string()));
+        }
+        let max_density = density.iter().fold(0.0, |max: f32, &value| max.max(value)) as Num;
+        Ok(VoxelGrid {
+            resolution,
+            bounds,
+            density,
+            temperature,
+            max_density,
+        })
+    }
+
+    pub fn resolution(&self) -> [usize; 3] {
+        self.resolution
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+
+    pub fn max_density(&self) -> Num {
+        self.max_density
+    }
+
+    pub fn has_temperature(&self) -> bool {
+        self.temperature.is_some()
+    }
+
+    pub fn density(&self, position: Point) -> Num {
+        self.lookup(&self.density, position)
+    }
+
+    /// Temperature in kelvin, zero for grids without any.
+    pub fn temperature(&self, position: Point) -> Num {
+        match &self.temperature {
+            Some(temperature) => self.lookup(temperature, position),
+            None => 0.0,
+        }
+    }
+
+    /// Trilinear interpolation of `values` between the voxel centers, held
+    /// constant out to the edges of the bounds.
+    fn lookup(&self, values: &[f32], position: Point) -> Num {
+        let (min, max) = (self.bounds.min, self.bounds.max);
+        if (0..3).any(|axis| position[axis] < min[axis] || position[axis] > max[axis]) {
+            return 0.0;
+        }
+        let mut base = [0; 3];
+        let mut fraction = [0.0; 3];
+        for axis in 0..3 {
+            let size = self.resolution[axis];
+            let scaled = (position[axis] - min[axis]) / (max[axis] - min[axis]) * size as Num;
+            let continuous = clamp_num(scaled - 0.5, 0.0, (size - 1) as Num);
+            base[axis] = usize::min(continuous as usize, size.saturating_sub(2));
+            fraction[axis] = continuous - base[axis] as Num;
+        }
+        let [nx, ny, _] = self.resolution;
+        let at = |x: usize, y: usize, z: usize| {
+            let clamp = |index: usize, axis: usize| usize::min(index, self.resolution[axis] - 1);
+            values[clamp(x, 0) + nx * (clamp(y, 1) + ny * clamp(z, 2))] as Num
+        };
+        let [x, y, z] = base;
+        let [fx, fy, fz] = fraction;
+        let lerp = |a: Num, b: Num, t: Num| a + (b - a) * t;
+        let plane = |z: usize| {
+            lerp(
+                lerp(at(x, y, z), at(x + 1, y, z), fx),
+                lerp(at(x, y + 1, z), at(x + 1, y + 1, z), fx),
+                fy,
+            )
+        };
+        lerp(plane(z), plane(z + 1), fz)
+    }
+
+    /// The grid in the file format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        let [nx, ny, nz] = self.resolution;
+        let channels = if self.temperature.is_some() { 2 } else { 1 };
+        for &value in &[VERSION, nx as u32, ny as u32, nz as u32, channels] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        let (min, max) = (self.bounds.min, self.bounds.max);
+        for &value in &[min.x, min.y, min.z, max.x, max.y, max.z] {
+            bytes.extend_from_slice(&(value as f32).to_le_bytes());
+        }
+        let values = self.density.iter().chain(self.temperature.iter().flatten());
+        for value in values {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+#[derive(Debug)]
+pub enum GridError {
+    Io(std::io::Error),
+    /// not a grid in the documented format
+    Format(String),
+}
+
+impl fmt::Display for GridError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GridError::Io(err) => write!(f, "couldn't read voxel grid: {}", err),
+            GridError::Format(message) => write!(f, "bad voxel grid: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for GridError {}
+
+impl From<std::io::Error> for GridError {
+    fn from(err: std::io::Error) -> GridError {
+        GridError::Io(err)
+    }
+}
+
+/// Loads a voxel grid file, see the module documentation for the format.
+pub fn load_grid<P: AsRef<Path>>(path: P) -> Result<VoxelGrid, GridError> {
+    parse_grid(&std::fs::read(path)?)
+}
+
+/// Parses a voxel grid in the documented format.
+pub fn parse_grid(bytes: &[u8]) -> Result<VoxelGrid, GridError> {
+    let truncated = || GridError::Format("the file is truncated".to_string());
+    let mut words = bytes
+        .get(4..)
+        .ok_or_else(truncated)?
+        .chunks_exact(4)
+        .map(|word| word.try_into().unwrap());
+    if &bytes[..4] != MAGIC {
+        return Err(GridError::Format("missing the YVOX magic".to_string()));
+    }
+    let mut next_u32 = || words.next().map(u32::from_le_bytes).ok_or_else(truncated);
+    let version = next_u32()?;
+    if version != VERSION {
+        return Err(GridError::Format(format!(
+            "version {} isn't supported",
+            version
+        )));
+    }
+    let resolution = [
+        next_u32()? as usize,
+        next_u32()? as usize,
+        next_u32()? as usize,
+    ];
+    let channels = next_u32()?;
+    if channels != 1 && channels != 2 {
+        return Err(GridError::Format(format!(
+            "{} channels, expected 1 or 2",
+            channels
+        )));
+    }
+    let mut next_f32 = || words.next().map(f32::from_le_bytes).ok_or_else(truncated);
+    let mut corner = || -> Result<Point, GridError> {
+        Ok(Point::new(
+            next_f32()? as Num,
+            next_f32()? as Num,
+            next_f32()? as Num,
+        ))
+    };
+    let bounds = Aabb::new(corner()?, corner()?);
+    let too_large = || GridError::Format("the resolution is too large".to_string());
+    let count = resolution
+        .iter()
+        .try_fold(1usize, |count, &size| count.checked_mul(size))
+        .ok_or_else(too_large)?;
+    let total = count.checked_mul(channels as usize).ok_or_else(too_large)?;
+    let values: Vec<f32> = words.map(f32::from_le_bytes).collect();
+    if values.len() != total {
+        return Err(GridError::Format(format!(
+            "expected {} values, found {}",
+            total,
+            values.len()
+        )));
+    }
+    let (density, temperature) = values.split_at(count);
+    let temperature = if channels == 2 {
+        Some(temperature.to_vec())
+    } else {
+        None
+    };
+    VoxelGrid::new(resolution, bounds, density.to_vec(), temperature)
+}
+
+#[cfg(test)]
+mod test_voxels {
+    use super::*;
+
+    fn unit_bounds() -> Aabb {
+        Aabb::new(Point::zero(), Point::new(1, 1, 1))
+    }
+
+    #[test]
+    fn test_lookup() {
+        // density rising along x, temperature along z
+        let mut density = Vec::new();
+        let mut temperature = Vec::new();
+        for z in 0..4 {
+            for _ in 0..3 {
+                for x in 0..2 {
+                    density.push(x as f32);
+                    temperature.push(1000.0 * z as f32);
+                }
+            }
+        }
+        let grid = VoxelGrid::new([2, 3, 4], unit_bounds(), density, Some(temperature))
+            .unwrap_or_else(|err| panic!("{}", err));
+        assert_eq!(grid.max_density(), 1.0);
+        // the centers hold the values, with lines in between
+        assert_eq!(grid.density(Point::new(0.25, 0.5, 0.5)), 0.0);
+        assert_eq!(grid.density(Point::new(0.75, 0.5, 0.5)), 1.0);
+        assert!((grid.density(Point::new(0.5, 0.1, 0.9)) - 0.5).abs() < 1e-12);
+        assert!((grid.density(Point::new(0.4, 0.3, 0.2)) - 0.3).abs() < 1e-12);
+        // and the edges the nearest ones
+        assert_eq!(grid.density(Point::new(1.0, 0.0, 1.0)), 1.0);
+        assert_eq!(grid.temperature(Point::new(0.5, 0.5, 0.0)), 0.0);
+        assert!((grid.temperature(Point::new(0.5, 0.5, 0.5)) - 1500.0).abs() < 1e-9);
+        assert_eq!(grid.temperature(Point::new(0.5, 0.5, 1.0)), 3000.0);
+        // outside there's nothing
+        assert_eq!(grid.density(Point::new(1.1, 0.5, 0.5)), 0.0);
+        assert_eq!(grid.temperature(Point::new(0.5, -0.1, 0.5)), 0.0);
+
+        // a single voxel fills its bounds
+        let single = VoxelGrid::new([1, 1, 1], unit_bounds(), vec![2.0], None).unwrap();
+        assert_eq!(single.density(Point::new(0.9, 0.1, 0.5)), 2.0);
+        assert_eq!(single.temperature(Point::new(0.9, 0.1, 0.5)), 0.0);
+    }
+
+    #[test]
+    fn test_format() {
+        let density: Vec<f32> = (0..24).map(|i| i as f32 / 10.0).collect();
+        let temperature: Vec<f32> = (0..24).map(|i| 300.0 + i as f32).collect();
+        let bounds = Aabb::new(Point::new(-1, -2, -3), Point::new(1, 2, 3));
+        for temperature in [None, Some(temperature)] {
+            let grid = VoxelGrid::new([2, 3, 4], bounds, density.clone(), temperature).unwrap();
+            let bytes = grid.to_bytes();
+            assert_eq!(&bytes[..4], b"YVOX");
+            assert_eq!(
+                bytes.len(),
+                48 + 4 * 24 * if grid.has_temperature() { 2 } else { 1 }
+            );
+            let parsed = parse_grid(&bytes).unwrap();
+            assert_eq!(parsed.resolution(), [2, 3, 4]);
+            assert_eq!(parsed.bounds(), bounds);
+            assert_eq!(parsed.has_temperature(), grid.has_temperature());
+            let point = Point::new(0.3, -0.7, 1.9);
+            assert_eq!(parsed.density(point), grid.density(point));
+            assert_eq!(parsed.temperature(point), grid.temperature(point));
+
+            // cut short, or not a grid at all
+            let message = |bytes: &[u8]| match parse_grid(bytes) {
+                Err(GridError::Format(message)) => message,
+                _ => panic!("parsed a broken grid"),
+            };
+            assert!(message(&bytes[..bytes.len() - 4]).contains("expected"));
+            assert!(message(&bytes[..30]).contains("truncated"));
+            assert!(message(&bytes[..2]).contains("truncated"));
+            let mut wrong = bytes.clone();
+            wrong[0] = b'X';
+            assert!(message(&wrong).contains("magic"));
+            wrong = bytes.clone();
+            wrong[20] = 3;
+            assert!(message(&wrong).contains("channels"));
+            // more voxels than could ever be addressed
+            wrong = bytes.clone();
+            wrong[8..20].copy_from_slice(&[0xff; 12]);
+            assert!(message(&wrong).contains("too large"));
+            if grid.has_temperature() {
+                // each channel fits, both together don't
+                wrong[16..20].copy_from_slice(&1u32.to_le_bytes());
+                assert!(message(&wrong).contains("too large"));
+            }
+        }
+
+        let error = VoxelGrid::new([2, 1, 1], unit_bounds(), vec![1.0, -1.0], None);
+        assert!(matches!(error, Err(GridError::Format(_))));
+        let error = VoxelGrid::new([0, 1, 1], unit_bounds(), vec![], None);
+        assert!(matches!(error, Err(GridError::Format(_))));
+        for &bad in &[f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            let error = VoxelGrid::new([1, 1, 1], unit_bounds(), vec![1.0], Some(vec![bad]));
+            assert!(matches!(error, Err(GridError::Format(_))), "{}", bad);
+        }
+        assert!(matches!(
+            load_grid("no/such/grid.yvox"),
+            Err(GridError::Io(_))
+        ));
+    }
+}